@@ -0,0 +1,206 @@
+//! Query count and latency of the admin list endpoints at several page sizes.
+//!
+//! Creates a throwaway database next to `DATABASE_URL`, seeds it, calls the
+//! router in-process and counts the statements sqlx executes per request.
+//! Fails if the count depends on the page size, i.e. if an N+1 query creeps
+//! back into a list handler.
+//!
+//!     DATABASE_URL=postgres://postgres@localhost/app_db cargo bench --bench list_queries
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use rust_backend_template::metrics::Metrics;
+use rust_backend_template::migrations::run_migrations;
+use rust_backend_template::models::{AppState, Claims};
+use rust_backend_template::routes::build_router;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Connection, PgPool};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tower::ServiceExt;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+const JWT_SECRET: &str = "bench-secret";
+const PAGE_SIZES: [u32; 3] = [10, 50, 100];
+const ITERATIONS: u32 = 20;
+const USERS: i32 = 500;
+const ROLES: i32 = 150;
+
+/// Statements executed since the last reset, as reported by sqlx's query log
+static QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+struct QueryCounter;
+
+impl<S: tracing::Subscriber> Layer<S> for QueryCounter {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() == "sqlx::query" {
+            QUERIES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+fn admin_token() -> String {
+    let claims = Claims {
+        sub: "bench-admin".to_string(),
+        exp: 4_102_444_800,
+        email_verified: Some(true),
+        mfa_enabled: Some(true),
+        email: Some("bench@example.com".to_string()),
+        name: Some("Bench Admin".to_string()),
+        admin: Some(true),
+        organization: None,
+    };
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+/// Every role gets six permissions; every user gets three roles
+async fn seed(pool: &PgPool) {
+    sqlx::query(
+        "INSERT INTO roles (name, description)
+         SELECT 'Bench role ' || g, 'Seeded' FROM generate_series(1, $1) g",
+    )
+    .bind(ROLES)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO permissions (role_id, page, can_view)
+         SELECT r.id, p.page, TRUE FROM roles r
+         CROSS JOIN unnest(ARRAY['dashboard', 'users', 'roles', 'profile', 'preferences', 'support']) AS p(page)
+         ON CONFLICT DO NOTHING",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO users (sub, user_email, user_fullname)
+         SELECT 'bench-' || g, 'user' || g || '@example.com', 'Bench User ' || g
+         FROM generate_series(1, $1) g",
+    )
+    .bind(USERS)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO user_roles (user_id, role_id)
+         SELECT u.id, r.id FROM users u
+         CROSS JOIN LATERAL (SELECT id FROM roles ORDER BY random() LIMIT 3) r",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Queries per request and median latency for one endpoint and page size
+async fn measure(app: &axum::Router, token: &str, uri: &str) -> (usize, Duration) {
+    let mut counts = Vec::new();
+    let mut timings = Vec::new();
+
+    for _ in 0..ITERATIONS {
+        let request = Request::get(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+
+        QUERIES.store(0, Ordering::SeqCst);
+        let started = Instant::now();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        timings.push(started.elapsed());
+        counts.push(QUERIES.load(Ordering::SeqCst));
+
+        assert_eq!(status, StatusCode::OK, "{} failed", uri);
+    }
+
+    counts.dedup();
+    assert_eq!(counts.len(), 1, "{} ran a varying number of queries", uri);
+    timings.sort();
+    (counts[0], timings[timings.len() / 2])
+}
+
+#[tokio::main]
+async fn main() {
+    let Ok(database_url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set; skipping the list query benchmark");
+        return;
+    };
+
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(QueryCounter))
+        .unwrap();
+
+    let server = PgConnectOptions::from_str(&database_url).expect("Invalid DATABASE_URL");
+    let database = format!("bench_list_queries_{}", std::process::id());
+    let mut admin = server.connect().await.unwrap();
+    sqlx::query(&format!("CREATE DATABASE {}", database))
+        .execute(&mut admin)
+        .await
+        .unwrap();
+
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(server.clone().database(&database))
+        .await
+        .unwrap();
+    run_migrations(&pool).await.unwrap();
+    seed(&pool).await;
+
+    let app = build_router(Arc::new(AppState {
+        jwt_secret: JWT_SECRET.into(),
+        start_time: SystemTime::now(),
+        db_pool: pool.clone(),
+        metrics: Metrics::default(),
+        shutdown: Default::default(),
+        rate_limiter: Default::default(),
+        trusted_proxies: Default::default(),
+    }));
+    let token = admin_token();
+
+    println!(
+        "{:<24} {:>6} {:>8} {:>12}",
+        "endpoint", "limit", "queries", "median"
+    );
+    let mut failures = Vec::new();
+    for endpoint in ["/api/v1/admin/users", "/api/v1/admin/roles"] {
+        let mut counts = Vec::new();
+        for limit in PAGE_SIZES {
+            let uri = format!("{}?limit={}", endpoint, limit);
+            let (queries, median) = measure(&app, &token, &uri).await;
+            println!(
+                "{:<24} {:>6} {:>8} {:>12?}",
+                endpoint, limit, queries, median
+            );
+            counts.push(queries);
+        }
+        if counts.iter().any(|&count| count != counts[0]) {
+            failures.push(endpoint);
+        }
+    }
+
+    pool.close().await;
+    sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", database))
+        .execute(&mut admin)
+        .await
+        .unwrap();
+    admin.close().await.unwrap();
+
+    assert!(
+        failures.is_empty(),
+        "query count grows with the page size: {:?}",
+        failures
+    );
+}
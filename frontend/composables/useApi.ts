@@ -1,5 +1,14 @@
 import { mockData, createMockResponse } from '~/utils/mockData'
 
+/**
+ * One page of a collection endpoint
+ */
+export interface Page<T> {
+  items: T[]
+  next_cursor: string | null
+  total: number | null
+}
+
 /**
  * Composable for making API calls with automatic mock support when AI_FRONTEND_DEV is enabled.
  * This allows AI agents and UI builders to work without a running backend.
@@ -84,6 +93,40 @@ export const useApi = () => {
     }
   }
   
+  /**
+   * Fetch every item of a paginated collection by following `next_cursor`.
+   * Mock data for collections is a plain array of items.
+   */
+  const getAll = async <T>(endpoint: string, mockKey?: keyof typeof mockData): Promise<T[]> => {
+    if (isDevMode && mockKey && mockData[mockKey]) {
+      console.log(`[AI_FRONTEND_DEV] Returning mock data for: ${endpoint}`)
+      return createMockResponse(mockData[mockKey] as T[])
+    }
+
+    try {
+      const items: T[] = []
+      const separator = endpoint.includes('?') ? '&' : '?'
+      let cursor: string | null = null
+      do {
+        const url = cursor
+          ? `${endpoint}${separator}cursor=${encodeURIComponent(cursor)}`
+          : endpoint
+        const page: Page<T> = await get<Page<T>>(url)
+        items.push(...page.items)
+        cursor = page.next_cursor
+      } while (cursor)
+      return items
+    } catch (error) {
+      // Fallback to mock data if available and real call fails
+      if (mockKey && mockData[mockKey]) {
+        console.warn(`[Fallback] Using mock data for: ${endpoint}`)
+        return createMockResponse(mockData[mockKey] as T[], 100)
+      }
+
+      throw error
+    }
+  }
+
   /**
    * Make a POST request to the API or return mock data
    */
@@ -121,6 +164,50 @@ export const useApi = () => {
     }
   }
   
+  /**
+   * Send a request with a body-less or JSON body using the given HTTP verb.
+   * Resolves to undefined for 204 No Content responses.
+   */
+  const send = async <T>(
+    method: 'PUT' | 'PATCH' | 'DELETE',
+    endpoint: string,
+    body?: any
+  ): Promise<T> => {
+    if (isDevMode) {
+      console.log(`[AI_FRONTEND_DEV] Skipping ${method} ${endpoint}`)
+      return createMockResponse(undefined as T)
+    }
+
+    const response = await fetch(`${config.public.apiUrl}${endpoint}`, {
+      method,
+      headers: getHeaders(),
+      body: body ? JSON.stringify(body) : undefined,
+    })
+
+    if (!response.ok) {
+      throw new Error(`HTTP error! status: ${response.status}`)
+    }
+    if (response.status === 204) {
+      return undefined as T
+    }
+    return await response.json()
+  }
+
+  /**
+   * Replace a resource (idempotent)
+   */
+  const put = <T>(endpoint: string, body?: any) => send<T>('PUT', endpoint, body)
+
+  /**
+   * Partially update a resource
+   */
+  const patch = <T>(endpoint: string, body: any) => send<T>('PATCH', endpoint, body)
+
+  /**
+   * Delete a resource
+   */
+  const del = <T = void>(endpoint: string) => send<T>('DELETE', endpoint)
+
   /**
    * Make an authenticated request with JWT token
    * @deprecated Use get() or post() instead - they now automatically include the auth token from cookie
@@ -171,7 +258,11 @@ export const useApi = () => {
   
   return {
     get,
+    getAll,
     post,
+    put,
+    patch,
+    del,
     authenticatedRequest,
     getAuthToken,
     isDevMode
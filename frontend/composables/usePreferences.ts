@@ -7,6 +7,13 @@ interface UserPreferences {
   language: string
 }
 
+// Response of GET/PATCH /v1/preferences
+interface PreferencesResponse {
+  preferences: UserPreferences
+  defaults: UserPreferences
+  overrides: Partial<UserPreferences>
+}
+
 const DARK_MODE_MEDIA_QUERY = '(prefers-color-scheme: dark)'
 
 // Shared state across all component instances
@@ -17,8 +24,54 @@ const savedLanguage = ref<string>((process.client && localStorage.getItem('langu
 // MediaQueryList instance for system theme detection
 let mediaQueryList: MediaQueryList | null = null
 
+// Server preferences are fetched once per page load
+let serverSync: Promise<void> | null = null
+
 export const usePreferences = () => {
   const { locale, setLocale } = useI18n()
+  const { get, patch, getAuthToken } = useApi()
+
+  // Apply preferences without writing them back to the server
+  const applyPreferences = (preferences: UserPreferences) => {
+    theme.value = preferences.theme
+    localStorage.setItem('theme', preferences.theme)
+    applyTheme(preferences.theme)
+
+    savedLanguage.value = preferences.language
+    localStorage.setItem('language', preferences.language)
+    setLocale(preferences.language)
+  }
+
+  // Replace the localStorage copy with the preferences stored on the server,
+  // so they follow the user across devices. localStorage stays the fallback
+  // when signed out or offline.
+  const syncFromServer = (): Promise<void> => {
+    if (!process.client || !getAuthToken()) {
+      return Promise.resolve()
+    }
+    serverSync ??= (async () => {
+      try {
+        // No mock key: a failed call must not replace local preferences with mock data
+        const response = await get<PreferencesResponse>('/v1/preferences')
+        applyPreferences(response.preferences)
+      } catch (error) {
+        console.warn('[usePreferences] Using local preferences:', error)
+      }
+    })()
+    return serverSync
+  }
+
+  // Store a change on the server; local state is already updated
+  const pushToServer = async (changes: Partial<UserPreferences>) => {
+    if (!getAuthToken()) {
+      return
+    }
+    try {
+      await patch<PreferencesResponse>('/v1/preferences', changes)
+    } catch (error) {
+      console.warn('[usePreferences] Could not save preferences on the server:', error)
+    }
+  }
   
   // Load preferences from localStorage
   const loadPreferences = () => {
@@ -38,6 +91,8 @@ export const usePreferences = () => {
       
       // Apply theme
       applyTheme(theme.value)
+
+      syncFromServer()
     }
   }
   
@@ -82,6 +137,7 @@ export const usePreferences = () => {
       theme.value = newTheme
       localStorage.setItem('theme', newTheme)
       applyTheme(newTheme)
+      pushToServer({ theme: newTheme })
     }
   }
   
@@ -91,13 +147,16 @@ export const usePreferences = () => {
       savedLanguage.value = newLanguage
       localStorage.setItem('language', newLanguage)
       setLocale(newLanguage)
+      pushToServer({ language: newLanguage })
     }
   }
   
   // Save all preferences
   const savePreferences = (preferences: UserPreferences) => {
-    saveTheme(preferences.theme)
-    saveLanguage(preferences.language)
+    if (process.client) {
+      applyPreferences(preferences)
+      pushToServer(preferences)
+    }
   }
   
   // Get current effective theme (resolves 'system' to actual theme)
@@ -117,6 +176,7 @@ export const usePreferences = () => {
     savedLanguage,
     effectiveTheme,
     loadPreferences,
+    syncFromServer,
     saveTheme,
     saveLanguage,
     savePreferences,
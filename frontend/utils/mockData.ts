@@ -1,4 +1,5 @@
-// Mock data for AI frontend development
+// Mock data for AI frontend development.
+// Shapes follow the backend OpenAPI spec in /openapi.json (served at /api/openapi.json).
 export const mockData = {
   version: {
     version: '0.1.0'
@@ -32,11 +33,14 @@ export const mockData = {
       user_email: 'user@example.com',
       user_fullname: 'John Doe',
       organization: 'Acme Corp',
+      organization_id: '990e8400-e29b-41d4-a716-446655440100',
       group_id: '660e8400-e29b-41d4-a716-446655440001',
       properties: {
         department: 'Engineering',
         role: 'Developer'
       },
+      deactivated_at: null,
+      anonymized_at: null,
       created_at: '2026-01-01T00:00:00Z',
       updated_at: '2026-01-16T00:00:00Z'
     }
@@ -50,6 +54,7 @@ export const mockData = {
         name: 'Admin',
         description: 'Full system administrator with all permissions',
         is_admin: true,
+        organization_id: null,
         created_at: '2026-01-01T00:00:00Z',
         updated_at: '2026-01-01T00:00:00Z'
       },
@@ -64,6 +69,7 @@ export const mockData = {
           can_edit_own: true,
           can_view_ours: true,
           can_edit_ours: true,
+          organization_id: null,
           created_at: '2026-01-01T00:00:00Z',
           updated_at: '2026-01-01T00:00:00Z'
         },
@@ -77,6 +83,7 @@ export const mockData = {
           can_edit_own: true,
           can_view_ours: true,
           can_edit_ours: true,
+          organization_id: null,
           created_at: '2026-01-01T00:00:00Z',
           updated_at: '2026-01-01T00:00:00Z'
         },
@@ -90,6 +97,7 @@ export const mockData = {
           can_edit_own: true,
           can_view_ours: true,
           can_edit_ours: true,
+          organization_id: null,
           created_at: '2026-01-01T00:00:00Z',
           updated_at: '2026-01-01T00:00:00Z'
         }
@@ -101,6 +109,7 @@ export const mockData = {
         name: 'View',
         description: 'View-only access to assigned pages',
         is_admin: false,
+        organization_id: null,
         created_at: '2026-01-01T00:00:00Z',
         updated_at: '2026-01-01T00:00:00Z'
       },
@@ -115,6 +124,7 @@ export const mockData = {
           can_edit_own: false,
           can_view_ours: false,
           can_edit_ours: false,
+          organization_id: null,
           created_at: '2026-01-01T00:00:00Z',
           updated_at: '2026-01-01T00:00:00Z'
         }
@@ -130,6 +140,7 @@ export const mockData = {
         user_email: 'john.doe@example.com',
         user_fullname: 'John Doe',
         organization: 'Acme Corp',
+        organization_id: '990e8400-e29b-41d4-a716-446655440100',
         group_id: '660e8400-e29b-41d4-a716-446655440001',
         properties: {},
         created_at: '2026-01-01T00:00:00Z',
@@ -141,6 +152,7 @@ export const mockData = {
           name: 'Admin',
           description: 'Full system administrator with all permissions',
           is_admin: true,
+          organization_id: null,
           created_at: '2026-01-01T00:00:00Z',
           updated_at: '2026-01-01T00:00:00Z'
         }
@@ -153,6 +165,7 @@ export const mockData = {
         user_email: 'jane.smith@example.com',
         user_fullname: 'Jane Smith',
         organization: 'Acme Corp',
+        organization_id: '990e8400-e29b-41d4-a716-446655440100',
         group_id: '660e8400-e29b-41d4-a716-446655440001',
         properties: {},
         created_at: '2026-01-02T00:00:00Z',
@@ -164,6 +177,7 @@ export const mockData = {
           name: 'View',
           description: 'View-only access to assigned pages',
           is_admin: false,
+          organization_id: null,
           created_at: '2026-01-01T00:00:00Z',
           updated_at: '2026-01-01T00:00:00Z'
         }
@@ -176,6 +190,7 @@ export const mockData = {
         user_email: 'bob.wilson@example.com',
         user_fullname: 'Bob Wilson',
         organization: 'Acme Corp',
+        organization_id: '990e8400-e29b-41d4-a716-446655440100',
         group_id: '660e8400-e29b-41d4-a716-446655440002',
         properties: {},
         created_at: '2026-01-03T00:00:00Z',
@@ -183,6 +198,36 @@ export const mockData = {
       },
       roles: []
     }
+  ],
+
+  // Support tickets of the current user (GET /v1/support/tickets items)
+  tickets: [
+    {
+      id: '990e8400-e29b-41d4-a716-446655440001',
+      organization_id: null,
+      requester_id: '550e8400-e29b-41d4-a716-446655440000',
+      assignee_id: null,
+      subject: 'Cannot export reports',
+      status: 'in_progress',
+      priority: 'high',
+      resolved_at: null,
+      closed_at: null,
+      created_at: '2026-01-20T09:30:00Z',
+      updated_at: '2026-01-20T11:00:00Z'
+    },
+    {
+      id: '990e8400-e29b-41d4-a716-446655440002',
+      organization_id: null,
+      requester_id: '550e8400-e29b-41d4-a716-446655440000',
+      assignee_id: null,
+      subject: 'How do I change my email?',
+      status: 'resolved',
+      priority: 'normal',
+      resolved_at: '2026-01-12T15:00:00Z',
+      closed_at: null,
+      created_at: '2026-01-12T08:00:00Z',
+      updated_at: '2026-01-12T15:00:00Z'
+    }
   ]
 }
 
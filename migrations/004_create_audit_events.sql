@@ -0,0 +1,41 @@
+-- Create append-only audit log for administrative changes
+CREATE TABLE IF NOT EXISTS audit_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Audit history must outlive the data it describes, so organizations with
+    -- recorded events cannot be deleted instead of cascading into the log
+    organization_id UUID REFERENCES organizations(id) ON DELETE RESTRICT,
+    -- Actor is stored by value (no foreign key) so deleting a user keeps their trail
+    actor_id UUID,
+    actor_sub VARCHAR(255) NOT NULL,
+    action VARCHAR(100) NOT NULL,
+    target_type VARCHAR(50) NOT NULL,
+    target_id UUID,
+    before JSONB,
+    after JSONB,
+    request_id VARCHAR(255),
+    ip_address VARCHAR(45),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_organization_id ON audit_events(organization_id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_org_created_at ON audit_events(organization_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_events_actor_id ON audit_events(actor_id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_action ON audit_events(action);
+CREATE INDEX IF NOT EXISTS idx_audit_events_target ON audit_events(target_type, target_id);
+
+-- Reject any modification of existing audit events
+CREATE OR REPLACE FUNCTION prevent_audit_event_modification() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only: % is not allowed', TG_OP;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER audit_events_no_update_delete
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION prevent_audit_event_modification();
+
+CREATE OR REPLACE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION prevent_audit_event_modification();
+
+COMMENT ON TABLE audit_events IS 'Append-only trail of administrative changes. Rows can only be inserted.';
@@ -0,0 +1,27 @@
+-- Chain audit events per organization so tampering can be detected.
+-- Each event stores its position in the organization's chain, the hash of the
+-- previous event and a SHA-256 hash over its own contents plus that link.
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS sequence BIGINT;
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS prev_hash VARCHAR(64);
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS hash VARCHAR(64);
+
+-- Number events recorded before chaining existed. They keep a NULL hash and
+-- are reported as unverifiable legacy events by the chain verifier.
+ALTER TABLE audit_events DISABLE TRIGGER audit_events_no_update_delete;
+
+UPDATE audit_events a
+SET sequence = numbered.sequence
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY organization_id ORDER BY created_at, id) AS sequence
+    FROM audit_events
+) numbered
+WHERE a.id = numbered.id AND a.sequence IS NULL;
+
+ALTER TABLE audit_events ENABLE TRIGGER audit_events_no_update_delete;
+
+ALTER TABLE audit_events ALTER COLUMN sequence SET NOT NULL;
+
+-- One event per position and organization: concurrent writers cannot fork the chain
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_events_org_sequence_unique ON audit_events(COALESCE(organization_id, '00000000-0000-0000-0000-000000000000'::uuid), sequence);
+
+COMMENT ON COLUMN audit_events.hash IS 'SHA-256 over the canonical event contents and prev_hash. NULL only for events recorded before chaining.';
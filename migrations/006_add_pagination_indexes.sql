@@ -0,0 +1,5 @@
+-- Keyset pagination walks collections in these orders. The id column breaks
+-- ties between rows with the same sort key so every row has a unique position.
+CREATE INDEX IF NOT EXISTS idx_users_fullname_id ON users(user_fullname, id);
+CREATE INDEX IF NOT EXISTS idx_roles_name_id ON roles(name, id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_created_at_id ON audit_events(created_at DESC, id DESC);
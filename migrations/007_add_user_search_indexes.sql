@@ -0,0 +1,11 @@
+-- Substring search on user names and emails (ILIKE '%term%') uses trigram
+-- indexes, which stay fast on large tables where a sequential scan would not.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_users_fullname_trgm ON users USING GIN (user_fullname gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_users_email_trgm ON users USING GIN (user_email gin_trgm_ops);
+
+-- Keyset pagination for the other sortable columns
+CREATE INDEX IF NOT EXISTS idx_users_email_id ON users(user_email, id);
+CREATE INDEX IF NOT EXISTS idx_users_created_at_id ON users(created_at, id);
+CREATE INDEX IF NOT EXISTS idx_roles_created_at_id ON roles(created_at, id);
@@ -0,0 +1,8 @@
+-- Account lifecycle. Deactivated users are rejected by the auth middleware;
+-- anonymized users keep their row (and id) so user_roles.assigned_by and the
+-- audit trail stay intact, but their personal data is scrubbed.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deactivated_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS anonymized_at TIMESTAMPTZ;
+
+COMMENT ON COLUMN users.deactivated_at IS 'Set while the account is deactivated. Its tokens are rejected.';
+COMMENT ON COLUMN users.anonymized_at IS 'Set once user_email, user_fullname and properties have been scrubbed. Irreversible.';
@@ -0,0 +1,34 @@
+-- Keep updated_at current on every UPDATE, whether or not the statement sets it
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = NOW();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER users_set_updated_at
+    BEFORE UPDATE ON users
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE OR REPLACE TRIGGER roles_set_updated_at
+    BEFORE UPDATE ON roles
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE OR REPLACE TRIGGER permissions_set_updated_at
+    BEFORE UPDATE ON permissions
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE OR REPLACE TRIGGER organizations_set_updated_at
+    BEFORE UPDATE ON organizations
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE OR REPLACE TRIGGER groups_set_updated_at
+    BEFORE UPDATE ON groups
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- JSON Schema that users.properties must satisfy for members of the
+-- organization. NULL allows any JSON object.
+ALTER TABLE organizations ADD COLUMN IF NOT EXISTS properties_schema JSONB;
+
+UPDATE users SET properties = '{}' WHERE properties IS NULL;
+ALTER TABLE users ALTER COLUMN properties SET NOT NULL;
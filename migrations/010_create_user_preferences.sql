@@ -0,0 +1,15 @@
+-- Preferences a user has set explicitly. Keys that are absent fall back to
+-- the organization's defaults and then to the built-in defaults.
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    preferences JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE OR REPLACE TRIGGER user_preferences_set_updated_at
+    BEFORE UPDATE ON user_preferences
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- Organization-wide overrides of the built-in defaults
+ALTER TABLE organizations ADD COLUMN IF NOT EXISTS default_preferences JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,66 @@
+-- Support tickets raised from the support page. Requesters (can_view_own on
+-- the 'support' page) see their own tickets, staff (can_view) see every
+-- ticket of their organization.
+DO $$
+BEGIN
+    CREATE TYPE ticket_status AS ENUM ('open', 'in_progress', 'waiting_on_requester', 'resolved', 'closed');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+DO $$
+BEGIN
+    CREATE TYPE ticket_priority AS ENUM ('low', 'normal', 'high', 'urgent');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS support_tickets (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    -- Tickets outlive the accounts that raised or worked on them
+    requester_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    assignee_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    subject VARCHAR(200) NOT NULL,
+    status ticket_status NOT NULL DEFAULT 'open',
+    priority ticket_priority NOT NULL DEFAULT 'normal',
+    resolved_at TIMESTAMPTZ,
+    closed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_support_tickets_org_created ON support_tickets(organization_id, created_at, id);
+CREATE INDEX IF NOT EXISTS idx_support_tickets_requester_created ON support_tickets(requester_id, created_at, id);
+CREATE INDEX IF NOT EXISTS idx_support_tickets_assignee ON support_tickets(assignee_id);
+
+CREATE OR REPLACE TRIGGER support_tickets_set_updated_at
+    BEFORE UPDATE ON support_tickets
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- Comments form threads through parent_id. The ticket's first message is
+-- its first comment
+CREATE TABLE IF NOT EXISTS support_ticket_comments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    ticket_id UUID NOT NULL REFERENCES support_tickets(id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES support_ticket_comments(id) ON DELETE CASCADE,
+    author_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_support_ticket_comments_ticket ON support_ticket_comments(ticket_id, created_at);
+
+CREATE TABLE IF NOT EXISTS support_ticket_attachments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    ticket_id UUID NOT NULL REFERENCES support_tickets(id) ON DELETE CASCADE,
+    uploaded_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    sha256 CHAR(64) NOT NULL,
+    content BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_support_ticket_attachments_ticket ON support_ticket_attachments(ticket_id, created_at);
@@ -0,0 +1,14 @@
+-- Rate limit counters shared by every replica, see src/rate_limit.rs.
+-- Unlogged: losing them in a crash only resets the limits.
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limit_counters (
+    bucket_key TEXT PRIMARY KEY,
+    -- Tokens left (token bucket) or hits in the current window (sliding window)
+    value DOUBLE PRECISION NOT NULL,
+    -- Hits in the previous window (sliding window)
+    previous DOUBLE PRECISION NOT NULL DEFAULT 0,
+    -- Last refill or start of the current window, in Unix seconds
+    since DOUBLE PRECISION NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS rate_limit_counters_expires_at_idx ON rate_limit_counters (expires_at);
@@ -0,0 +1,28 @@
+-- Permission rows carry their role's organization_id. The single-page upsert
+-- used to leave it NULL while the bulk replacement set it, so a role could
+-- hold one row per page under each side of the COALESCE unique index.
+-- Keep the most recently updated row of each pair, then align the rest.
+DELETE FROM permissions p
+USING roles r, permissions other
+WHERE r.id = p.role_id
+  AND other.role_id = p.role_id
+  AND other.page = p.page
+  AND other.id <> p.id
+  AND p.organization_id IS DISTINCT FROM r.organization_id
+  AND other.organization_id IS NOT DISTINCT FROM r.organization_id
+  AND p.updated_at <= other.updated_at;
+
+DELETE FROM permissions p
+USING roles r, permissions other
+WHERE r.id = p.role_id
+  AND other.role_id = p.role_id
+  AND other.page = p.page
+  AND other.id <> p.id
+  AND p.organization_id IS NOT DISTINCT FROM r.organization_id
+  AND other.organization_id IS DISTINCT FROM r.organization_id;
+
+UPDATE permissions p
+SET organization_id = r.organization_id
+FROM roles r
+WHERE r.id = p.role_id
+  AND p.organization_id IS DISTINCT FROM r.organization_id;
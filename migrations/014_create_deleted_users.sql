@@ -0,0 +1,6 @@
+-- Subjects of deleted users. Their tokens may still be unexpired, so the
+-- auth middleware rejects them and onboarding refuses to recreate the account.
+CREATE TABLE IF NOT EXISTS deleted_users (
+    sub VARCHAR(255) PRIMARY KEY,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,462 @@
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::middleware::RequestId;
+use crate::models::{AppState, AuditChainBreak, AuditChainVerification, AuditEvent, Claims};
+
+/// Audit action names recorded in `audit_events.action`
+pub mod actions {
+    pub const ROLE_CREATE: &str = "role.create";
+    pub const ROLE_UPDATE: &str = "role.update";
+    pub const ROLE_DELETE: &str = "role.delete";
+    pub const ROLE_PERMISSION_SET: &str = "role.permission.set";
+    pub const ROLE_PERMISSIONS_REPLACE: &str = "role.permissions.replace";
+    pub const USER_ROLE_ASSIGN: &str = "user.role.assign";
+    pub const USER_ROLE_REMOVE: &str = "user.role.remove";
+    pub const USER_DEACTIVATE: &str = "user.deactivate";
+    pub const USER_REACTIVATE: &str = "user.reactivate";
+    pub const USER_ANONYMIZE: &str = "user.anonymize";
+    pub const USER_DELETE: &str = "user.delete";
+    pub const USER_UPDATE: &str = "user.update";
+    pub const ORGANIZATION_PROPERTIES_SCHEMA_SET: &str = "organization.properties_schema.set";
+    pub const ORGANIZATION_DEFAULT_PREFERENCES_SET: &str = "organization.default_preferences.set";
+}
+
+/// Audit target types recorded in `audit_events.target_type`
+pub mod targets {
+    pub const ROLE: &str = "role";
+    pub const USER: &str = "user";
+    pub const ORGANIZATION: &str = "organization";
+}
+
+/// Who made a change and where the request came from
+pub struct AuditContext {
+    pub actor_sub: String,
+    pub organization: Option<String>,
+    pub request_id: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// A single change to be written to the audit log
+pub struct AuditRecord<'a> {
+    pub action: &'a str,
+    pub target_type: &'a str,
+    pub target_id: Option<Uuid>,
+    /// Organization of the target, whose chain the event joins; the actor's
+    /// organization is used when the target has none
+    pub organization_id: Option<Uuid>,
+    pub before: Option<JsonValue>,
+    pub after: Option<JsonValue>,
+}
+
+/// Serialize a value for the `before`/`after` columns
+pub fn snapshot<T: Serialize>(value: &T) -> Option<JsonValue> {
+    serde_json::to_value(value).ok()
+}
+
+/// Append an event to the audit log.
+///
+/// Takes a connection rather than the pool so callers can write the event in
+/// the same transaction as the change it describes. The event belongs to the
+/// target's organization, falling back to the actor's, and is linked to the
+/// previous event of that organization through `prev_hash`. Writers of one
+/// organization's chain are serialized with a transaction-level advisory lock.
+pub async fn record(
+    conn: &mut PgConnection,
+    ctx: &AuditContext,
+    event: AuditRecord<'_>,
+) -> Result<(), sqlx::Error> {
+    let (actor_id, organization_id): (Option<Uuid>, Option<Uuid>) = sqlx::query_as(
+        "SELECT
+            (SELECT id FROM users WHERE sub = $1),
+            COALESCE(
+                $3,
+                (SELECT organization_id FROM users WHERE sub = $1),
+                (SELECT id FROM organizations WHERE name = $2)
+            )",
+    )
+    .bind(&ctx.actor_sub)
+    .bind(&ctx.organization)
+    .bind(event.organization_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "SELECT pg_advisory_xact_lock(hashtext('audit_events'), hashtext(COALESCE($1::text, '')))",
+    )
+    .bind(organization_id)
+    .execute(&mut *conn)
+    .await?;
+
+    let previous: Option<(i64, Option<String>)> = sqlx::query_as(
+        "SELECT sequence, hash FROM audit_events
+         WHERE COALESCE(organization_id, '00000000-0000-0000-0000-000000000000'::uuid)
+             = COALESCE($1, '00000000-0000-0000-0000-000000000000'::uuid)
+         ORDER BY sequence DESC
+         LIMIT 1",
+    )
+    .bind(organization_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    // Truncate to the microsecond precision of TIMESTAMPTZ so the hash can be recomputed
+    let now = Utc::now();
+    let created_at = DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now);
+
+    let (sequence, prev_hash) = match previous {
+        Some((sequence, hash)) => (sequence + 1, hash),
+        None => (1, None),
+    };
+
+    let mut audit_event = AuditEvent {
+        id: Uuid::new_v4(),
+        organization_id,
+        actor_id,
+        actor_sub: ctx.actor_sub.clone(),
+        action: event.action.to_string(),
+        target_type: event.target_type.to_string(),
+        target_id: event.target_id,
+        before: event.before,
+        after: event.after,
+        request_id: ctx.request_id.clone(),
+        ip_address: ctx.ip_address.clone(),
+        created_at,
+        sequence,
+        prev_hash,
+        hash: None,
+    };
+    audit_event.hash = Some(event_hash(&audit_event));
+
+    sqlx::query(
+        "INSERT INTO audit_events
+            (id, organization_id, actor_id, actor_sub, action, target_type, target_id, before, after,
+             request_id, ip_address, created_at, sequence, prev_hash, hash)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+    )
+    .bind(audit_event.id)
+    .bind(audit_event.organization_id)
+    .bind(audit_event.actor_id)
+    .bind(&audit_event.actor_sub)
+    .bind(&audit_event.action)
+    .bind(&audit_event.target_type)
+    .bind(audit_event.target_id)
+    .bind(&audit_event.before)
+    .bind(&audit_event.after)
+    .bind(&audit_event.request_id)
+    .bind(&audit_event.ip_address)
+    .bind(audit_event.created_at)
+    .bind(audit_event.sequence)
+    .bind(&audit_event.prev_hash)
+    .bind(&audit_event.hash)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Compute the chain hash of an event: SHA-256 over the canonical JSON of
+/// every column except `hash` itself, which includes the link to the previous event
+pub fn event_hash(event: &AuditEvent) -> String {
+    let contents = json!({
+        "id": event.id,
+        "organization_id": event.organization_id,
+        "actor_id": event.actor_id,
+        "actor_sub": event.actor_sub,
+        "action": event.action,
+        "target_type": event.target_type,
+        "target_id": event.target_id,
+        "before": event.before,
+        "after": event.after,
+        "request_id": event.request_id,
+        "ip_address": event.ip_address,
+        "created_at": event.created_at.timestamp_micros(),
+        "sequence": event.sequence,
+        "prev_hash": event.prev_hash,
+    });
+
+    let mut canonical = String::new();
+    write_canonical_json(&contents, &mut canonical);
+
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// Serialize JSON with object keys sorted at every level, independent of the
+/// map implementation serde_json was built with and of JSONB's key ordering
+fn write_canonical_json(value: &JsonValue, out: &mut String) {
+    match value {
+        JsonValue::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (index, key) in keys.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&JsonValue::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical_json(&map[key], out);
+            }
+            out.push('}');
+        }
+        JsonValue::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_canonical_json(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// Walks one organization's chain in sequence order and stops at the first broken link
+pub struct ChainVerifier {
+    expected_sequence: i64,
+    previous_hash: Option<String>,
+    chained: bool,
+    verified_events: u64,
+    legacy_events: u64,
+    first_broken_link: Option<AuditChainBreak>,
+}
+
+impl Default for ChainVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChainVerifier {
+    pub fn new() -> Self {
+        ChainVerifier {
+            expected_sequence: 1,
+            previous_hash: None,
+            chained: false,
+            verified_events: 0,
+            legacy_events: 0,
+            first_broken_link: None,
+        }
+    }
+
+    /// Check the next event of the chain. Returns false once the chain is broken.
+    pub fn push(&mut self, event: &AuditEvent) -> bool {
+        if self.first_broken_link.is_some() {
+            return false;
+        }
+
+        if event.sequence != self.expected_sequence {
+            return self.broken(
+                event,
+                format!(
+                    "expected sequence {} but found {}; events are missing or out of order",
+                    self.expected_sequence, event.sequence
+                ),
+            );
+        }
+
+        match &event.hash {
+            // Events recorded before chaining may only appear before the first hashed event
+            None if !self.chained && event.prev_hash.is_none() => {
+                self.legacy_events += 1;
+            }
+            None => return self.broken(event, "event has no hash".to_string()),
+            Some(hash) => {
+                if event.prev_hash != self.previous_hash {
+                    return self.broken(
+                        event,
+                        "prev_hash does not match the hash of the previous event".to_string(),
+                    );
+                }
+                if *hash != event_hash(event) {
+                    return self.broken(event, "event contents do not match its hash".to_string());
+                }
+                self.chained = true;
+                self.previous_hash = Some(hash.clone());
+                self.verified_events += 1;
+            }
+        }
+
+        self.expected_sequence += 1;
+        true
+    }
+
+    fn broken(&mut self, event: &AuditEvent, reason: String) -> bool {
+        self.first_broken_link = Some(AuditChainBreak {
+            sequence: self.expected_sequence,
+            event_id: Some(event.id),
+            reason,
+        });
+        false
+    }
+
+    pub fn finish(self, organization_id: Option<Uuid>) -> AuditChainVerification {
+        AuditChainVerification {
+            organization_id,
+            valid: self.first_broken_link.is_none(),
+            verified_events: self.verified_events,
+            legacy_events: self.legacy_events,
+            head_hash: self.previous_hash,
+            first_broken_link: self.first_broken_link,
+        }
+    }
+}
+
+// Extractor for AuditContext; requires claims inserted by the auth middleware
+#[axum::async_trait]
+impl FromRequestParts<Arc<AppState>> for AuditContext {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let claims = parts
+            .extensions
+            .get::<Claims>()
+            .ok_or_else(|| AppError::unauthorized("missing_token", "Unauthorized"))?;
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0);
+
+        Ok(AuditContext {
+            actor_sub: claims.sub.clone(),
+            organization: claims.organization.clone(),
+            request_id: parts.extensions.get::<RequestId>().map(|id| id.0.clone()),
+            // Only believe forwarding headers from trusted proxies, so the
+            // chain never records an address the client made up
+            ip_address: state
+                .trusted_proxies
+                .client_ip(&parts.headers, peer)
+                .map(|ip| ip.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(length: i64) -> Vec<AuditEvent> {
+        let mut events: Vec<AuditEvent> = Vec::new();
+        for sequence in 1..=length {
+            let mut event = AuditEvent {
+                id: Uuid::new_v4(),
+                organization_id: None,
+                actor_id: Some(Uuid::new_v4()),
+                actor_sub: "admin".to_string(),
+                action: actions::ROLE_UPDATE.to_string(),
+                target_type: targets::ROLE.to_string(),
+                target_id: Some(Uuid::new_v4()),
+                before: Some(json!({"name": "View", "is_admin": false})),
+                after: Some(json!({"name": "Viewer", "is_admin": false})),
+                request_id: None,
+                ip_address: Some("127.0.0.1".to_string()),
+                created_at: Utc::now(),
+                sequence,
+                prev_hash: events.last().and_then(|e| e.hash.clone()),
+                hash: None,
+            };
+            event.hash = Some(event_hash(&event));
+            events.push(event);
+        }
+        events
+    }
+
+    fn verify(events: &[AuditEvent]) -> AuditChainVerification {
+        let mut verifier = ChainVerifier::new();
+        for event in events {
+            if !verifier.push(event) {
+                break;
+            }
+        }
+        verifier.finish(None)
+    }
+
+    #[test]
+    fn test_intact_chain_verifies() {
+        let events = chain(3);
+        let result = verify(&events);
+
+        assert!(result.valid);
+        assert_eq!(result.verified_events, 3);
+        assert_eq!(result.head_hash, events[2].hash);
+    }
+
+    #[test]
+    fn test_modified_event_breaks_chain() {
+        let mut events = chain(3);
+        events[1].after = Some(json!({"name": "Admin", "is_admin": true}));
+
+        let broken = verify(&events).first_broken_link.unwrap();
+
+        assert_eq!(broken.sequence, 2);
+        assert!(broken.reason.contains("contents"));
+    }
+
+    #[test]
+    fn test_rehashed_event_breaks_next_link() {
+        let mut events = chain(3);
+        events[1].actor_sub = "someone-else".to_string();
+        events[1].hash = Some(event_hash(&events[1]));
+
+        let broken = verify(&events).first_broken_link.unwrap();
+
+        assert_eq!(broken.sequence, 3);
+        assert!(broken.reason.contains("prev_hash"));
+    }
+
+    #[test]
+    fn test_deleted_event_breaks_chain() {
+        let mut events = chain(3);
+        events.remove(1);
+
+        let broken = verify(&events).first_broken_link.unwrap();
+
+        assert_eq!(broken.sequence, 2);
+        assert_eq!(broken.event_id, Some(events[1].id));
+    }
+
+    #[test]
+    fn test_legacy_events_only_allowed_before_chain() {
+        let mut events = chain(3);
+        events[0].hash = None;
+        events[1].prev_hash = None;
+        events[1].hash = Some(event_hash(&events[1]));
+        events[2].prev_hash = events[1].hash.clone();
+        events[2].hash = Some(event_hash(&events[2]));
+
+        let result = verify(&events);
+        assert!(result.valid);
+        assert_eq!(result.legacy_events, 1);
+        assert_eq!(result.verified_events, 2);
+
+        let mut events = chain(3);
+        events[2].hash = None;
+        assert!(!verify(&events).valid);
+    }
+
+    #[test]
+    fn test_event_hash_ignores_json_key_order() {
+        let mut events = chain(1);
+        let hash = event_hash(&events[0]);
+
+        events[0].after = serde_json::from_str(r#"{"is_admin": false, "name": "Viewer"}"#).ok();
+
+        assert_eq!(hash, event_hash(&events[0]));
+    }
+}
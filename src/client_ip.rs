@@ -0,0 +1,154 @@
+//! Client addresses behind reverse proxies.
+//!
+//! `X-Forwarded-For` and `X-Real-IP` are only believed when the connection
+//! comes from a configured trusted proxy, such as the nginx gateway. Anyone
+//! reaching the backend directly could otherwise claim any address, getting
+//! a fresh rate limit bucket per request or a forged IP in the audit log.
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+/// Networks whose forwarding headers are believed, see `server.trusted_proxies`
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    /// Parse CIDRs or single addresses, as listed in the configuration
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> Result<Self, String> {
+        entries
+            .iter()
+            .map(|entry| parse_network(entry.as_ref()))
+            .collect::<Result<_, _>>()
+            .map(|networks| TrustedProxies { networks })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+
+    /// The address of the client behind `peer`.
+    ///
+    /// When `peer` is a trusted proxy the rightmost `X-Forwarded-For` hop not
+    /// added by a trusted proxy is taken, then `X-Real-IP`. Otherwise, or when
+    /// the headers are missing or malformed, it is `peer` itself.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> Option<IpAddr> {
+        let peer = peer?.ip();
+        if !self.contains(peer) {
+            return Some(peer);
+        }
+
+        let header = |name: &str| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let forwarded = header("X-Forwarded-For");
+        let mut hops = forwarded
+            .split(',')
+            .map(str::trim)
+            .filter(|hop| !hop.is_empty())
+            .peekable();
+        if hops.peek().is_some() {
+            let mut client = peer;
+            for hop in hops.rev() {
+                match hop.parse::<IpAddr>() {
+                    Ok(ip) if self.contains(ip) => client = ip,
+                    Ok(ip) => return Some(ip),
+                    Err(_) => return Some(client),
+                }
+            }
+            return Some(client);
+        }
+
+        Some(header("X-Real-IP").trim().parse::<IpAddr>().unwrap_or(peer))
+    }
+}
+
+fn parse_network(entry: &str) -> Result<IpNet, String> {
+    let entry = entry.trim();
+    entry
+        .parse::<IpNet>()
+        .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("{:?} is not an IP address or CIDR", entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies::parse(&["10.0.0.0/8", "::1"]).unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(text: &str) -> Option<IpAddr> {
+        text.parse().ok()
+    }
+
+    #[test]
+    fn test_untrusted_peer_headers_are_ignored() {
+        let spoofed = headers(&[
+            ("X-Real-IP", "203.0.113.7"),
+            ("X-Forwarded-For", "198.51.100.1"),
+        ]);
+        let peer = "192.0.2.10:4000".parse().ok();
+
+        assert_eq!(proxies().client_ip(&spoofed, peer), ip("192.0.2.10"));
+        assert_eq!(
+            TrustedProxies::default().client_ip(&spoofed, "10.0.0.2:4000".parse().ok()),
+            ip("10.0.0.2")
+        );
+        assert_eq!(proxies().client_ip(&spoofed, None), None);
+    }
+
+    #[test]
+    fn test_rightmost_untrusted_forwarded_hop_is_the_client() {
+        // The client prepended a forged hop; the gateway appended the real one
+        let forwarded = headers(&[("X-Forwarded-For", "1.2.3.4, 198.51.100.1, 10.0.0.5")]);
+        let peer = "10.0.0.2:4000".parse().ok();
+
+        assert_eq!(proxies().client_ip(&forwarded, peer), ip("198.51.100.1"));
+
+        let split = headers(&[
+            ("X-Forwarded-For", "1.2.3.4"),
+            ("X-Forwarded-For", "198.51.100.1"),
+        ]);
+        assert_eq!(proxies().client_ip(&split, peer), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn test_trusted_peer_falls_back_to_real_ip_then_itself() {
+        let peer = "[::1]:4000".parse().ok();
+
+        assert_eq!(
+            proxies().client_ip(&headers(&[("X-Real-IP", "203.0.113.7")]), peer),
+            ip("203.0.113.7")
+        );
+        assert_eq!(proxies().client_ip(&HeaderMap::new(), peer), ip("::1"));
+        assert_eq!(
+            proxies().client_ip(&headers(&[("X-Forwarded-For", "not-an-ip")]), peer),
+            ip("::1")
+        );
+    }
+
+    #[test]
+    fn test_invalid_networks_are_refused() {
+        assert!(TrustedProxies::parse(&["10.0.0.0/33"]).is_err());
+        assert!(TrustedProxies::parse(&["gateway"]).is_err());
+        assert!(TrustedProxies::parse(&["192.168.0.1"]).is_ok());
+    }
+}
@@ -0,0 +1,288 @@
+//! Cross-origin resource sharing.
+//!
+//! The policy comes from [`CorsConfig`]. Origins are matched exactly or
+//! against subdomain patterns: `https://*.example.com` allows
+//! `https://app.example.com` and `https://eu.app.example.com`, but not
+//! `https://example.com` itself. `dev` and `test` allow any origin, `prod`
+//! none until configured, and refuses `*` for origins, methods and headers.
+
+use axum::http::{request::Parts, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer, ExposeHeaders};
+
+use crate::config::CorsConfig;
+
+/// Wildcard allowing any origin, method or header
+pub const ANY: &str = "*";
+
+/// An allowed origin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginPattern {
+    Exact(String),
+    /// Scheme, then the domain and port below which any subdomain matches,
+    /// with its leading dot
+    Subdomains {
+        scheme: String,
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> Result<OriginPattern, String> {
+        let pattern = pattern.trim().trim_end_matches('/').to_ascii_lowercase();
+        let Some((scheme, host)) = pattern.split_once("://") else {
+            return Err(format!(
+                "{:?} is not an origin like https://app.example.com",
+                pattern
+            ));
+        };
+        if scheme != "http" && scheme != "https" {
+            return Err(format!("{:?} must use http or https", pattern));
+        }
+        if host.is_empty() || host.contains('/') {
+            return Err(format!("{:?} must not have a path", pattern));
+        }
+        match host.strip_prefix('*') {
+            None if host.contains('*') => Err(format!(
+                "{:?} may only use * as its leftmost label, as in https://*.example.com",
+                pattern
+            )),
+            None => Ok(OriginPattern::Exact(pattern)),
+            Some(suffix) => {
+                let domain = suffix.strip_prefix('.').unwrap_or_default();
+                let domain = domain.split(':').next().unwrap_or_default();
+                // `*.com` would allow every site of a top-level domain
+                if !suffix.starts_with('.') || domain.contains('*') || !domain.contains('.') {
+                    return Err(format!(
+                        "{:?} must name a domain below the wildcard, as in https://*.example.com",
+                        pattern
+                    ));
+                }
+                Ok(OriginPattern::Subdomains {
+                    scheme: scheme.to_string(),
+                    suffix: suffix.to_string(),
+                })
+            }
+        }
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            OriginPattern::Exact(exact) => origin == *exact,
+            OriginPattern::Subdomains { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|rest| rest.strip_prefix("://"))
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && subdomain
+                            .split('.')
+                            .all(|label| !label.is_empty() && is_host_label(label))
+                }),
+        }
+    }
+}
+
+fn is_host_label(label: &str) -> bool {
+    label
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// Every problem of `config`, for [`crate::config::Config::validate`]
+pub fn problems(config: &CorsConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    for origin in &config.allowed_origins {
+        if origin != ANY {
+            if let Err(e) = OriginPattern::parse(origin) {
+                errors.push(format!("cors.allowed_origins: {}", e));
+            }
+        }
+    }
+    for method in &config.allowed_methods {
+        if method != ANY && Method::from_bytes(method.as_bytes()).is_err() {
+            errors.push(format!(
+                "cors.allowed_methods: {:?} is not a method",
+                method
+            ));
+        }
+    }
+    for (key, headers) in [
+        ("allowed_headers", &config.allowed_headers),
+        ("exposed_headers", &config.exposed_headers),
+    ] {
+        for name in headers {
+            if name != ANY && HeaderName::from_bytes(name.as_bytes()).is_err() {
+                errors.push(format!("cors.{}: {:?} is not a header name", key, name));
+            }
+        }
+    }
+    // Browsers ignore wildcards on credentialed requests, and tower-http
+    // refuses to build such a layer
+    if config.allow_credentials {
+        for (key, values) in [
+            ("allowed_origins", &config.allowed_origins),
+            ("allowed_methods", &config.allowed_methods),
+            ("allowed_headers", &config.allowed_headers),
+            ("exposed_headers", &config.exposed_headers),
+        ] {
+            if values.iter().any(|value| value == ANY) {
+                errors.push(format!(
+                    "cors.{} cannot be * when cors.allow_credentials is set",
+                    key
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// The layer enforcing `config`, which must have passed [`problems`]
+pub fn layer(config: &CorsConfig) -> CorsLayer {
+    let any = |values: &[String]| values.iter().any(|value| value == ANY);
+
+    let origins = if any(&config.allowed_origins) {
+        AllowOrigin::any()
+    } else {
+        let patterns: Vec<OriginPattern> = config
+            .allowed_origins
+            .iter()
+            .filter_map(|origin| OriginPattern::parse(origin).ok())
+            .collect();
+        AllowOrigin::predicate(move |origin: &HeaderValue, _: &Parts| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| patterns.iter().any(|pattern| pattern.matches(origin)))
+        })
+    };
+    let methods = if any(&config.allowed_methods) {
+        AllowMethods::any()
+    } else {
+        let parsed = config
+            .allowed_methods
+            .iter()
+            .filter_map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes()).ok());
+        AllowMethods::list(parsed)
+    };
+    let headers = if any(&config.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(header_names(&config.allowed_headers))
+    };
+    let exposed = if any(&config.exposed_headers) {
+        ExposeHeaders::any()
+    } else {
+        ExposeHeaders::list(header_names(&config.exposed_headers))
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .expose_headers(exposed)
+        .allow_credentials(config.allow_credentials)
+        .max_age(Duration::from_secs(config.max_age_secs))
+}
+
+fn header_names(names: &[String]) -> Vec<HeaderName> {
+    names
+        .iter()
+        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Profile};
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_origin_patterns() {
+        let exact = OriginPattern::parse("https://App.example.com/").unwrap();
+        assert!(exact.matches("https://app.example.com"));
+        assert!(!exact.matches("http://app.example.com"));
+
+        let subdomains = OriginPattern::parse("https://*.example.com").unwrap();
+        assert!(subdomains.matches("https://app.example.com"));
+        assert!(subdomains.matches("https://eu.app.example.com"));
+        assert!(!subdomains.matches("https://example.com"));
+        assert!(!subdomains.matches("https://evilexample.com"));
+        assert!(!subdomains.matches("https://app.example.com.evil.io"));
+        assert!(!subdomains.matches("https://app.example.com:8443"));
+
+        let with_port = OriginPattern::parse("http://*.localhost.test:3000").unwrap();
+        assert!(with_port.matches("http://ui.localhost.test:3000"));
+
+        for invalid in [
+            "app.example.com",
+            "ftp://app.example.com",
+            "https://*.com",
+            "https://*example.com",
+            "https://app.*.example.com",
+            "https://app.example.com/path",
+        ] {
+            assert!(OriginPattern::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_credentials_refuse_wildcards() {
+        let mut config = Config::defaults(Profile::Dev).cors;
+        assert!(problems(&config).is_empty());
+
+        config.allow_credentials = true;
+        assert_eq!(problems(&config).len(), 1);
+
+        config.allowed_origins = vec!["https://app.example.com".to_string()];
+        config.allowed_methods = vec!["GET".to_string(), "NOT A METHOD".to_string()];
+        let errors = problems(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("not a method"));
+    }
+
+    async fn preflight(config: &CorsConfig, origin: &str) -> Option<String> {
+        let app = Router::new()
+            .route("/api/v1/admin/roles", get(|| async { "roles" }))
+            .layer(layer(config));
+        let response = app
+            .oneshot(
+                Request::options("/api/v1/admin/roles")
+                    .header("Origin", origin)
+                    .header("Access-Control-Request-Method", "GET")
+                    .header("Access-Control-Request-Headers", "authorization")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        response
+            .headers()
+            .get("access-control-allow-origin")
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_layer_allows_configured_origins_only() {
+        let mut config = Config::defaults(Profile::Prod).cors;
+        assert_eq!(preflight(&config, "https://app.example.com").await, None);
+
+        config.allowed_origins = vec!["https://*.example.com".to_string()];
+        config.allow_credentials = true;
+        assert_eq!(
+            preflight(&config, "https://app.example.com")
+                .await
+                .as_deref(),
+            Some("https://app.example.com")
+        );
+        assert_eq!(preflight(&config, "https://evil.io").await, None);
+
+        let dev = Config::defaults(Profile::Dev).cors;
+        assert_eq!(
+            preflight(&dev, "https://evil.io").await.as_deref(),
+            Some("*")
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use axum::{
+    extract::rejection::{BytesRejection, JsonRejection},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::middleware::current_request_id;
+
+/// Media type of RFC 7807 problem documents
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// A problem with a single request field
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// RFC 7807 problem details body
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Stable machine-readable error code
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// Application error returned by handlers and middleware.
+///
+/// Renders as `application/problem+json` with the request ID of the current
+/// request, so every failure has the same shape regardless of where it happened.
+#[derive(Debug)]
+pub struct AppError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub detail: String,
+    pub errors: Vec<FieldError>,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        AppError {
+            status,
+            code,
+            detail: detail.into(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn bad_request(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, detail)
+    }
+
+    pub fn unauthorized(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, code, detail)
+    }
+
+    pub fn forbidden(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, code, detail)
+    }
+
+    pub fn not_found(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, detail)
+    }
+
+    pub fn conflict(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, detail)
+    }
+
+    /// 422 listing every invalid field
+    pub fn validation(errors: Vec<FieldError>) -> Self {
+        AppError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            code: "validation_failed",
+            detail: "The request contains invalid fields".to_string(),
+            errors,
+        }
+    }
+
+    pub fn problem(&self) -> ProblemDetails {
+        ProblemDetails {
+            problem_type: format!("/problems/{}", self.code.replace('_', "-")),
+            title: self
+                .status
+                .canonical_reason()
+                .unwrap_or("Error")
+                .to_string(),
+            status: self.status.as_u16(),
+            detail: self.detail.clone(),
+            code: self.code.to_string(),
+            request_id: current_request_id(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.code, self.detail)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let body = serde_json::to_vec(&self.problem()).unwrap_or_default();
+        let mut response = (self.status, body).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
+
+// PostgreSQL SQLSTATE codes mapped to client errors
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const NOT_NULL_VIOLATION: &str = "23502";
+const CHECK_VIOLATION: &str = "23514";
+
+/// Stable codes for the constraints clients are expected to run into. Other
+/// constraints get the generic code of their SQLSTATE; constraint names are
+/// schema details and never reach the response.
+const KNOWN_CONSTRAINTS: &[(&str, &str, &str)] = &[
+    (
+        "idx_roles_name_org_unique",
+        "role_name_taken",
+        "A role with this name already exists",
+    ),
+    (
+        "organizations_name_key",
+        "organization_name_taken",
+        "An organization with this name already exists",
+    ),
+    (
+        "user_roles_user_id_fkey",
+        "user_not_found",
+        "The user does not exist",
+    ),
+    (
+        "user_roles_role_id_fkey",
+        "role_not_found",
+        "The role does not exist",
+    ),
+];
+
+/// Map a constraint violation to a client error, `None` for other database errors
+fn constraint_error(sqlstate: Option<&str>, constraint: Option<&str>) -> Option<AppError> {
+    let (status, code, detail) = match sqlstate? {
+        UNIQUE_VIOLATION => (
+            StatusCode::CONFLICT,
+            "unique_violation",
+            "A resource with this value already exists",
+        ),
+        FOREIGN_KEY_VIOLATION => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "foreign_key_violation",
+            "A referenced resource does not exist",
+        ),
+        NOT_NULL_VIOLATION | CHECK_VIOLATION => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "constraint_violation",
+            "The request violates a data constraint",
+        ),
+        _ => return None,
+    };
+    tracing::warn!(
+        constraint = constraint.unwrap_or("unknown"),
+        code,
+        "Request violated a database constraint"
+    );
+
+    let known = constraint.and_then(|name| KNOWN_CONSTRAINTS.iter().find(|(c, _, _)| *c == name));
+    Some(match known {
+        Some((_, code, detail)) => AppError::new(status, code, *detail),
+        None => AppError::new(status, code, detail),
+    })
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::RowNotFound => {
+                AppError::not_found("not_found", "The requested resource was not found")
+            }
+            sqlx::Error::PoolTimedOut => {
+                tracing::error!("Database error: {}", e);
+                AppError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "database_unavailable",
+                    "The database is temporarily unavailable",
+                )
+            }
+            sqlx::Error::Database(db) => constraint_error(db.code().as_deref(), db.constraint())
+                .unwrap_or_else(|| {
+                    tracing::error!("Database error: {}", e);
+                    AppError::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "database_error",
+                        "Database error",
+                    )
+                }),
+            _ => {
+                tracing::error!("Database error: {}", e);
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "database_error",
+                    "Database error",
+                )
+            }
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match &rejection {
+            JsonRejection::JsonDataError(_) => "invalid_body",
+            JsonRejection::JsonSyntaxError(_) => "malformed_json",
+            JsonRejection::MissingJsonContentType(_) => "unsupported_media_type",
+            _ => "invalid_body",
+        };
+        AppError::new(rejection.status(), code, rejection.body_text())
+    }
+}
+
+impl From<BytesRejection> for AppError {
+    fn from(rejection: BytesRejection) -> Self {
+        let code = match rejection.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            _ => "invalid_body",
+        };
+        AppError::new(rejection.status(), code, rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_details_shape() {
+        let error = AppError::not_found("role_not_found", "Role not found");
+        let problem = serde_json::to_value(error.problem()).unwrap();
+
+        assert_eq!(problem["type"], "/problems/role-not-found");
+        assert_eq!(problem["title"], "Not Found");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["code"], "role_not_found");
+        assert!(problem.get("errors").is_none());
+        assert!(problem.get("request_id").is_none());
+    }
+
+    #[test]
+    fn test_validation_error_lists_fields() {
+        let error = AppError::validation(vec![
+            FieldError::new("name", "length", "name must not be empty"),
+            FieldError::new("page", "pattern", "page is invalid"),
+        ]);
+        let problem = serde_json::to_value(error.problem()).unwrap();
+
+        assert_eq!(problem["status"], 422);
+        assert_eq!(problem["errors"].as_array().unwrap().len(), 2);
+        assert_eq!(problem["errors"][1]["field"], "page");
+    }
+
+    #[test]
+    fn test_row_not_found_maps_to_404() {
+        let error = AppError::from(sqlx::Error::RowNotFound);
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_constraint_names_stay_out_of_details() {
+        let error = constraint_error(Some(UNIQUE_VIOLATION), Some("users_sub_key")).unwrap();
+        assert_eq!(error.status, StatusCode::CONFLICT);
+        assert_eq!(error.code, "unique_violation");
+        assert!(!error.detail.contains("users_sub_key"));
+
+        let error =
+            constraint_error(Some(FOREIGN_KEY_VIOLATION), Some("user_roles_role_id_fkey")).unwrap();
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code, "role_not_found");
+
+        assert!(constraint_error(Some("40001"), None).is_none());
+    }
+
+    #[test]
+    fn test_into_response_uses_problem_json() {
+        let response = AppError::conflict("version_conflict", "stale").into_response();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_JSON
+        );
+    }
+}
@@ -1,174 +1,317 @@
 use axum::{
-    extract::{Path, Query, State},
+    extract::{OriginalUri, Path, Query, State},
     http::StatusCode,
     response::Json,
 };
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::audit::{self, actions, targets, AuditContext, AuditRecord, ChainVerifier};
+use crate::error::{AppError, ProblemDetails};
 use crate::models::{
-    AppState, AssignRoleRequest, Claims, CreateRoleRequest, ErrorResponse, PaginationQuery,
-    Permission, Role, RoleWithPermissions, SetPermissionRequest, UpdateRoleRequest, User, UserRole,
-    UserWithRoles,
+    AppState, AssignRoleRequest, AuditChainQuery, AuditChainVerification, AuditEvent,
+    AuditEventQuery, Claims, CreateRoleRequest, DefaultPreferencesResponse, LegacyPageQuery, Page,
+    PaginationQuery, Permission, PermissionChange, PermissionDiff, PermissionFlags,
+    PermissionMatrixResponse, PreferenceOverrides, Preferences, PropertiesSchemaRequest,
+    PropertiesSchemaResponse, ReplacePermissionsRequest, ReplacePermissionsResponse, Role,
+    RoleListQuery, RoleWithPermissions, SetPermissionRequest, UpdateRoleRequest, UpdateUserRequest,
+    User, UserListQuery, UserRole, UserWithRoles,
 };
+use crate::pagination::{self, Paginated, Sort, SortField};
+use crate::preferences;
+use crate::validation::{self, ValidatedJson};
+
+/// Number of audit events loaded per round-trip while verifying a chain
+const AUDIT_VERIFY_BATCH_SIZE: i64 = 500;
+
+/// Columns `GET /admin/roles` can be sorted by; the first is the default
+const ROLE_SORT_FIELDS: &[SortField] = &[
+    SortField {
+        name: "name",
+        column: "name",
+        sql_type: "text",
+    },
+    SortField {
+        name: "created_at",
+        column: "created_at",
+        sql_type: "timestamptz",
+    },
+];
+
+/// Columns `GET /admin/users` can be sorted by; the first is the default
+const USER_SORT_FIELDS: &[SortField] = &[
+    SortField {
+        name: "name",
+        column: "user_fullname",
+        sql_type: "text",
+    },
+    SortField {
+        name: "email",
+        column: "user_email",
+        sql_type: "text",
+    },
+    SortField {
+        name: "created_at",
+        column: "created_at",
+        sql_type: "timestamptz",
+    },
+];
 
 // ==================== Role Management ====================
 
-/// List all roles with their permissions
+/// List roles with their permissions, optionally searched and sorted
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/roles",
+    tag = "roles",
+    params(
+        RoleListQuery,
+        PaginationQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of roles with their permissions", body = Page<RoleWithPermissions>),
+        (status = 400, description = "The cursor is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown sort field or search term too long", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn list_roles(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<RoleWithPermissions>>, (StatusCode, Json<ErrorResponse>)> {
-    let roles: Vec<Role> = sqlx::query_as::<_, Role>("SELECT * FROM roles ORDER BY name")
+    OriginalUri(uri): OriginalUri,
+    Query(filter): Query<RoleListQuery>,
+    Query(params): Query<PaginationQuery>,
+) -> Result<Paginated<RoleWithPermissions>, AppError> {
+    validation::validate(&filter)?;
+    let sort = Sort::parse(filter.sort.as_deref(), ROLE_SORT_FIELDS)?;
+    let limit = params.limit();
+    let after = params.after_sorted(&sort)?;
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM roles");
+    push_role_filters(&mut query, &filter);
+    sort.push_after(&mut query, after);
+    sort.push_order_by(&mut query);
+    query.push(" LIMIT ").push_bind(limit + 1);
+
+    let roles: Vec<Role> = query
+        .build_query_as::<Role>()
         .fetch_all(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+        .await?;
+    let (roles, next_cursor) = pagination::split_page(roles, limit, |role| {
+        let value = match sort.field() {
+            "created_at" => role.created_at.to_rfc3339(),
+            _ => role.name.clone(),
+        };
+        sort.key(value, role.id)
+    });
+
+    let total = if params.include_total() {
+        let mut count = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*) FROM roles");
+        push_role_filters(&mut count, &filter);
+        Some(
+            count
+                .build_query_scalar::<i64>()
+                .fetch_one(&state.db_pool)
+                .await?,
+        )
+    } else {
+        None
+    };
 
-    let mut result = Vec::new();
-    for role in roles {
-        let permissions: Vec<Permission> =
-            sqlx::query_as::<_, Permission>("SELECT * FROM permissions WHERE role_id = $1")
-                .bind(role.id)
-                .fetch_all(&state.db_pool)
-                .await
-                .map_err(|e| {
-                    tracing::error!("Database error: {}", e);
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(ErrorResponse {
-                            error: "Database error".to_string(),
-                        }),
-                    )
-                })?;
+    let role_ids: Vec<Uuid> = roles.iter().map(|role| role.id).collect();
+    let mut permissions = load_permissions(&state, &role_ids).await?;
+    let items = roles
+        .into_iter()
+        .map(|role| RoleWithPermissions {
+            permissions: permissions.remove(&role.id).unwrap_or_default(),
+            role,
+        })
+        .collect();
 
-        result.push(RoleWithPermissions { role, permissions });
+    Ok(Paginated::new(
+        Page {
+            items,
+            next_cursor,
+            total,
+        },
+        uri,
+    ))
+}
+
+/// Permissions of every role in `role_ids`, in one query
+async fn load_permissions(
+    state: &AppState,
+    role_ids: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<Permission>>, AppError> {
+    let rows: Vec<Permission> = sqlx::query_as::<_, Permission>(
+        "SELECT * FROM permissions WHERE role_id = ANY($1) ORDER BY page",
+    )
+    .bind(role_ids)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let mut by_role: HashMap<Uuid, Vec<Permission>> = HashMap::new();
+    for permission in rows {
+        by_role
+            .entry(permission.role_id)
+            .or_default()
+            .push(permission);
     }
+    Ok(by_role)
+}
 
-    Ok(Json(result))
+/// Append the `WHERE` clause selecting the roles matching `filter`
+fn push_role_filters(query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, filter: &RoleListQuery) {
+    query.push(" WHERE TRUE");
+
+    if let Some(term) = search_term(&filter.q) {
+        query
+            .push(" AND name ILIKE ")
+            .push_bind(pagination::contains_pattern(term));
+    }
 }
 
 /// Create a new role
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/roles",
+    tag = "roles",
+    request_body = CreateRoleRequest,
+    responses(
+        (status = 200, description = "The created role", body = Role),
+        (status = 422, description = "The request body failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A role with this name already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn create_role(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<CreateRoleRequest>,
-) -> Result<Json<Role>, (StatusCode, Json<ErrorResponse>)> {
+    audit_ctx: AuditContext,
+    ValidatedJson(payload): ValidatedJson<CreateRoleRequest>,
+) -> Result<Json<Role>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
     let role: Role = sqlx::query_as::<_, Role>(
         "INSERT INTO roles (name, description, is_admin) VALUES ($1, $2, $3) RETURNING *",
     )
     .bind(&payload.name)
     .bind(&payload.description)
     .bind(payload.is_admin)
-    .fetch_one(&state.db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to create role: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to create role".to_string(),
-            }),
-        )
-    })?;
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        &audit_ctx,
+        AuditRecord {
+            action: actions::ROLE_CREATE,
+            target_type: targets::ROLE,
+            target_id: Some(role.id),
+            organization_id: role.organization_id,
+            before: None,
+            after: audit::snapshot(&role),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
 
     Ok(Json(role))
 }
 
 /// Get a specific role with permissions
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 200, description = "The role with its permissions", body = RoleWithPermissions),
+        (status = 404, description = "Role not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn get_role(
     State(state): State<Arc<AppState>>,
     Path(role_id): Path<Uuid>,
-) -> Result<Json<RoleWithPermissions>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<RoleWithPermissions>, AppError> {
     let role: Option<Role> = sqlx::query_as::<_, Role>("SELECT * FROM roles WHERE id = $1")
         .bind(role_id)
         .fetch_optional(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+        .await?;
 
-    let role = role.ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Role not found".to_string(),
-            }),
-        )
-    })?;
+    let role = role.ok_or_else(role_not_found)?;
 
     let permissions: Vec<Permission> =
         sqlx::query_as::<_, Permission>("SELECT * FROM permissions WHERE role_id = $1")
             .bind(role_id)
             .fetch_all(&state.db_pool)
-            .await
-            .map_err(|e| {
-                tracing::error!("Database error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: "Database error".to_string(),
-                    }),
-                )
-            })?;
+            .await?;
 
     Ok(Json(RoleWithPermissions { role, permissions }))
 }
 
 /// Update a role
+#[utoipa::path(
+    patch,
+    path = "/api/v1/admin/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    request_body = UpdateRoleRequest,
+    responses(
+        (status = 200, description = "The updated role", body = Role),
+        (status = 400, description = "No fields to update", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Role not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The request body failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn update_role(
     State(state): State<Arc<AppState>>,
     Path(role_id): Path<Uuid>,
-    Json(payload): Json<UpdateRoleRequest>,
-) -> Result<Json<Role>, (StatusCode, Json<ErrorResponse>)> {
+    audit_ctx: AuditContext,
+    ValidatedJson(payload): ValidatedJson<UpdateRoleRequest>,
+) -> Result<Json<Role>, AppError> {
     // Validate that at least one field is provided
     if payload.name.is_none() && payload.description.is_none() && payload.is_admin.is_none() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "No fields to update".to_string(),
-            }),
+        return Err(AppError::bad_request(
+            "no_fields_to_update",
+            "No fields to update",
         ));
     }
 
+    let mut tx = state.db_pool.begin().await?;
+
     // Get current role to use as defaults for unspecified fields
-    let current_role: Option<Role> = sqlx::query_as::<_, Role>("SELECT * FROM roles WHERE id = $1")
-        .bind(role_id)
-        .fetch_optional(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+    let current_role: Option<Role> =
+        sqlx::query_as::<_, Role>("SELECT * FROM roles WHERE id = $1 FOR UPDATE")
+            .bind(role_id)
+            .fetch_optional(&mut *tx)
+            .await?;
 
-    let current_role = current_role.ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Role not found".to_string(),
-            }),
-        )
-    })?;
+    let current_role = current_role.ok_or_else(role_not_found)?;
 
     // Use provided values or fall back to current values
-    let name = payload.name.unwrap_or(current_role.name);
-    let description = payload.description.or(current_role.description);
+    let name = payload.name.unwrap_or_else(|| current_role.name.clone());
+    let description = payload
+        .description
+        .or_else(|| current_role.description.clone());
     let is_admin = payload.is_admin.unwrap_or(current_role.is_admin);
 
     // Update with all fields
@@ -180,48 +323,74 @@ pub async fn update_role(
     .bind(name)
     .bind(description)
     .bind(is_admin)
-    .fetch_one(&state.db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to update role: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to update role".to_string(),
-            }),
-        )
-    })?;
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        &audit_ctx,
+        AuditRecord {
+            action: actions::ROLE_UPDATE,
+            target_type: targets::ROLE,
+            target_id: Some(role.id),
+            organization_id: role.organization_id,
+            before: audit::snapshot(&current_role),
+            after: audit::snapshot(&role),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
 
     Ok(Json(role))
 }
 
 /// Delete a role
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/roles/{role_id}",
+    tag = "roles",
+    params(
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 204, description = "The role was deleted"),
+        (status = 404, description = "Role not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn delete_role(
     State(state): State<Arc<AppState>>,
     Path(role_id): Path<Uuid>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let result = sqlx::query("DELETE FROM roles WHERE id = $1")
-        .bind(role_id)
-        .execute(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to delete role: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to delete role".to_string(),
-                }),
-            )
-        })?;
+    audit_ctx: AuditContext,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.db_pool.begin().await?;
 
-    if result.rows_affected() == 0 {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Role not found".to_string(),
-            }),
-        ));
-    }
+    let deleted: Option<Role> =
+        sqlx::query_as::<_, Role>("DELETE FROM roles WHERE id = $1 RETURNING *")
+            .bind(role_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    let deleted = deleted.ok_or_else(role_not_found)?;
+
+    audit::record(
+        &mut tx,
+        &audit_ctx,
+        AuditRecord {
+            action: actions::ROLE_DELETE,
+            target_type: targets::ROLE,
+            target_id: Some(deleted.id),
+            organization_id: deleted.organization_id,
+            before: audit::snapshot(&deleted),
+            after: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -230,12 +399,73 @@ pub async fn delete_role(
 pub async fn set_role_permission(
     State(state): State<Arc<AppState>>,
     Path(role_id): Path<Uuid>,
-    Json(payload): Json<SetPermissionRequest>,
-) -> Result<Json<Permission>, (StatusCode, Json<ErrorResponse>)> {
+    audit_ctx: AuditContext,
+    ValidatedJson(payload): ValidatedJson<SetPermissionRequest>,
+) -> Result<Json<Permission>, AppError> {
+    let permission = upsert_permission(&state, role_id, &audit_ctx, &payload).await?;
+    Ok(Json(permission))
+}
+
+/// Create or replace the permission of a role on the page named in the path
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/roles/{role_id}/permissions/{page}",
+    tag = "roles",
+    params(
+        ("role_id" = Uuid, Path, description = "Role ID"),
+        ("page" = String, Path, description = "Page identifier"),
+    ),
+    request_body = PermissionFlags,
+    responses(
+        (status = 200, description = "The stored permission", body = Permission),
+        (status = 422, description = "The request body failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn put_role_permission(
+    State(state): State<Arc<AppState>>,
+    Path((role_id, page)): Path<(Uuid, String)>,
+    audit_ctx: AuditContext,
+    ValidatedJson(flags): ValidatedJson<PermissionFlags>,
+) -> Result<Json<Permission>, AppError> {
+    let payload = flags.for_page(page);
+    validation::validate(&payload)?;
+
+    let permission = upsert_permission(&state, role_id, &audit_ctx, &payload).await?;
+    Ok(Json(permission))
+}
+
+async fn upsert_permission(
+    state: &AppState,
+    role_id: Uuid,
+    audit_ctx: &AuditContext,
+    payload: &SetPermissionRequest,
+) -> Result<Permission, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    // Rows carry their role's organization, as in `replace_role_permissions`,
+    // so both paths hit the same conflict key
+    let organization_id: Option<Uuid> =
+        sqlx::query_scalar::<_, Option<Uuid>>("SELECT organization_id FROM roles WHERE id = $1")
+            .bind(role_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(role_not_found)?;
+
+    let before: Option<Permission> = sqlx::query_as::<_, Permission>(
+        "SELECT * FROM permissions WHERE role_id = $1 AND page = $2 FOR UPDATE",
+    )
+    .bind(role_id)
+    .bind(&payload.page)
+    .fetch_optional(&mut *tx)
+    .await?;
+
     let permission: Permission = sqlx::query_as::<_, Permission>(
-        "INSERT INTO permissions (role_id, page, can_view, can_edit, can_view_own, can_edit_own, can_view_ours, can_edit_ours)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-         ON CONFLICT (role_id, page) 
+        "INSERT INTO permissions (role_id, page, can_view, can_edit, can_view_own, can_edit_own, can_view_ours, can_edit_ours, organization_id)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+         ON CONFLICT (role_id, page, COALESCE(organization_id, '00000000-0000-0000-0000-000000000000'::uuid))
          DO UPDATE SET 
             can_view = $3, 
             can_edit = $4, 
@@ -254,98 +484,525 @@ pub async fn set_role_permission(
     .bind(payload.can_edit_own)
     .bind(payload.can_view_ours)
     .bind(payload.can_edit_ours)
-    .fetch_one(&state.db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to set permission: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to set permission".to_string(),
-            }),
+    .bind(organization_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        audit_ctx,
+        AuditRecord {
+            action: actions::ROLE_PERMISSION_SET,
+            target_type: targets::ROLE,
+            target_id: Some(role_id),
+            organization_id,
+            before: before.as_ref().and_then(audit::snapshot),
+            after: audit::snapshot(&permission),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(permission)
+}
+
+/// Get the full permission matrix of a role together with its version token
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/roles/{role_id}/permissions",
+    tag = "roles",
+    params(
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 200, description = "The permission matrix and its version token", body = PermissionMatrixResponse),
+        (status = 404, description = "Role not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_role_permissions(
+    State(state): State<Arc<AppState>>,
+    Path(role_id): Path<Uuid>,
+) -> Result<Json<PermissionMatrixResponse>, AppError> {
+    let role_exists: Option<Uuid> = sqlx::query_scalar("SELECT id FROM roles WHERE id = $1")
+        .bind(role_id)
+        .fetch_optional(&state.db_pool)
+        .await?;
+
+    if role_exists.is_none() {
+        return Err(role_not_found());
+    }
+
+    let permissions: Vec<Permission> = sqlx::query_as::<_, Permission>(
+        "SELECT * FROM permissions WHERE role_id = $1 ORDER BY page",
+    )
+    .bind(role_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(PermissionMatrixResponse {
+        role_id,
+        version: permission_matrix_version(&permissions),
+        permissions,
+    }))
+}
+
+/// Replace the whole permission matrix of a role in a single transaction.
+///
+/// Pages missing from the payload are removed, new pages are inserted and
+/// pages whose flags changed are updated. When `version` is given it must
+/// match the current matrix, otherwise the request fails with 409 Conflict.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/roles/{role_id}/permissions",
+    tag = "roles",
+    params(
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    request_body = ReplacePermissionsRequest,
+    responses(
+        (status = 200, description = "The new matrix and what changed", body = ReplacePermissionsResponse),
+        (status = 404, description = "Role not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The matrix changed since `version` was read", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The request body failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn replace_role_permissions(
+    State(state): State<Arc<AppState>>,
+    Path(role_id): Path<Uuid>,
+    audit_ctx: AuditContext,
+    ValidatedJson(payload): ValidatedJson<ReplacePermissionsRequest>,
+) -> Result<Json<ReplacePermissionsResponse>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    // Lock the role so concurrent replacements of the same matrix are serialized
+    let role: Option<Role> =
+        sqlx::query_as::<_, Role>("SELECT * FROM roles WHERE id = $1 FOR UPDATE")
+            .bind(role_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    let role = role.ok_or_else(role_not_found)?;
+
+    let current: Vec<Permission> = sqlx::query_as::<_, Permission>(
+        "SELECT * FROM permissions WHERE role_id = $1 ORDER BY page FOR UPDATE",
+    )
+    .bind(role_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if let Some(expected) = &payload.version {
+        if *expected != permission_matrix_version(&current) {
+            return Err(AppError::conflict(
+                "version_conflict",
+                "Permission matrix was modified by another request",
+            ));
+        }
+    }
+
+    let plan = plan_permission_changes(&current, &payload.permissions);
+    let mut diff = PermissionDiff::default();
+
+    for entry in plan.insert {
+        let permission: Permission = sqlx::query_as::<_, Permission>(
+            "INSERT INTO permissions (role_id, page, can_view, can_edit, can_view_own,
+                 can_edit_own, can_view_ours, can_edit_ours, organization_id)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+             RETURNING *",
         )
-    })?;
+        .bind(role_id)
+        .bind(&entry.page)
+        .bind(entry.can_view)
+        .bind(entry.can_edit)
+        .bind(entry.can_view_own)
+        .bind(entry.can_edit_own)
+        .bind(entry.can_view_ours)
+        .bind(entry.can_edit_ours)
+        .bind(role.organization_id)
+        .fetch_one(&mut *tx)
+        .await?;
 
-    Ok(Json(permission))
+        diff.added.push(permission);
+    }
+
+    for (before, entry) in plan.update {
+        let after: Permission = sqlx::query_as::<_, Permission>(
+            "UPDATE permissions SET
+                can_view = $2,
+                can_edit = $3,
+                can_view_own = $4,
+                can_edit_own = $5,
+                can_view_ours = $6,
+                can_edit_ours = $7,
+                updated_at = NOW()
+             WHERE id = $1
+             RETURNING *",
+        )
+        .bind(before.id)
+        .bind(entry.can_view)
+        .bind(entry.can_edit)
+        .bind(entry.can_view_own)
+        .bind(entry.can_edit_own)
+        .bind(entry.can_view_ours)
+        .bind(entry.can_edit_ours)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        diff.updated.push(PermissionChange {
+            page: after.page.clone(),
+            before: before.clone(),
+            after,
+        });
+    }
+
+    if !plan.remove.is_empty() {
+        let ids: Vec<Uuid> = plan.remove.iter().map(|p| p.id).collect();
+        sqlx::query("DELETE FROM permissions WHERE id = ANY($1)")
+            .bind(&ids)
+            .execute(&mut *tx)
+            .await?;
+
+        diff.removed = plan.remove.into_iter().cloned().collect();
+    }
+
+    let permissions: Vec<Permission> = sqlx::query_as::<_, Permission>(
+        "SELECT * FROM permissions WHERE role_id = $1 ORDER BY page",
+    )
+    .bind(role_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        &audit_ctx,
+        AuditRecord {
+            action: actions::ROLE_PERMISSIONS_REPLACE,
+            target_type: targets::ROLE,
+            target_id: Some(role_id),
+            organization_id: role.organization_id,
+            before: audit::snapshot(&current),
+            after: audit::snapshot(&permissions),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ReplacePermissionsResponse {
+        role_id,
+        version: permission_matrix_version(&permissions),
+        permissions,
+        diff,
+    }))
+}
+
+/// Changes needed to turn the current permission matrix into the desired one
+struct PermissionPlan<'a> {
+    insert: Vec<&'a SetPermissionRequest>,
+    update: Vec<(&'a Permission, &'a SetPermissionRequest)>,
+    remove: Vec<&'a Permission>,
+}
+
+fn plan_permission_changes<'a>(
+    current: &'a [Permission],
+    desired: &'a [SetPermissionRequest],
+) -> PermissionPlan<'a> {
+    let by_page: HashMap<&str, &Permission> =
+        current.iter().map(|p| (p.page.as_str(), p)).collect();
+    let desired_pages: HashSet<&str> = desired.iter().map(|d| d.page.as_str()).collect();
+
+    let mut plan = PermissionPlan {
+        insert: Vec::new(),
+        update: Vec::new(),
+        remove: Vec::new(),
+    };
+
+    for entry in desired {
+        match by_page.get(entry.page.as_str()) {
+            None => plan.insert.push(entry),
+            Some(existing) if !same_flags(existing, entry) => plan.update.push((existing, entry)),
+            Some(_) => {}
+        }
+    }
+
+    plan.remove = current
+        .iter()
+        .filter(|p| !desired_pages.contains(p.page.as_str()))
+        .collect();
+
+    plan
+}
+
+fn same_flags(permission: &Permission, entry: &SetPermissionRequest) -> bool {
+    permission.can_view == entry.can_view
+        && permission.can_edit == entry.can_edit
+        && permission.can_view_own == entry.can_view_own
+        && permission.can_edit_own == entry.can_edit_own
+        && permission.can_view_ours == entry.can_view_ours
+        && permission.can_edit_ours == entry.can_edit_ours
+}
+
+/// Compute an opaque version token for a permission matrix.
+/// Any insert, update or delete of a row yields a different token.
+fn permission_matrix_version(permissions: &[Permission]) -> String {
+    let mut rows: Vec<&Permission> = permissions.iter().collect();
+    rows.sort_by(|a, b| a.page.cmp(&b.page));
+
+    let mut hasher = Sha256::new();
+    for p in rows {
+        hasher.update(p.id.as_bytes());
+        hasher.update(p.page.as_bytes());
+        hasher.update([
+            p.can_view as u8,
+            p.can_edit as u8,
+            p.can_view_own as u8,
+            p.can_edit_own as u8,
+            p.can_view_ours as u8,
+            p.can_edit_ours as u8,
+        ]);
+        hasher.update(p.updated_at.timestamp_micros().to_be_bytes());
+    }
+
+    hex::encode(&hasher.finalize()[..8])
+}
+
+fn role_not_found() -> AppError {
+    AppError::not_found("role_not_found", "Role not found")
 }
 
 // ==================== User Management ====================
 
-/// List all users with pagination
+/// List users with their roles, optionally searched, filtered and sorted
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users",
+    tag = "users",
+    params(
+        UserListQuery,
+        PaginationQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of users with their roles", body = Page<UserWithRoles>),
+        (status = 400, description = "The cursor is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown sort field or search term too long", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn list_users(
     State(state): State<Arc<AppState>>,
+    OriginalUri(uri): OriginalUri,
+    Query(filter): Query<UserListQuery>,
     Query(params): Query<PaginationQuery>,
-) -> Result<Json<Vec<UserWithRoles>>, (StatusCode, Json<ErrorResponse>)> {
-    let page = params.page.unwrap_or(1).max(1);
-    let per_page = params.per_page.unwrap_or(50).clamp(1, 100);
-    let offset = (page - 1) * per_page;
-
-    let users: Vec<User> =
-        sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY user_fullname LIMIT $1 OFFSET $2")
-            .bind(per_page as i64)
-            .bind(offset as i64)
-            .fetch_all(&state.db_pool)
-            .await
-            .map_err(|e| {
-                tracing::error!("Database error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: "Database error".to_string(),
-                    }),
-                )
-            })?;
-
-    let mut result = Vec::new();
-    for user in users {
-        let roles: Vec<Role> = sqlx::query_as::<_, Role>(
-            "SELECT r.* FROM roles r 
-             INNER JOIN user_roles ur ON r.id = ur.role_id 
-             WHERE ur.user_id = $1",
-        )
-        .bind(user.id)
+) -> Result<Paginated<UserWithRoles>, AppError> {
+    validation::validate(&filter)?;
+    let sort = Sort::parse(filter.sort.as_deref(), USER_SORT_FIELDS)?;
+    let limit = params.limit();
+    let after = params.after_sorted(&sort)?;
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM users");
+    push_user_filters(&mut query, &filter);
+    sort.push_after(&mut query, after);
+    sort.push_order_by(&mut query);
+    query.push(" LIMIT ").push_bind(limit + 1);
+
+    let users: Vec<User> = query
+        .build_query_as::<User>()
         .fetch_all(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+        .await?;
+    let (users, next_cursor) = pagination::split_page(users, limit, |user| {
+        let value = match sort.field() {
+            "email" => user.user_email.clone(),
+            "created_at" => user.created_at.to_rfc3339(),
+            _ => user.user_fullname.clone(),
+        };
+        sort.key(value, user.id)
+    });
+
+    let total = if params.include_total() {
+        let mut count = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*) FROM users");
+        push_user_filters(&mut count, &filter);
+        Some(
+            count
+                .build_query_scalar::<i64>()
+                .fetch_one(&state.db_pool)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    let user_ids: Vec<Uuid> = users.iter().map(|user| user.id).collect();
+    let mut roles = load_user_roles(&state, &user_ids).await?;
+    let items = users
+        .into_iter()
+        .map(|user| UserWithRoles {
+            roles: roles.remove(&user.id).unwrap_or_default(),
+            user,
+        })
+        .collect();
+
+    Ok(Paginated::new(
+        Page {
+            items,
+            next_cursor,
+            total,
+        },
+        uri,
+    ))
+}
+
+/// A role together with the user holding it
+#[derive(sqlx::FromRow)]
+struct UserRoleRow {
+    user_id: Uuid,
+    #[sqlx(flatten)]
+    role: Role,
+}
+
+/// Roles of every user in `user_ids`, in one query
+async fn load_user_roles(
+    state: &AppState,
+    user_ids: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<Role>>, AppError> {
+    let rows: Vec<UserRoleRow> = sqlx::query_as::<_, UserRoleRow>(
+        "SELECT ur.user_id, r.* FROM user_roles ur
+         INNER JOIN roles r ON r.id = ur.role_id
+         WHERE ur.user_id = ANY($1)
+         ORDER BY r.name",
+    )
+    .bind(user_ids)
+    .fetch_all(&state.db_pool)
+    .await?;
 
-        result.push(UserWithRoles { user, roles });
+    let mut by_user: HashMap<Uuid, Vec<Role>> = HashMap::new();
+    for row in rows {
+        by_user.entry(row.user_id).or_default().push(row.role);
+    }
+    Ok(by_user)
+}
+
+/// Append the `WHERE` clause selecting the users matching `filter`.
+/// The search uses the trigram indexes on name and email.
+fn push_user_filters(query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, filter: &UserListQuery) {
+    query.push(" WHERE TRUE");
+
+    if let Some(term) = search_term(&filter.q) {
+        let pattern = pagination::contains_pattern(term);
+        query
+            .push(" AND (user_fullname ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR user_email ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    if let Some(role_id) = filter.role_id {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM user_roles ur WHERE ur.user_id = users.id AND ur.role_id = ")
+            .push_bind(role_id)
+            .push(")");
     }
+    if let Some(organization_id) = filter.organization_id {
+        query
+            .push(" AND organization_id = ")
+            .push_bind(organization_id);
+    }
+    if let Some(group_id) = filter.group_id {
+        query.push(" AND group_id = ").push_bind(group_id);
+    }
+    if let Some(from) = filter.created_from {
+        query.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = filter.created_to {
+        query.push(" AND created_at < ").push_bind(to);
+    }
+}
 
-    Ok(Json(result))
+/// Trimmed search term, or `None` when blank
+fn search_term(q: &Option<String>) -> Option<&str> {
+    q.as_deref().map(str::trim).filter(|term| !term.is_empty())
 }
 
-/// Get roles for a specific user
+/// Get the roles of a specific user, by name
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users/{user_id}/roles",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+        PaginationQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of the roles assigned to the user", body = Page<Role>),
+        (status = 400, description = "The cursor is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "User not found, or in another organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn get_user_roles(
     State(state): State<Arc<AppState>>,
     Path(user_id): Path<Uuid>,
-) -> Result<Json<Vec<Role>>, (StatusCode, Json<ErrorResponse>)> {
-    let roles: Vec<Role> = sqlx::query_as::<_, Role>(
-        "SELECT r.* FROM roles r 
-         INNER JOIN user_roles ur ON r.id = ur.role_id 
-         WHERE ur.user_id = $1",
-    )
-    .bind(user_id)
-    .fetch_all(&state.db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Database error: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Database error".to_string(),
-            }),
+    claims: Claims,
+    OriginalUri(uri): OriginalUri,
+    Query(params): Query<PaginationQuery>,
+) -> Result<Paginated<Role>, AppError> {
+    load_managed_user(&state, &claims, user_id).await?;
+    let limit = params.limit();
+    let after: Option<(String, Uuid)> = params.after()?;
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(
+        "SELECT r.* FROM roles r
+         INNER JOIN user_roles ur ON r.id = ur.role_id
+         WHERE ur.user_id = ",
+    );
+    query.push_bind(user_id);
+    if let Some((name, id)) = after {
+        query
+            .push(" AND (r.name, r.id) > (")
+            .push_bind(name)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query
+        .push(" ORDER BY r.name, r.id LIMIT ")
+        .push_bind(limit + 1);
+
+    let roles: Vec<Role> = query
+        .build_query_as::<Role>()
+        .fetch_all(&state.db_pool)
+        .await?;
+    let (items, next_cursor) =
+        pagination::split_page(roles, limit, |role| (role.name.clone(), role.id));
+
+    let total = if params.include_total() {
+        Some(
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM user_roles WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&state.db_pool)
+                .await?,
         )
-    })?;
+    } else {
+        None
+    };
 
-    Ok(Json(roles))
+    Ok(Paginated::new(
+        Page {
+            items,
+            next_cursor,
+            total,
+        },
+        uri,
+    ))
 }
 
 /// Assign a role to a user
@@ -353,78 +1010,1242 @@ pub async fn assign_user_role(
     State(state): State<Arc<AppState>>,
     Path(user_id): Path<Uuid>,
     claims: Claims,
-    Json(payload): Json<AssignRoleRequest>,
-) -> Result<Json<UserRole>, (StatusCode, Json<ErrorResponse>)> {
+    audit_ctx: AuditContext,
+    ValidatedJson(payload): ValidatedJson<AssignRoleRequest>,
+) -> Result<Json<UserRole>, AppError> {
+    let user_role = insert_user_role(&state, user_id, payload.role_id, &claims, &audit_ctx)
+        .await?
+        .ok_or_else(|| {
+            AppError::conflict(
+                "role_already_assigned",
+                "The role is already assigned to this user",
+            )
+        })?;
+
+    Ok(Json(user_role))
+}
+
+/// Assign a role to a user; assigning a role the user already has is a no-op
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{user_id}/roles/{role_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 200, description = "The role assignment", body = UserRole),
+        (status = 422, description = "The user or role does not exist", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn put_user_role(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, role_id)): Path<(Uuid, Uuid)>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+) -> Result<Json<UserRole>, AppError> {
+    if let Some(user_role) = insert_user_role(&state, user_id, role_id, &claims, &audit_ctx).await?
+    {
+        return Ok(Json(user_role));
+    }
+
+    let existing: UserRole = sqlx::query_as::<_, UserRole>(
+        "SELECT * FROM user_roles WHERE user_id = $1 AND role_id = $2",
+    )
+    .bind(user_id)
+    .bind(role_id)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    Ok(Json(existing))
+}
+
+/// Insert a user role, returning `None` when the assignment already exists
+async fn insert_user_role(
+    state: &AppState,
+    user_id: Uuid,
+    role_id: Uuid,
+    claims: &Claims,
+    audit_ctx: &AuditContext,
+) -> Result<Option<UserRole>, AppError> {
     // Get current user's ID
     let admin_user: Option<User> = sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1")
         .bind(&claims.sub)
         .fetch_optional(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+        .await?;
 
     let assigned_by_id = admin_user.map(|u| u.id);
 
-    let user_role: UserRole = sqlx::query_as::<_, UserRole>(
+    let mut tx = state.db_pool.begin().await?;
+
+    let user_role: Option<UserRole> = sqlx::query_as::<_, UserRole>(
         "INSERT INTO user_roles (user_id, role_id, assigned_by) 
          VALUES ($1, $2, $3) 
          ON CONFLICT (user_id, role_id) DO NOTHING
          RETURNING *",
     )
     .bind(user_id)
-    .bind(payload.role_id)
+    .bind(role_id)
     .bind(assigned_by_id)
-    .fetch_one(&state.db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to assign role: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to assign role".to_string(),
-            }),
-        )
-    })?;
+    .fetch_optional(&mut *tx)
+    .await?;
 
-    Ok(Json(user_role))
+    let Some(user_role) = user_role else {
+        return Ok(None);
+    };
+
+    let organization_id = user_organization(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        audit_ctx,
+        AuditRecord {
+            action: actions::USER_ROLE_ASSIGN,
+            target_type: targets::USER,
+            target_id: Some(user_id),
+            organization_id,
+            before: None,
+            after: audit::snapshot(&user_role),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(user_role))
+}
+
+/// Organization of a user, for audit events about them
+async fn user_organization(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar::<_, Option<Uuid>>("SELECT organization_id FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map(Option::flatten)
 }
 
 /// Remove a role from a user
 pub async fn remove_user_role(
     State(state): State<Arc<AppState>>,
     Path(user_id): Path<Uuid>,
-    Json(payload): Json<AssignRoleRequest>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let result = sqlx::query("DELETE FROM user_roles WHERE user_id = $1 AND role_id = $2")
+    audit_ctx: AuditContext,
+    ValidatedJson(payload): ValidatedJson<AssignRoleRequest>,
+) -> Result<StatusCode, AppError> {
+    unassign_user_role(&state, user_id, payload.role_id, &audit_ctx).await
+}
+
+/// Remove the role named in the path from a user
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/users/{user_id}/roles/{role_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+        ("role_id" = Uuid, Path, description = "Role ID"),
+    ),
+    responses(
+        (status = 204, description = "The role was removed from the user"),
+        (status = 404, description = "The user does not have this role", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_user_role(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, role_id)): Path<(Uuid, Uuid)>,
+    audit_ctx: AuditContext,
+) -> Result<StatusCode, AppError> {
+    unassign_user_role(&state, user_id, role_id, &audit_ctx).await
+}
+
+async fn unassign_user_role(
+    state: &AppState,
+    user_id: Uuid,
+    role_id: Uuid,
+    audit_ctx: &AuditContext,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let removed: Option<UserRole> = sqlx::query_as::<_, UserRole>(
+        "DELETE FROM user_roles WHERE user_id = $1 AND role_id = $2 RETURNING *",
+    )
+    .bind(user_id)
+    .bind(role_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let removed =
+        removed.ok_or_else(|| AppError::not_found("user_role_not_found", "User role not found"))?;
+
+    let organization_id = user_organization(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        audit_ctx,
+        AuditRecord {
+            action: actions::USER_ROLE_REMOVE,
+            target_type: targets::USER,
+            target_id: Some(user_id),
+            organization_id,
+            before: audit::snapshot(&removed),
+            after: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lifecycle state of a user as recorded in the audit log. Personal data is
+/// left out so anonymization is not undone by the append-only history.
+#[derive(Serialize)]
+struct UserStatusSnapshot {
+    id: Uuid,
+    sub: String,
+    deactivated_at: Option<DateTime<Utc>>,
+    anonymized_at: Option<DateTime<Utc>>,
+}
+
+impl From<&User> for UserStatusSnapshot {
+    fn from(user: &User) -> Self {
+        UserStatusSnapshot {
+            id: user.id,
+            sub: user.sub.clone(),
+            deactivated_at: user.deactivated_at,
+            anonymized_at: user.anonymized_at,
+        }
+    }
+}
+
+/// Load a user for a lifecycle change, locking the row for the transaction
+async fn lock_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+) -> Result<User, AppError> {
+    sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1 FOR UPDATE")
         .bind(user_id)
-        .bind(payload.role_id)
-        .execute(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to remove role: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to remove role".to_string(),
-                }),
-            )
-        })?;
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(user_not_found)
+}
+
+fn user_not_found() -> AppError {
+    AppError::not_found("user_not_found", "User not found")
+}
+
+/// Admins that belong to an organization can only manage its users. Users
+/// of other organizations are reported as not found.
+async fn managed_user(state: &AppState, claims: &Claims, user: &User) -> Result<(), AppError> {
+    let managed = match user.organization_id {
+        Some(organization_id) => managed_organization(state, claims, organization_id).await,
+        None => match caller_organization_id(state, claims).await {
+            Ok(Some(_)) => Err(user_not_found()),
+            other => other.map(|_| ()),
+        },
+    };
+    managed.map_err(|e| {
+        if e.code == "organization_not_found" {
+            user_not_found()
+        } else {
+            e
+        }
+    })
+}
+
+/// Load a user the calling admin may manage, see `managed_user`
+async fn load_managed_user(
+    state: &AppState,
+    claims: &Claims,
+    user_id: Uuid,
+) -> Result<User, AppError> {
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or_else(user_not_found)?;
+    managed_user(state, claims, &user).await?;
+    Ok(user)
+}
+
+/// Administrators must not lock themselves out
+fn ensure_not_self(user: &User, claims: &Claims) -> Result<(), AppError> {
+    if user.sub == claims.sub {
+        return Err(AppError::conflict(
+            "cannot_modify_self",
+            "Administrators cannot deactivate, anonymize or delete their own account",
+        ));
+    }
+    Ok(())
+}
+
+/// Record a lifecycle change of `before` into `after` in the audit log
+async fn record_user_status(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    audit_ctx: &AuditContext,
+    action: &str,
+    before: &User,
+    after: Option<&User>,
+) -> Result<(), AppError> {
+    audit::record(
+        tx,
+        audit_ctx,
+        AuditRecord {
+            action,
+            target_type: targets::USER,
+            target_id: Some(before.id),
+            organization_id: before.organization_id,
+            before: audit::snapshot(&UserStatusSnapshot::from(before)),
+            after: after.and_then(|user| audit::snapshot(&UserStatusSnapshot::from(user))),
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Names of the fields an update changed. Values are left out of the audit
+/// log for the same reason as in `UserStatusSnapshot`.
+#[derive(Serialize)]
+struct UserUpdateSnapshot {
+    id: Uuid,
+    fields: Vec<&'static str>,
+}
 
-    if result.rows_affected() == 0 {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "User role not found".to_string(),
+/// Apply a profile or admin update to a locked user row. Properties are
+/// checked against the schema of the user's organization.
+pub(crate) async fn update_user_fields(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    audit_ctx: &AuditContext,
+    user: User,
+    changes: &UpdateUserRequest,
+) -> Result<User, AppError> {
+    if user.anonymized_at.is_some() {
+        return Err(AppError::conflict(
+            "user_anonymized",
+            "Anonymized users cannot be updated",
+        ));
+    }
+
+    let fields: Vec<&'static str> = [
+        ("user_fullname", changes.user_fullname.is_some()),
+        ("user_email", changes.user_email.is_some()),
+        ("properties", changes.properties.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect();
+    if fields.is_empty() {
+        return Ok(user);
+    }
+
+    if let (Some(properties), Some(organization_id)) = (&changes.properties, user.organization_id) {
+        let schema: Option<JsonValue> =
+            sqlx::query_scalar("SELECT properties_schema FROM organizations WHERE id = $1")
+                .bind(organization_id)
+                .fetch_one(&mut **tx)
+                .await?;
+        if let Some(schema) = schema {
+            validation::check_properties(&schema, properties)?;
+        }
+    }
+
+    let updated: User = sqlx::query_as::<_, User>(
+        "UPDATE users SET
+            user_fullname = COALESCE($2, user_fullname),
+            user_email = COALESCE($3, user_email),
+            properties = COALESCE($4, properties)
+         WHERE id = $1
+         RETURNING *",
+    )
+    .bind(user.id)
+    .bind(changes.user_fullname.as_deref().map(str::trim))
+    .bind(changes.user_email.as_deref().map(str::trim))
+    .bind(&changes.properties)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    audit::record(
+        tx,
+        audit_ctx,
+        AuditRecord {
+            action: actions::USER_UPDATE,
+            target_type: targets::USER,
+            target_id: Some(user.id),
+            organization_id: user.organization_id,
+            before: None,
+            after: audit::snapshot(&UserUpdateSnapshot {
+                id: user.id,
+                fields,
             }),
+        },
+    )
+    .await?;
+
+    Ok(updated)
+}
+
+/// Update a user's name, email or properties
+#[utoipa::path(
+    patch,
+    path = "/api/v1/admin/users/{user_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "The updated user", body = User),
+        (status = 404, description = "User not found, or in another organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The user has been anonymized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid fields, or properties that violate the organization's schema", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+    ValidatedJson(request): ValidatedJson<UpdateUserRequest>,
+) -> Result<Json<User>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user = lock_user(&mut tx, user_id).await?;
+    managed_user(&state, &claims, &user).await?;
+    let updated = update_user_fields(&mut tx, &audit_ctx, user, &request).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(updated))
+}
+
+/// Deactivate a user. Their tokens are rejected until they are reactivated.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/users/{user_id}/deactivate",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 200, description = "The deactivated user; unchanged if already deactivated", body = User),
+        (status = 404, description = "User not found, or in another organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Administrators cannot deactivate themselves", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn deactivate_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+) -> Result<Json<User>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user = lock_user(&mut tx, user_id).await?;
+    managed_user(&state, &claims, &user).await?;
+    ensure_not_self(&user, &claims)?;
+    if user.deactivated_at.is_some() {
+        return Ok(Json(user));
+    }
+
+    let updated: User = sqlx::query_as::<_, User>(
+        "UPDATE users SET deactivated_at = NOW(), updated_at = NOW() WHERE id = $1 RETURNING *",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_user_status(
+        &mut tx,
+        &audit_ctx,
+        actions::USER_DEACTIVATE,
+        &user,
+        Some(&updated),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(updated))
+}
+
+/// Reactivate a deactivated user. Anonymized users cannot be reactivated.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/users/{user_id}/reactivate",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 200, description = "The reactivated user; unchanged if already active", body = User),
+        (status = 404, description = "User not found, or in another organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The user has been anonymized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn reactivate_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+) -> Result<Json<User>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user = lock_user(&mut tx, user_id).await?;
+    managed_user(&state, &claims, &user).await?;
+    if user.anonymized_at.is_some() {
+        return Err(AppError::conflict(
+            "user_anonymized",
+            "Anonymized users cannot be reactivated",
         ));
     }
+    if user.deactivated_at.is_none() {
+        return Ok(Json(user));
+    }
+
+    let updated: User = sqlx::query_as::<_, User>(
+        "UPDATE users SET deactivated_at = NULL, updated_at = NOW() WHERE id = $1 RETURNING *",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    record_user_status(
+        &mut tx,
+        &audit_ctx,
+        actions::USER_REACTIVATE,
+        &user,
+        Some(&updated),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(updated))
+}
+
+/// Scrub a user's email, name, properties and preferences and deactivate the account.
+/// The row is kept so role assignments made by the user and the audit trail
+/// still resolve. Irreversible.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/users/{user_id}/anonymize",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 200, description = "The anonymized user; unchanged if already anonymized", body = User),
+        (status = 404, description = "User not found, or in another organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Administrators cannot anonymize themselves", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn anonymize_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+) -> Result<Json<User>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user = lock_user(&mut tx, user_id).await?;
+    managed_user(&state, &claims, &user).await?;
+    ensure_not_self(&user, &claims)?;
+    if user.anonymized_at.is_some() {
+        return Ok(Json(user));
+    }
+
+    // `.invalid` is reserved (RFC 2606), so the placeholder can never receive mail
+    let updated: User = sqlx::query_as::<_, User>(
+        "UPDATE users SET
+            user_email = 'anonymized-' || id || '@example.invalid',
+            user_fullname = 'Anonymized user',
+            properties = '{}',
+            anonymized_at = NOW(),
+            deactivated_at = COALESCE(deactivated_at, NOW()),
+            updated_at = NOW()
+         WHERE id = $1
+         RETURNING *",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM user_preferences WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    record_user_status(
+        &mut tx,
+        &audit_ctx,
+        actions::USER_ANONYMIZE,
+        &user,
+        Some(&updated),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(updated))
+}
+
+/// Permanently delete a user and their role assignments. Roles they assigned
+/// to others keep the assignment with `assigned_by` cleared; audit events
+/// keep the actor by value. The `sub` is kept in `deleted_users`, so the
+/// user's tokens are refused and the account cannot be onboarded again.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/users/{user_id}",
+    tag = "users",
+    params(
+        ("user_id" = Uuid, Path, description = "User ID"),
+    ),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 404, description = "User not found, or in another organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Administrators cannot delete themselves", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user = lock_user(&mut tx, user_id).await?;
+    managed_user(&state, &claims, &user).await?;
+    ensure_not_self(&user, &claims)?;
+
+    // Tokens issued before the deletion may still be unexpired
+    sqlx::query("INSERT INTO deleted_users (sub) VALUES ($1) ON CONFLICT (sub) DO NOTHING")
+        .bind(&user.sub)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    record_user_status(&mut tx, &audit_ctx, actions::USER_DELETE, &user, None).await?;
+
+    tx.commit().await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+// ==================== Organizations ====================
+
+/// Resolve an organization the calling admin may manage. Admins that belong
+/// to an organization can only manage their own.
+async fn managed_organization(
+    state: &AppState,
+    claims: &Claims,
+    organization_id: Uuid,
+) -> Result<(), AppError> {
+    let not_found = || AppError::not_found("organization_not_found", "Organization not found");
+
+    if let Some(own) = caller_organization_id(state, claims).await? {
+        if own != organization_id {
+            return Err(not_found());
+        }
+    }
+    let exists: Option<Uuid> = sqlx::query_scalar("SELECT id FROM organizations WHERE id = $1")
+        .bind(organization_id)
+        .fetch_optional(&state.db_pool)
+        .await?;
+    exists.map(|_| ()).ok_or_else(not_found)
+}
+
+/// Get the JSON Schema that members' `properties` must satisfy
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/organizations/{organization_id}/properties-schema",
+    tag = "organizations",
+    params(
+        ("organization_id" = Uuid, Path, description = "Organization ID"),
+    ),
+    responses(
+        (status = 200, description = "The properties schema; `null` if any object is allowed", body = PropertiesSchemaResponse),
+        (status = 404, description = "Organization not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_properties_schema(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<Uuid>,
+    claims: Claims,
+) -> Result<Json<PropertiesSchemaResponse>, AppError> {
+    managed_organization(&state, &claims, organization_id).await?;
+
+    let properties_schema: Option<JsonValue> =
+        sqlx::query_scalar("SELECT properties_schema FROM organizations WHERE id = $1")
+            .bind(organization_id)
+            .fetch_one(&state.db_pool)
+            .await?;
+
+    Ok(Json(PropertiesSchemaResponse {
+        organization_id,
+        properties_schema,
+    }))
+}
+
+/// Set the JSON Schema that members' `properties` must satisfy. Existing
+/// properties are checked the next time they are written.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/organizations/{organization_id}/properties-schema",
+    tag = "organizations",
+    params(
+        ("organization_id" = Uuid, Path, description = "Organization ID"),
+    ),
+    request_body = PropertiesSchemaRequest,
+    responses(
+        (status = 200, description = "The stored properties schema", body = PropertiesSchemaResponse),
+        (status = 404, description = "Organization not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Not a valid JSON Schema", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn put_properties_schema(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+    ValidatedJson(request): ValidatedJson<PropertiesSchemaRequest>,
+) -> Result<Json<PropertiesSchemaResponse>, AppError> {
+    managed_organization(&state, &claims, organization_id).await?;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let before: Option<JsonValue> =
+        sqlx::query_scalar("SELECT properties_schema FROM organizations WHERE id = $1 FOR UPDATE")
+            .bind(organization_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    sqlx::query("UPDATE organizations SET properties_schema = $2 WHERE id = $1")
+        .bind(organization_id)
+        .bind(&request.properties_schema)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        &audit_ctx,
+        AuditRecord {
+            action: actions::ORGANIZATION_PROPERTIES_SCHEMA_SET,
+            target_type: targets::ORGANIZATION,
+            target_id: Some(organization_id),
+            organization_id: Some(organization_id),
+            before,
+            after: request.properties_schema.clone(),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(PropertiesSchemaResponse {
+        organization_id,
+        properties_schema: request.properties_schema,
+    }))
+}
+
+/// Get the preference defaults members of an organization get
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/organizations/{organization_id}/default-preferences",
+    tag = "organizations",
+    params(
+        ("organization_id" = Uuid, Path, description = "Organization ID"),
+    ),
+    responses(
+        (status = 200, description = "The organization's overrides and the resulting defaults", body = DefaultPreferencesResponse),
+        (status = 404, description = "Organization not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_default_preferences(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<Uuid>,
+    claims: Claims,
+) -> Result<Json<DefaultPreferencesResponse>, AppError> {
+    managed_organization(&state, &claims, organization_id).await?;
+
+    let mut conn = state.db_pool.acquire().await?;
+    let overrides = preferences::organization_overrides(&mut conn, Some(organization_id)).await?;
+
+    Ok(Json(DefaultPreferencesResponse {
+        organization_id,
+        defaults: overrides.apply_to(Preferences::default()),
+        overrides,
+    }))
+}
+
+/// Replace the preference defaults of an organization. Keys left out fall
+/// back to the built-in defaults; members' own choices still win.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/organizations/{organization_id}/default-preferences",
+    tag = "organizations",
+    params(
+        ("organization_id" = Uuid, Path, description = "Organization ID"),
+    ),
+    request_body = PreferenceOverrides,
+    responses(
+        (status = 200, description = "The stored overrides and the resulting defaults", body = DefaultPreferencesResponse),
+        (status = 404, description = "Organization not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown keys or values", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn put_default_preferences(
+    State(state): State<Arc<AppState>>,
+    Path(organization_id): Path<Uuid>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+    ValidatedJson(overrides): ValidatedJson<PreferenceOverrides>,
+) -> Result<Json<DefaultPreferencesResponse>, AppError> {
+    managed_organization(&state, &claims, organization_id).await?;
+
+    let mut tx = state.db_pool.begin().await?;
+
+    let before: JsonValue = sqlx::query_scalar(
+        "SELECT default_preferences FROM organizations WHERE id = $1 FOR UPDATE",
+    )
+    .bind(organization_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE organizations SET default_preferences = $2 WHERE id = $1")
+        .bind(organization_id)
+        .bind(sqlx::types::Json(&overrides))
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        &audit_ctx,
+        AuditRecord {
+            action: actions::ORGANIZATION_DEFAULT_PREFERENCES_SET,
+            target_type: targets::ORGANIZATION,
+            target_id: Some(organization_id),
+            organization_id: Some(organization_id),
+            before: Some(before),
+            after: audit::snapshot(&overrides),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(DefaultPreferencesResponse {
+        organization_id,
+        defaults: overrides.apply_to(Preferences::default()),
+        overrides,
+    }))
+}
+
+// ==================== Audit Log ====================
+
+/// Query the audit log with optional filters, newest first.
+/// Admins that belong to an organization only see that organization's events.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit-events",
+    tag = "audit",
+    params(
+        AuditEventQuery,
+        PaginationQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of matching audit events, newest first", body = Page<AuditEvent>),
+        (status = 400, description = "The cursor is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "The token's organization does not exist", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_audit_events(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    OriginalUri(uri): OriginalUri,
+    Query(filter): Query<AuditEventQuery>,
+    Query(params): Query<PaginationQuery>,
+) -> Result<Paginated<AuditEvent>, AppError> {
+    let limit = params.limit();
+    let after: Option<(DateTime<Utc>, Uuid)> = params.after()?;
+
+    let organization_id = caller_organization_id(&state, &claims).await?;
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM audit_events");
+    push_audit_filters(&mut query, organization_id, &filter);
+    if let Some((created_at, id)) = after {
+        query
+            .push(" AND (created_at, id) < (")
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(limit + 1);
+
+    let events: Vec<AuditEvent> = query
+        .build_query_as::<AuditEvent>()
+        .fetch_all(&state.db_pool)
+        .await?;
+    let (items, next_cursor) = pagination::split_page(events, limit, |e| (e.created_at, e.id));
+
+    let total = if params.include_total() {
+        let mut count =
+            sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*) FROM audit_events");
+        push_audit_filters(&mut count, organization_id, &filter);
+        Some(
+            count
+                .build_query_scalar::<i64>()
+                .fetch_one(&state.db_pool)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    Ok(Paginated::new(
+        Page {
+            items,
+            next_cursor,
+            total,
+        },
+        uri,
+    ))
+}
+
+/// Append the `WHERE` clause selecting the audit events matching `filter`
+fn push_audit_filters(
+    query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    organization_id: Option<Uuid>,
+    filter: &AuditEventQuery,
+) {
+    query.push(" WHERE TRUE");
+
+    if let Some(organization_id) = organization_id {
+        query
+            .push(" AND organization_id = ")
+            .push_bind(organization_id);
+    }
+    if let Some(actor_id) = filter.actor_id {
+        query.push(" AND actor_id = ").push_bind(actor_id);
+    }
+    if let Some(actor_sub) = &filter.actor_sub {
+        query.push(" AND actor_sub = ").push_bind(actor_sub.clone());
+    }
+    if let Some(action) = &filter.action {
+        query.push(" AND action = ").push_bind(action.clone());
+    }
+    if let Some(target_type) = &filter.target_type {
+        query
+            .push(" AND target_type = ")
+            .push_bind(target_type.clone());
+    }
+    if let Some(target_id) = filter.target_id {
+        query.push(" AND target_id = ").push_bind(target_id);
+    }
+    if let Some(from) = filter.from {
+        query.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        query.push(" AND created_at < ").push_bind(to);
+    }
+}
+
+/// Walk an organization's audit chain and report the first broken link.
+/// Admins that belong to an organization can only verify that organization's chain;
+/// system admins pick the chain with `organization_id` (omitted = system-wide events).
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit-events/verify",
+    tag = "audit",
+    params(
+        AuditChainQuery,
+    ),
+    responses(
+        (status = 200, description = "Result of walking the hash chain", body = AuditChainVerification),
+        (status = 404, description = "The token's organization does not exist", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn verify_audit_chain(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Query(params): Query<AuditChainQuery>,
+) -> Result<Json<AuditChainVerification>, AppError> {
+    let organization_id = caller_organization_id(&state, &claims)
+        .await?
+        .or(params.organization_id);
+
+    let mut verifier = ChainVerifier::new();
+    let mut last_sequence = 0i64;
+
+    loop {
+        let batch: Vec<AuditEvent> = sqlx::query_as::<_, AuditEvent>(
+            "SELECT * FROM audit_events
+             WHERE COALESCE(organization_id, '00000000-0000-0000-0000-000000000000'::uuid)
+                 = COALESCE($1, '00000000-0000-0000-0000-000000000000'::uuid)
+               AND sequence > $2
+             ORDER BY sequence
+             LIMIT $3",
+        )
+        .bind(organization_id)
+        .bind(last_sequence)
+        .bind(AUDIT_VERIFY_BATCH_SIZE)
+        .fetch_all(&state.db_pool)
+        .await?;
+
+        let intact = batch.iter().all(|event| verifier.push(event));
+        match batch.last() {
+            Some(event) if intact && batch.len() as i64 == AUDIT_VERIFY_BATCH_SIZE => {
+                last_sequence = event.sequence;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(Json(verifier.finish(organization_id)))
+}
+
+// ==================== Legacy Collections ====================
+//
+// The unversioned paths answer with bare arrays and `page`/`per_page`, as
+// before cursor pagination, until their sunset.
+
+/// Unversioned `GET /admin/roles`: every role with its permissions
+pub async fn legacy_list_roles(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<RoleWithPermissions>>, AppError> {
+    let roles: Vec<Role> = sqlx::query_as::<_, Role>("SELECT * FROM roles ORDER BY name, id")
+        .fetch_all(&state.db_pool)
+        .await?;
+
+    let role_ids: Vec<Uuid> = roles.iter().map(|role| role.id).collect();
+    let mut permissions = load_permissions(&state, &role_ids).await?;
+    Ok(Json(
+        roles
+            .into_iter()
+            .map(|role| RoleWithPermissions {
+                permissions: permissions.remove(&role.id).unwrap_or_default(),
+                role,
+            })
+            .collect(),
+    ))
+}
+
+/// Unversioned `GET /admin/users`: one page of users with their roles, by name
+pub async fn legacy_list_users(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LegacyPageQuery>,
+) -> Result<Json<Vec<UserWithRoles>>, AppError> {
+    let (limit, offset) = params.limit_offset();
+
+    let users: Vec<User> = sqlx::query_as::<_, User>(
+        "SELECT * FROM users ORDER BY user_fullname, id LIMIT $1 OFFSET $2",
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let user_ids: Vec<Uuid> = users.iter().map(|user| user.id).collect();
+    let mut roles = load_user_roles(&state, &user_ids).await?;
+    Ok(Json(
+        users
+            .into_iter()
+            .map(|user| UserWithRoles {
+                roles: roles.remove(&user.id).unwrap_or_default(),
+                user,
+            })
+            .collect(),
+    ))
+}
+
+/// Unversioned `GET /admin/users/:user_id/roles`: every role of the user
+pub async fn legacy_get_user_roles(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    claims: Claims,
+) -> Result<Json<Vec<Role>>, AppError> {
+    load_managed_user(&state, &claims, user_id).await?;
+    let mut roles = load_user_roles(&state, &[user_id]).await?;
+    Ok(Json(roles.remove(&user_id).unwrap_or_default()))
+}
+
+/// Unversioned `GET /admin/audit-events`: one page of matching events, newest first
+pub async fn legacy_list_audit_events(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Query(filter): Query<AuditEventQuery>,
+    Query(params): Query<LegacyPageQuery>,
+) -> Result<Json<Vec<AuditEvent>>, AppError> {
+    let (limit, offset) = params.limit_offset();
+    let organization_id = caller_organization_id(&state, &claims).await?;
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM audit_events");
+    push_audit_filters(&mut query, organization_id, &filter);
+    query
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let events: Vec<AuditEvent> = query
+        .build_query_as::<AuditEvent>()
+        .fetch_all(&state.db_pool)
+        .await?;
+    Ok(Json(events))
+}
+
+/// Resolve the organization of the calling admin; `None` only for tokens
+/// without an organization claim. A claim naming no known organization is
+/// refused rather than read as a system admin.
+async fn caller_organization_id(
+    state: &AppState,
+    claims: &Claims,
+) -> Result<Option<Uuid>, AppError> {
+    let Some(org_name) = &claims.organization else {
+        return Ok(None);
+    };
+    let organization_id: Option<Uuid> =
+        sqlx::query_scalar("SELECT id FROM organizations WHERE name = $1")
+            .bind(org_name)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    organization_id
+        .map(Some)
+        .ok_or_else(|| AppError::not_found("organization_not_found", "Organization not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn permission(page: &str, can_view: bool, can_edit: bool) -> Permission {
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        Permission {
+            id: Uuid::new_v4(),
+            role_id: Uuid::nil(),
+            page: page.to_string(),
+            can_view,
+            can_edit,
+            can_view_own: false,
+            can_edit_own: false,
+            can_view_ours: false,
+            can_edit_ours: false,
+            organization_id: None,
+            created_at: timestamp,
+            updated_at: timestamp,
+        }
+    }
+
+    fn entry(page: &str, can_view: bool, can_edit: bool) -> SetPermissionRequest {
+        SetPermissionRequest {
+            page: page.to_string(),
+            can_view,
+            can_edit,
+            can_view_own: false,
+            can_edit_own: false,
+            can_view_ours: false,
+            can_edit_ours: false,
+        }
+    }
+
+    #[test]
+    fn test_plan_permission_changes() {
+        let current = vec![
+            permission("dashboard", true, false),
+            permission("users", true, true),
+            permission("support", true, false),
+        ];
+        let desired = vec![
+            entry("dashboard", true, false),
+            entry("users", true, false),
+            entry("roles", true, true),
+        ];
+
+        let plan = plan_permission_changes(&current, &desired);
+
+        assert_eq!(plan.insert.len(), 1);
+        assert_eq!(plan.insert[0].page, "roles");
+        assert_eq!(plan.update.len(), 1);
+        assert_eq!(plan.update[0].0.page, "users");
+        assert!(!plan.update[0].1.can_edit);
+        assert_eq!(plan.remove.len(), 1);
+        assert_eq!(plan.remove[0].page, "support");
+    }
+
+    #[test]
+    fn test_plan_permission_changes_unchanged() {
+        let current = vec![permission("dashboard", true, false)];
+        let desired = vec![entry("dashboard", true, false)];
+
+        let plan = plan_permission_changes(&current, &desired);
+
+        assert!(plan.insert.is_empty());
+        assert!(plan.update.is_empty());
+        assert!(plan.remove.is_empty());
+    }
+
+    #[test]
+    fn test_permission_matrix_version() {
+        let mut permissions = vec![
+            permission("dashboard", true, false),
+            permission("users", true, true),
+        ];
+        let version = permission_matrix_version(&permissions);
+
+        permissions.reverse();
+        assert_eq!(version, permission_matrix_version(&permissions));
+
+        permissions[0].can_edit = false;
+        assert_ne!(version, permission_matrix_version(&permissions));
+
+        permissions.pop();
+        assert_ne!(version, permission_matrix_version(&permissions));
+    }
+}
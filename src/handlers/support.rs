@@ -0,0 +1,511 @@
+use axum::{
+    body::Bytes,
+    extract::{rejection::BytesRejection, OriginalUri, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::error::{AppError, FieldError, ProblemDetails};
+use crate::models::{
+    AppState, AttachmentUploadQuery, Claims, CreateTicketCommentRequest, CreateTicketRequest, Page,
+    PaginationQuery, SupportTicket, TicketAttachment, TicketComment, TicketDetail, TicketListQuery,
+    TicketPriority, TicketStatus, UpdateTicketRequest,
+};
+use crate::pagination::{self, Paginated};
+use crate::support::{self, SupportCaller, DEFAULT_ATTACHMENT_CONTENT_TYPE};
+use crate::validation::{self, ValidatedJson};
+
+/// Columns of `support_ticket_attachments` other than the content
+const ATTACHMENT_COLUMNS: &str =
+    "id, ticket_id, uploaded_by, filename, content_type, size_bytes, sha256, created_at";
+
+/// List the tickets the caller can see, newest first. Support staff see
+/// every ticket of their organization, requesters their own.
+#[utoipa::path(
+    get,
+    path = "/api/v1/support/tickets",
+    tag = "support",
+    params(
+        TicketListQuery,
+        PaginationQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of tickets, newest first", body = Page<SupportTicket>),
+        (status = 400, description = "The cursor or a filter is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "The user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn list_tickets(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    OriginalUri(uri): OriginalUri,
+    Query(filter): Query<TicketListQuery>,
+    Query(params): Query<PaginationQuery>,
+) -> Result<Paginated<SupportTicket>, AppError> {
+    let limit = params.limit();
+    let after: Option<(chrono::DateTime<Utc>, Uuid)> = params.after()?;
+
+    let mut conn = state.db_pool.acquire().await?;
+    let caller = support::caller(&mut conn, &claims).await?;
+
+    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM support_tickets");
+    push_ticket_filters(&mut query, &caller, &filter);
+    if let Some((created_at, id)) = after {
+        query
+            .push(" AND (created_at, id) < (")
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(limit + 1);
+
+    let tickets: Vec<SupportTicket> = query
+        .build_query_as::<SupportTicket>()
+        .fetch_all(&mut *conn)
+        .await?;
+    let (items, next_cursor) = pagination::split_page(tickets, limit, |t| (t.created_at, t.id));
+
+    let total = if params.include_total() {
+        let mut count =
+            sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*) FROM support_tickets");
+        push_ticket_filters(&mut count, &caller, &filter);
+        Some(
+            count
+                .build_query_scalar::<i64>()
+                .fetch_one(&mut *conn)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    Ok(Paginated::new(
+        Page {
+            items,
+            next_cursor,
+            total,
+        },
+        uri,
+    ))
+}
+
+/// Append the `WHERE` clause selecting the visible tickets matching `filter`
+fn push_ticket_filters(
+    query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    caller: &SupportCaller,
+    filter: &TicketListQuery,
+) {
+    query.push(" WHERE TRUE");
+    support::push_visibility(query, caller);
+
+    if let Some(status) = filter.status {
+        query.push(" AND status = ").push_bind(status);
+    }
+    if let Some(priority) = filter.priority {
+        query.push(" AND priority = ").push_bind(priority);
+    }
+    if let Some(assignee_id) = filter.assignee_id {
+        query.push(" AND assignee_id = ").push_bind(assignee_id);
+    }
+}
+
+/// Open a ticket; the message becomes its first comment
+#[utoipa::path(
+    post,
+    path = "/api/v1/support/tickets",
+    tag = "support",
+    request_body = CreateTicketRequest,
+    responses(
+        (status = 200, description = "The created ticket", body = SupportTicket),
+        (status = 422, description = "The request body failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "The user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_ticket(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    ValidatedJson(request): ValidatedJson<CreateTicketRequest>,
+) -> Result<Json<SupportTicket>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+    let caller = support::caller(&mut tx, &claims).await?;
+
+    let ticket: SupportTicket = sqlx::query_as::<_, SupportTicket>(
+        "INSERT INTO support_tickets (organization_id, requester_id, subject, priority)
+         VALUES ($1, $2, $3, $4) RETURNING *",
+    )
+    .bind(caller.user.organization_id)
+    .bind(caller.user.id)
+    .bind(request.subject.trim())
+    .bind(request.priority.unwrap_or(TicketPriority::Normal))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO support_ticket_comments (ticket_id, author_id, body) VALUES ($1, $2, $3)",
+    )
+    .bind(ticket.id)
+    .bind(caller.user.id)
+    .bind(&request.message)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ticket))
+}
+
+/// Get a ticket with its comments and attachments
+#[utoipa::path(
+    get,
+    path = "/api/v1/support/tickets/{ticket_id}",
+    tag = "support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+    ),
+    responses(
+        (status = 200, description = "The ticket with its comments and attachments", body = TicketDetail),
+        (status = 404, description = "Ticket not found, or the user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_ticket(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Path(ticket_id): Path<Uuid>,
+) -> Result<Json<TicketDetail>, AppError> {
+    let mut conn = state.db_pool.acquire().await?;
+    let caller = support::caller(&mut conn, &claims).await?;
+    let ticket = support::visible_ticket(&mut conn, &caller, ticket_id, false).await?;
+
+    let comments: Vec<TicketComment> = sqlx::query_as::<_, TicketComment>(
+        "SELECT * FROM support_ticket_comments WHERE ticket_id = $1 ORDER BY created_at, id",
+    )
+    .bind(ticket.id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let attachments: Vec<TicketAttachment> = sqlx::query_as::<_, TicketAttachment>(&format!(
+        "SELECT {} FROM support_ticket_attachments WHERE ticket_id = $1 ORDER BY created_at, id",
+        ATTACHMENT_COLUMNS
+    ))
+    .bind(ticket.id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Json(TicketDetail {
+        ticket,
+        comments,
+        attachments,
+    }))
+}
+
+/// Change a ticket's status, priority or assignee
+#[utoipa::path(
+    patch,
+    path = "/api/v1/support/tickets/{ticket_id}",
+    tag = "support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+    ),
+    request_body = UpdateTicketRequest,
+    responses(
+        (status = 200, description = "The updated ticket", body = SupportTicket),
+        (status = 404, description = "Ticket not found, or the user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The workflow does not allow this status change", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The request body failed validation or the assignee is not support staff of the organization", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The change is reserved to support staff, the user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_ticket(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Path(ticket_id): Path<Uuid>,
+    ValidatedJson(request): ValidatedJson<UpdateTicketRequest>,
+) -> Result<Json<SupportTicket>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+    let caller = support::caller(&mut tx, &claims).await?;
+    let ticket = support::visible_ticket(&mut tx, &caller, ticket_id, true).await?;
+
+    if (request.priority.is_some() || request.assignee_id.is_some()) && !caller.is_staff() {
+        return Err(support::staff_required());
+    }
+    let status = request.status.unwrap_or(ticket.status);
+    support::check_status_change(caller.role, ticket.status, status)?;
+
+    if let Some(Some(assignee_id)) = request.assignee_id {
+        if !support::is_staff_member(&mut tx, assignee_id, ticket.organization_id).await? {
+            return Err(AppError::validation(vec![FieldError::new(
+                "assignee_id",
+                "not_support_staff",
+                "assignee_id is not an active support staff member of the ticket's organization",
+            )]));
+        }
+    }
+
+    let (resolved_at, closed_at) = if status == ticket.status {
+        (ticket.resolved_at, ticket.closed_at)
+    } else {
+        let now = Utc::now();
+        match status {
+            TicketStatus::Resolved => (Some(now), None),
+            // Keep when the ticket was resolved, if it was, before closing
+            TicketStatus::Closed => (ticket.resolved_at, Some(now)),
+            _ => (None, None),
+        }
+    };
+
+    let ticket: SupportTicket = sqlx::query_as::<_, SupportTicket>(
+        "UPDATE support_tickets
+         SET status = $2, priority = $3, assignee_id = $4, resolved_at = $5, closed_at = $6
+         WHERE id = $1 RETURNING *",
+    )
+    .bind(ticket.id)
+    .bind(status)
+    .bind(request.priority.unwrap_or(ticket.priority))
+    .bind(request.assignee_id.unwrap_or(ticket.assignee_id))
+    .bind(resolved_at)
+    .bind(closed_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ticket))
+}
+
+/// Comment on a ticket, optionally in reply to another comment. A reply from
+/// the requester to a ticket waiting on them puts it back in progress.
+#[utoipa::path(
+    post,
+    path = "/api/v1/support/tickets/{ticket_id}/comments",
+    tag = "support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+    ),
+    request_body = CreateTicketCommentRequest,
+    responses(
+        (status = 200, description = "The created comment", body = TicketComment),
+        (status = 404, description = "Ticket not found, or the user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The ticket is closed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The request body failed validation or the parent is not a comment of this ticket", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn create_comment(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Path(ticket_id): Path<Uuid>,
+    ValidatedJson(request): ValidatedJson<CreateTicketCommentRequest>,
+) -> Result<Json<TicketComment>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+    let caller = support::caller(&mut tx, &claims).await?;
+    let ticket = support::visible_ticket(&mut tx, &caller, ticket_id, true).await?;
+    ensure_open(&ticket)?;
+
+    if let Some(parent_id) = request.parent_id {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM support_ticket_comments WHERE id = $1 AND ticket_id = $2)",
+        )
+        .bind(parent_id)
+        .bind(ticket.id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !exists {
+            return Err(AppError::validation(vec![FieldError::new(
+                "parent_id",
+                "not_found",
+                "parent_id is not a comment of this ticket",
+            )]));
+        }
+    }
+
+    let comment: TicketComment = sqlx::query_as::<_, TicketComment>(
+        "INSERT INTO support_ticket_comments (ticket_id, parent_id, author_id, body)
+         VALUES ($1, $2, $3, $4) RETURNING *",
+    )
+    .bind(ticket.id)
+    .bind(request.parent_id)
+    .bind(caller.user.id)
+    .bind(&request.body)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let status = if !caller.is_staff() && ticket.status == TicketStatus::WaitingOnRequester {
+        TicketStatus::InProgress
+    } else {
+        ticket.status
+    };
+    // Also bumps updated_at, so recently discussed tickets stand out
+    sqlx::query("UPDATE support_tickets SET status = $2 WHERE id = $1")
+        .bind(ticket.id)
+        .bind(status)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(comment))
+}
+
+/// Attach a file to a ticket. The request body is the file content.
+#[utoipa::path(
+    post,
+    path = "/api/v1/support/tickets/{ticket_id}/attachments",
+    tag = "support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+        AttachmentUploadQuery,
+    ),
+    request_body(content = Vec<u8>, description = "File content, at most 5 MiB, sent with its own media type", content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "The stored attachment", body = TicketAttachment),
+        (status = 404, description = "Ticket not found, or the user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The ticket is closed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "The file is larger than 5 MiB", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The file is empty or the filename is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn upload_attachment(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Path(ticket_id): Path<Uuid>,
+    Query(upload): Query<AttachmentUploadQuery>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Json<TicketAttachment>, AppError> {
+    let content = body?;
+    validation::validate(&upload)?;
+    let filename = support::clean_filename(&upload.filename);
+    if filename.is_empty() {
+        return Err(AppError::validation(vec![FieldError::new(
+            "filename",
+            "blank",
+            "filename must name a file",
+        )]));
+    }
+    if content.is_empty() {
+        return Err(AppError::validation(vec![FieldError::new(
+            "body",
+            "empty",
+            "the file must not be empty",
+        )]));
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.contains('/') && value.len() <= 255)
+        .unwrap_or(DEFAULT_ATTACHMENT_CONTENT_TYPE);
+
+    let mut tx = state.db_pool.begin().await?;
+    let caller = support::caller(&mut tx, &claims).await?;
+    let ticket = support::visible_ticket(&mut tx, &caller, ticket_id, true).await?;
+    ensure_open(&ticket)?;
+
+    let attachment: TicketAttachment = sqlx::query_as::<_, TicketAttachment>(&format!(
+        "INSERT INTO support_ticket_attachments
+             (ticket_id, uploaded_by, filename, content_type, size_bytes, sha256, content)
+         VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {}",
+        ATTACHMENT_COLUMNS
+    ))
+    .bind(ticket.id)
+    .bind(caller.user.id)
+    .bind(&filename)
+    .bind(content_type)
+    .bind(content.len() as i64)
+    .bind(hex::encode(Sha256::digest(&content)))
+    .bind(content.as_ref())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(attachment))
+}
+
+/// Download an attachment
+#[utoipa::path(
+    get,
+    path = "/api/v1/support/tickets/{ticket_id}/attachments/{attachment_id}",
+    tag = "support",
+    params(
+        ("ticket_id" = Uuid, Path, description = "Ticket ID"),
+        ("attachment_id" = Uuid, Path, description = "Attachment ID"),
+    ),
+    responses(
+        (status = 200, description = "The file, served as a download with its stored media type", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 404, description = "Ticket or attachment not found, or the user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The user's roles do not grant access to the support page, or the token lacks a verified email or MFA, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn download_attachment(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Path((ticket_id, attachment_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response, AppError> {
+    let mut conn = state.db_pool.acquire().await?;
+    let caller = support::caller(&mut conn, &claims).await?;
+    let ticket = support::visible_ticket(&mut conn, &caller, ticket_id, false).await?;
+
+    let (filename, content_type, content): (String, String, Vec<u8>) = sqlx::query_as(
+        "SELECT filename, content_type, content FROM support_ticket_attachments
+         WHERE id = $1 AND ticket_id = $2",
+    )
+    .bind(attachment_id)
+    .bind(ticket.id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::not_found("attachment_not_found", "Attachment not found"))?;
+
+    let content_type = HeaderValue::from_str(&content_type)
+        .unwrap_or(HeaderValue::from_static(DEFAULT_ATTACHMENT_CONTENT_TYPE));
+    let disposition = HeaderValue::from_str(&support::content_disposition(&filename))
+        .unwrap_or(HeaderValue::from_static("attachment"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+            // Never let a browser render uploaded content as another type
+            (
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ),
+        ],
+        content,
+    )
+        .into_response())
+}
+
+fn ensure_open(ticket: &SupportTicket) -> Result<(), AppError> {
+    if ticket.status == TicketStatus::Closed {
+        return Err(AppError::conflict(
+            "ticket_closed",
+            "The ticket is closed; reopen it first",
+        ));
+    }
+    Ok(())
+}
@@ -1,22 +1,35 @@
 use axum::{
     extract::State,
-    http::StatusCode,
     response::{IntoResponse, Json},
 };
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 
-use crate::middleware::validate_jwt_token_with_claims;
+use super::admin::update_user_fields;
+use crate::audit::AuditContext;
+use crate::error::{AppError, ProblemDetails};
+use crate::middleware::{account_deleted, validate_jwt_token_with_claims};
 use crate::models::{
-    AppState, Claims, ErrorResponse, HealthResponse, OnboardingResponse, ProfileResponse,
-    UptimeResponse, User, ValidateTokenRequest, ValidateTokenResponse, VersionResponse,
+    AppState, Claims, HealthResponse, OnboardingResponse, PreferencesResponse, ProfileResponse,
+    UpdatePreferencesRequest, UpdateProfileRequest, UptimeResponse, User, ValidateTokenRequest,
+    ValidateTokenResponse, VersionResponse,
 };
+use crate::preferences;
+use crate::validation::ValidatedJson;
 
 // Environment constants
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// Health check endpoint
+#[utoipa::path(
+    get,
+    path = "/api/v1/health",
+    tag = "system",
+    responses(
+        (status = 200, description = "The service is healthy", body = HealthResponse),
+    ),
+)]
 pub async fn health_check() -> impl IntoResponse {
     Json(HealthResponse {
         status: "ok".to_string(),
@@ -25,6 +38,14 @@ pub async fn health_check() -> impl IntoResponse {
 }
 
 /// Get application version
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/version",
+    tag = "system",
+    responses(
+        (status = 200, description = "Application version", body = VersionResponse),
+    ),
+)]
 pub async fn system_version() -> impl IntoResponse {
     Json(VersionResponse {
         version: VERSION.to_string(),
@@ -32,11 +53,21 @@ pub async fn system_version() -> impl IntoResponse {
 }
 
 /// Validate a JWT token
+#[utoipa::path(
+    post,
+    path = "/api/v1/validate-token",
+    tag = "system",
+    request_body = ValidateTokenRequest,
+    responses(
+        (status = 200, description = "Whether the token is valid", body = ValidateTokenResponse),
+        (status = 422, description = "The request body failed validation", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+)]
 pub async fn validate_token(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<ValidateTokenRequest>,
+    ValidatedJson(payload): ValidatedJson<ValidateTokenRequest>,
 ) -> impl IntoResponse {
-    match validate_jwt_token_with_claims(&payload.token, &state.jwt_secret) {
+    match validate_jwt_token_with_claims(&payload.token, state.jwt_secret.get().expose()) {
         Ok(claims) => {
             // Check email_verified and mfa_enabled
             let email_verified = claims.email_verified.unwrap_or(false);
@@ -61,14 +92,30 @@ pub async fn validate_token(
                 message: "Token is valid".to_string(),
             })
         }
-        Err(e) => Json(ValidateTokenResponse {
-            valid: false,
-            message: format!("Token is invalid: {}", e),
-        }),
+        // Which check failed stays in the server log, so callers guessing
+        // tokens cannot tell a bad signature from an expired or malformed one
+        Err(e) => {
+            tracing::info!(reason = %e, "Token validation failed");
+            Json(ValidateTokenResponse {
+                valid: false,
+                message: "Token is invalid".to_string(),
+            })
+        }
     }
 }
 
 /// Get system uptime
+#[utoipa::path(
+    get,
+    path = "/api/v1/system/uptime",
+    tag = "system",
+    responses(
+        (status = 200, description = "Time since the server started", body = UptimeResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn system_uptime(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     let uptime = match SystemTime::now().duration_since(state.start_time) {
         Ok(duration) => duration,
@@ -101,10 +148,21 @@ pub async fn system_uptime(State(state): State<Arc<AppState>>) -> impl IntoRespo
 }
 
 /// User onboarding - auto-register user from JWT claims
+#[utoipa::path(
+    post,
+    path = "/api/v1/system/onboarding",
+    tag = "system",
+    responses(
+        (status = 200, description = "The registered user", body = OnboardingResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email or MFA, or the account is deactivated or deleted", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn system_onboarding(
     State(state): State<Arc<AppState>>,
     claims: Claims,
-) -> Result<Json<OnboardingResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<OnboardingResponse>, AppError> {
     let sub = &claims.sub;
 
     // Check if user exists
@@ -112,16 +170,7 @@ pub async fn system_onboarding(
         sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1")
             .bind(sub)
             .fetch_optional(&state.db_pool)
-            .await
-            .map_err(|e| {
-                tracing::error!("Database error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: "Database error".to_string(),
-                    }),
-                )
-            })?;
+            .await?;
 
     if let Some(user) = existing_user {
         return Ok(Json(OnboardingResponse {
@@ -154,10 +203,11 @@ pub async fn system_onboarding(
         None
     };
 
-    // Create new user
-    let new_user: User = sqlx::query_as::<_, User>(
-        "INSERT INTO users (sub, user_email, user_fullname, organization, organization_id, properties) 
-         VALUES ($1, $2, $3, $4, $5, $6) 
+    // Create new user, unless the account was deleted
+    let new_user: Option<User> = sqlx::query_as::<_, User>(
+        "INSERT INTO users (sub, user_email, user_fullname, organization, organization_id, properties)
+         SELECT $1, $2, $3, $4, $5, $6
+         WHERE NOT EXISTS (SELECT 1 FROM deleted_users WHERE sub = $1)
          RETURNING *",
     )
     .bind(sub)
@@ -166,17 +216,9 @@ pub async fn system_onboarding(
     .bind(&organization)
     .bind(organization_id)
     .bind(serde_json::json!({}))
-    .fetch_one(&state.db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to create user: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to create user".to_string(),
-            }),
-        )
-    })?;
+    .fetch_optional(&state.db_pool)
+    .await?;
+    let new_user = new_user.ok_or_else(account_deleted)?;
 
     Ok(Json(OnboardingResponse {
         user_id: new_user.id,
@@ -186,33 +228,145 @@ pub async fn system_onboarding(
 }
 
 /// Get user profile
+#[utoipa::path(
+    get,
+    path = "/api/v1/profile",
+    tag = "system",
+    responses(
+        (status = 200, description = "Profile of the authenticated user", body = ProfileResponse),
+        (status = 404, description = "The user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn get_profile(
     State(state): State<Arc<AppState>>,
     claims: Claims,
-) -> Result<Json<ProfileResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ProfileResponse>, AppError> {
     let sub = &claims.sub;
 
     let user: Option<User> = sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1")
         .bind(sub)
         .fetch_optional(&state.db_pool)
-        .await
-        .map_err(|e| {
-            tracing::error!("Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+        .await?;
 
     match user {
         Some(user) => Ok(Json(ProfileResponse { user })),
-        None => Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "User not found".to_string(),
-            }),
-        )),
+        None => Err(AppError::not_found("user_not_found", "User not found")),
     }
 }
+
+/// Update the caller's display name or properties
+#[utoipa::path(
+    patch,
+    path = "/api/v1/profile",
+    tag = "system",
+    request_body = UpdateProfileRequest,
+    responses(
+        (status = 200, description = "The updated profile", body = ProfileResponse),
+        (status = 404, description = "The user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid or read-only fields, or properties that violate the organization's schema", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    audit_ctx: AuditContext,
+    ValidatedJson(request): ValidatedJson<UpdateProfileRequest>,
+) -> Result<Json<ProfileResponse>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user: User = sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1 FOR UPDATE")
+        .bind(&claims.sub)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    let user = update_user_fields(&mut tx, &audit_ctx, user, &request.into()).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ProfileResponse { user }))
+}
+
+/// Get the caller's effective preferences
+#[utoipa::path(
+    get,
+    path = "/api/v1/preferences",
+    tag = "system",
+    responses(
+        (status = 200, description = "Effective preferences, the defaults they fall back to and the keys the user has set", body = PreferencesResponse),
+        (status = 404, description = "The user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn get_preferences(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> Result<Json<PreferencesResponse>, AppError> {
+    let mut conn = state.db_pool.acquire().await?;
+
+    let user: User = sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1")
+        .bind(&claims.sub)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    let organization = preferences::organization_overrides(&mut conn, user.organization_id).await?;
+    let overrides = preferences::user_overrides(&mut conn, user.id).await?;
+
+    Ok(Json(preferences::resolve(&organization, overrides)))
+}
+
+/// Change some of the caller's preferences; `null` resets a key to the default
+#[utoipa::path(
+    patch,
+    path = "/api/v1/preferences",
+    tag = "system",
+    request_body = UpdatePreferencesRequest,
+    responses(
+        (status = 200, description = "The updated preferences", body = PreferencesResponse),
+        (status = 404, description = "The user has not been onboarded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown keys or values", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks a verified email, MFA or admin rights, or the account is deactivated", body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn update_preferences(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    ValidatedJson(request): ValidatedJson<UpdatePreferencesRequest>,
+) -> Result<Json<PreferencesResponse>, AppError> {
+    let mut tx = state.db_pool.begin().await?;
+
+    let user: User = sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1 FOR UPDATE")
+        .bind(&claims.sub)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    let mut overrides = preferences::user_overrides(&mut tx, user.id).await?;
+    overrides.update(&request);
+
+    sqlx::query(
+        "INSERT INTO user_preferences (user_id, preferences) VALUES ($1, $2)
+         ON CONFLICT (user_id) DO UPDATE SET preferences = EXCLUDED.preferences",
+    )
+    .bind(user.id)
+    .bind(sqlx::types::Json(&overrides))
+    .execute(&mut *tx)
+    .await?;
+
+    let organization = preferences::organization_overrides(&mut tx, user.organization_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(preferences::resolve(&organization, overrides)))
+}
@@ -0,0 +1,272 @@
+//! Liveness and readiness probes.
+//!
+//! `/livez` only shows the process answers requests. `/readyz` checks what
+//! serving traffic depends on, each component under a timeout, and answers
+//! 503 while any of them is down, or while the server shuts down, so the
+//! orchestrator stops routing to the instance without restarting it.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::migrations::{pending_migrations, MIGRATIONS};
+use crate::models::AppState;
+use crate::shutdown::Shutdown;
+
+/// Longest a single readiness check may take before it counts as down
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+/// Outcome of one readiness check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: ComponentStatus,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LivenessResponse {
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadinessResponse {
+    /// `ready` when every check is up, `not_ready` otherwise
+    pub status: String,
+    pub checks: BTreeMap<String, ComponentHealth>,
+}
+
+impl ReadinessResponse {
+    pub fn from_checks(checks: BTreeMap<String, ComponentHealth>) -> Self {
+        let ready = checks
+            .values()
+            .all(|check| check.status == ComponentStatus::Up);
+        ReadinessResponse {
+            status: if ready { "ready" } else { "not_ready" }.to_string(),
+            checks,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+impl IntoResponse for ReadinessResponse {
+    fn into_response(self) -> axum::response::Response {
+        let status = if self.is_ready() {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// Liveness probe
+pub async fn livez() -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: "alive".to_string(),
+    })
+}
+
+/// Readiness probe: database, schema and signing key
+pub async fn readyz(State(state): State<Arc<AppState>>) -> ReadinessResponse {
+    let (database, migrations) = tokio::join!(
+        timed(CHECK_TIMEOUT, check_database(&state.db_pool)),
+        timed(CHECK_TIMEOUT, check_migrations(&state.db_pool)),
+    );
+
+    ReadinessResponse::from_checks(BTreeMap::from([
+        ("database".to_string(), database),
+        ("migrations".to_string(), migrations),
+        (
+            "signing_key".to_string(),
+            check_signing_key(state.jwt_secret.get().expose()),
+        ),
+        ("lifecycle".to_string(), check_lifecycle(&state.shutdown)),
+    ]))
+}
+
+/// Readiness probe of `MOCK_MODE`, which has no database to check
+pub async fn mock_readyz(State(state): State<Arc<AppState>>) -> ReadinessResponse {
+    ReadinessResponse::from_checks(BTreeMap::from([
+        (
+            "signing_key".to_string(),
+            check_signing_key(state.jwt_secret.get().expose()),
+        ),
+        ("lifecycle".to_string(), check_lifecycle(&state.shutdown)),
+    ]))
+}
+
+/// Run a check under `limit` and measure it
+async fn timed<F>(limit: Duration, check: F) -> ComponentHealth
+where
+    F: Future<Output = Result<Option<String>, String>>,
+{
+    let started = Instant::now();
+    let outcome = tokio::time::timeout(limit, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("No answer within {:?}", limit)));
+    // Microsecond precision is plenty
+    let latency_ms = (started.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0;
+
+    match outcome {
+        Ok(detail) => ComponentHealth {
+            status: ComponentStatus::Up,
+            latency_ms,
+            detail,
+        },
+        Err(detail) => ComponentHealth {
+            status: ComponentStatus::Down,
+            latency_ms,
+            detail: Some(detail),
+        },
+    }
+}
+
+async fn check_database(pool: &sqlx::PgPool) -> Result<Option<String>, String> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .map(|_| None)
+        .map_err(|e| e.to_string())
+}
+
+async fn check_migrations(pool: &sqlx::PgPool) -> Result<Option<String>, String> {
+    let pending = pending_migrations(pool).await.map_err(|e| e.to_string())?;
+    if pending.is_empty() {
+        return Ok(Some(format!("{} applied", MIGRATIONS.len())));
+    }
+    let names: Vec<String> = pending
+        .iter()
+        .map(|migration| format!("{:03}_{}", migration.version, migration.name))
+        .collect();
+    Err(format!("Pending: {}", names.join(", ")))
+}
+
+fn check_signing_key(secret: &str) -> ComponentHealth {
+    if secret.is_empty() {
+        ComponentHealth {
+            status: ComponentStatus::Down,
+            latency_ms: 0.0,
+            detail: Some("No JWT signing key is configured".to_string()),
+        }
+    } else {
+        ComponentHealth {
+            status: ComponentStatus::Up,
+            latency_ms: 0.0,
+            detail: None,
+        }
+    }
+}
+
+/// Down once shutdown has begun, so traffic moves elsewhere while
+/// in-flight requests drain
+fn check_lifecycle(shutdown: &Shutdown) -> ComponentHealth {
+    ComponentHealth {
+        status: if shutdown.is_draining() {
+            ComponentStatus::Down
+        } else {
+            ComponentStatus::Up
+        },
+        latency_ms: 0.0,
+        detail: shutdown.is_draining().then(|| "Shutting down".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use sqlx::postgres::PgPoolOptions;
+
+    fn unreachable_state() -> Arc<AppState> {
+        Arc::new(AppState {
+            jwt_secret: "secret".into(),
+            start_time: std::time::SystemTime::now(),
+            db_pool: PgPoolOptions::new()
+                .acquire_timeout(Duration::from_secs(5))
+                .connect_lazy("postgres://unused@127.0.0.1:1/unused")
+                .unwrap(),
+            metrics: Metrics::default(),
+            shutdown: Default::default(),
+            rate_limiter: Default::default(),
+            trusted_proxies: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_database_is_not_ready() {
+        let started = Instant::now();
+        let response = readyz(State(unreachable_state())).await;
+
+        assert!(!response.is_ready());
+        assert_eq!(response.checks["database"].status, ComponentStatus::Down);
+        assert_eq!(response.checks["migrations"].status, ComponentStatus::Down);
+        assert_eq!(response.checks["signing_key"].status, ComponentStatus::Up);
+        assert!(started.elapsed() < CHECK_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(
+            response.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_checks_time_out() {
+        let check = timed(Duration::from_millis(10), async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(None)
+        })
+        .await;
+
+        assert_eq!(check.status, ComponentStatus::Down);
+        assert!(check.detail.unwrap().starts_with("No answer within"));
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_while_shutting_down() {
+        let state = unreachable_state();
+        let ready = mock_readyz(State(state.clone())).await;
+        assert!(ready.is_ready());
+
+        state.shutdown.begin();
+        let draining = mock_readyz(State(state)).await;
+        assert!(!draining.is_ready());
+        assert_eq!(
+            draining.checks["lifecycle"].detail.as_deref(),
+            Some("Shutting down")
+        );
+    }
+
+    #[test]
+    fn test_ready_when_every_check_is_up() {
+        let up = check_signing_key("secret");
+        let down = check_signing_key("");
+        assert_eq!(down.status, ComponentStatus::Down);
+
+        let ready = ReadinessResponse::from_checks(BTreeMap::from([("a".to_string(), up.clone())]));
+        assert_eq!(ready.into_response().status(), StatusCode::OK);
+
+        let not_ready = ReadinessResponse::from_checks(BTreeMap::from([
+            ("a".to_string(), up),
+            ("b".to_string(), down),
+        ]));
+        assert_eq!(not_ready.status, "not_ready");
+    }
+}
@@ -0,0 +1,25 @@
+//! Backend library: handlers, models and the router, shared by the server
+//! binary and the typed API client in `tools/api-client`.
+
+pub mod audit;
+pub mod client_ip;
+pub mod config;
+pub mod cors;
+pub mod error;
+pub mod handlers;
+pub mod health;
+pub mod metrics;
+pub mod middleware;
+pub mod migrations;
+pub mod mock;
+pub mod models;
+pub mod openapi;
+pub mod pagination;
+pub mod preferences;
+pub mod rate_limit;
+pub mod routes;
+pub mod secrets;
+pub mod shutdown;
+pub mod support;
+pub mod telemetry;
+pub mod validation;
@@ -0,0 +1,405 @@
+//! Metrics: RED metrics per route, connection pool usage and authentication
+//! rejections.
+//!
+//! Instruments are recorded through OpenTelemetry. They are always readable
+//! in the Prometheus text format on `/metrics`, and are also pushed over OTLP
+//! when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use opentelemetry::{
+    metrics::{Counter, Histogram, Meter, MeterProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    metrics::{PeriodicReader, SdkMeterProvider},
+    Resource,
+};
+use opentelemetry_semantic_conventions::attribute::{
+    HTTP_REQUEST_METHOD, HTTP_RESPONSE_STATUS_CODE, HTTP_ROUTE,
+};
+use prometheus::{Encoder, TextEncoder};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{field::Visit, Subscriber};
+use tracing_subscriber::{layer::Context, Layer};
+
+use crate::models::AppState;
+
+/// Target of the event sqlx emits after handing out a pooled connection
+pub const SQLX_ACQUIRE_TARGET: &str = "sqlx::pool::acquire";
+
+/// Histogram buckets for durations, in seconds
+const DURATION_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Why a request was turned away by the authentication middleware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthRejection {
+    MissingToken,
+    Expired,
+    BadSignature,
+    MalformedToken,
+    EmailNotVerified,
+    MissingMfa,
+    NotAdmin,
+    Deactivated,
+    Deleted,
+}
+
+impl AuthRejection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuthRejection::MissingToken => "missing_token",
+            AuthRejection::Expired => "expired",
+            AuthRejection::BadSignature => "bad_signature",
+            AuthRejection::MalformedToken => "malformed_token",
+            AuthRejection::EmailNotVerified => "email_not_verified",
+            AuthRejection::MissingMfa => "missing_mfa",
+            AuthRejection::NotAdmin => "not_admin",
+            AuthRejection::Deactivated => "deactivated",
+            AuthRejection::Deleted => "deleted",
+        }
+    }
+}
+
+/// Application instruments and the registry `/metrics` is rendered from.
+/// Cloning is cheap, clones record into the same instruments.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: prometheus::Registry,
+    provider: SdkMeterProvider,
+    meter: Meter,
+    http_requests: Counter<u64>,
+    http_duration: Histogram<f64>,
+    auth_rejections: Counter<u64>,
+    rate_limited: Counter<u64>,
+    pool_wait_time: Histogram<f64>,
+}
+
+impl Metrics {
+    /// Instruments readable on `/metrics`, also pushed to `otlp_endpoint`
+    /// when one is given
+    pub fn new(
+        resource: Resource,
+        otlp_endpoint: Option<&str>,
+    ) -> Result<Metrics, opentelemetry_otlp::ExporterBuildError> {
+        let registry = prometheus::Registry::new();
+        let prometheus = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .expect("a new registry has no conflicting collectors");
+
+        let mut provider = SdkMeterProvider::builder()
+            .with_reader(prometheus)
+            .with_resource(resource);
+        if let Some(endpoint) = otlp_endpoint {
+            let exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+            provider = provider.with_reader(PeriodicReader::builder(exporter).build());
+        }
+        let provider = provider.build();
+        let meter = provider.meter(env!("CARGO_PKG_NAME"));
+
+        Ok(Metrics {
+            registry,
+            http_requests: meter
+                .u64_counter("http.server.requests")
+                .with_description("HTTP requests handled, by route and status")
+                .build(),
+            http_duration: meter
+                .f64_histogram("http.server.request.duration")
+                .with_description("Time to answer HTTP requests")
+                .with_unit("s")
+                .with_boundaries(DURATION_BUCKETS.to_vec())
+                .build(),
+            auth_rejections: meter
+                .u64_counter("auth.rejections")
+                .with_description("Requests refused by the authentication middleware, by reason")
+                .build(),
+            rate_limited: meter
+                .u64_counter("rate_limit.rejections")
+                .with_description("Requests answered 429 by the rate limiter, by rule")
+                .build(),
+            pool_wait_time: meter
+                .f64_histogram("db.client.connection.wait_time")
+                .with_description("Time spent waiting for a pooled database connection")
+                .with_unit("s")
+                .with_boundaries(DURATION_BUCKETS.to_vec())
+                .build(),
+            provider,
+            meter,
+        })
+    }
+
+    /// Report the size, limit and usage of `pool` at each collection
+    pub fn observe_pool(&self, pool: &PgPool) {
+        let max_connections = pool.options().get_max_connections();
+        self.meter
+            .u64_observable_gauge("db.client.connection.max")
+            .with_description("Most connections the pool opens")
+            .with_callback(move |observer| observer.observe(u64::from(max_connections), &[]))
+            .build();
+
+        let pool = pool.clone();
+        self.meter
+            .u64_observable_gauge("db.client.connection.count")
+            .with_description("Open database connections, by state")
+            .with_callback(move |observer| {
+                let idle = pool.num_idle() as u64;
+                let used = (pool.size() as u64).saturating_sub(idle);
+                observer.observe(idle, &[KeyValue::new("state", "idle")]);
+                observer.observe(used, &[KeyValue::new("state", "used")]);
+            })
+            .build();
+    }
+
+    pub fn record_request(&self, method: &str, route: Option<&str>, status: u16, seconds: f64) {
+        let mut attributes = vec![
+            KeyValue::new(HTTP_REQUEST_METHOD, method.to_string()),
+            KeyValue::new("http.response.status_class", format!("{}xx", status / 100)),
+        ];
+        if let Some(route) = route {
+            attributes.push(KeyValue::new(HTTP_ROUTE, route.to_string()));
+        }
+        self.http_duration.record(seconds, &attributes);
+        attributes.push(KeyValue::new(HTTP_RESPONSE_STATUS_CODE, i64::from(status)));
+        self.http_requests.add(1, &attributes);
+    }
+
+    pub fn record_auth_rejection(&self, reason: AuthRejection) {
+        self.auth_rejections
+            .add(1, &[KeyValue::new("reason", reason.as_str())]);
+    }
+
+    pub fn record_rate_limited(&self, rule: &str) {
+        self.rate_limited
+            .add(1, &[KeyValue::new("rule", rule.to_string())]);
+    }
+
+    pub fn record_pool_wait(&self, seconds: f64) {
+        self.pool_wait_time.record(seconds, &[]);
+    }
+
+    /// Current values in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+
+    /// Push the last values over OTLP and stop the readers
+    pub fn shutdown(&self) -> Result<(), opentelemetry_sdk::error::OTelSdkError> {
+        self.provider.shutdown()
+    }
+}
+
+impl Default for Metrics {
+    /// Instruments only readable on `/metrics`
+    fn default() -> Self {
+        Metrics::new(Resource::builder().build(), None)
+            .expect("no exporter is built without an endpoint")
+    }
+}
+
+/// Prometheus scrape endpoint
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        state.metrics.render(),
+    )
+}
+
+/// Count every request and time it, by method, matched route and status.
+/// Add it with `Router::layer` so the matched route is known.
+pub async fn http_metrics_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let route = req.extensions().get::<MatchedPath>().cloned();
+
+    let response = next.run(req).await;
+
+    state.metrics.record_request(
+        method.as_str(),
+        route.as_ref().map(MatchedPath::as_str),
+        response.status().as_u16(),
+        started.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+/// Records the pool wait time sqlx reports after each acquire. Needs
+/// `PgPoolOptions::acquire_time_level` to let the events through.
+pub struct PoolMetricsLayer {
+    metrics: Metrics,
+}
+
+impl PoolMetricsLayer {
+    pub fn new(metrics: Metrics) -> Self {
+        PoolMetricsLayer { metrics }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for PoolMetricsLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != SQLX_ACQUIRE_TARGET {
+            return;
+        }
+        let mut wait = AcquiredAfter(None);
+        event.record(&mut wait);
+        if let Some(seconds) = wait.0 {
+            self.metrics.record_pool_wait(seconds);
+        }
+    }
+}
+
+/// `aquired_after_secs` field of an acquire event (spelled as sqlx does)
+struct AcquiredAfter(Option<f64>);
+
+impl Visit for AcquiredAfter {
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        if field.name() == "aquired_after_secs" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &tracing::field::Field, _value: &dyn std::fmt::Debug) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn state() -> Arc<AppState> {
+        Arc::new(AppState {
+            jwt_secret: "secret".into(),
+            start_time: std::time::SystemTime::now(),
+            db_pool: PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/unused")
+                .unwrap(),
+            metrics: Metrics::default(),
+            shutdown: Default::default(),
+            rate_limiter: Default::default(),
+            trusted_proxies: Default::default(),
+        })
+    }
+
+    /// Sample lines of `name` carrying every label in `labels`
+    fn samples<'a>(text: &'a str, name: &str, labels: &[&str]) -> Vec<&'a str> {
+        text.lines()
+            .filter(|line| line.starts_with(&format!("{}{{", name)))
+            .filter(|line| labels.iter().all(|label| line.contains(label)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_counted_by_route_and_status() {
+        let state = state();
+        let app = Router::new()
+            .route("/items/:id", get(|| async { "ok" }))
+            .route("/metrics", get(metrics_handler))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                http_metrics_middleware,
+            ))
+            .with_state(state);
+
+        for uri in ["/items/1", "/items/2", "/missing"] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+        let request = Request::get("/metrics").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+
+        let items = samples(
+            &text,
+            "http_server_requests_total",
+            &[
+                r#"http_route="/items/:id""#,
+                r#"http_response_status_class="2xx""#,
+            ],
+        );
+        assert_eq!(items.len(), 1, "{}", text);
+        assert!(items[0].ends_with(" 2"), "{}", items[0]);
+
+        let missing = samples(
+            &text,
+            "http_server_requests_total",
+            &[r#"http_response_status_code="404""#],
+        );
+        assert_eq!(missing.len(), 1, "{}", text);
+        assert!(!missing[0].contains("http_route"), "{}", missing[0]);
+
+        let buckets = samples(
+            &text,
+            "http_server_request_duration_seconds_count",
+            &[r#"http_route="/items/:id""#],
+        );
+        assert!(buckets[0].ends_with(" 2"), "{}", buckets[0]);
+    }
+
+    #[tokio::test]
+    async fn test_pool_usage_is_observed() {
+        let metrics = Metrics::default();
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        metrics.observe_pool(&pool);
+
+        let text = metrics.render();
+        assert!(!samples(&text, "db_client_connection_count", &[r#"state="idle""#]).is_empty());
+        assert!(
+            text.lines()
+                .any(|line| line.starts_with("db_client_connection_max") && line.ends_with(" 5")),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_acquire_events_record_wait_time() {
+        let metrics = Metrics::default();
+        let subscriber =
+            tracing_subscriber::registry().with(PoolMetricsLayer::new(metrics.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::trace!(
+                target: "sqlx::pool::acquire",
+                aquired_after_secs = 0.02f64,
+                "acquired connection"
+            );
+            tracing::trace!(target: "sqlx::query", elapsed_secs = 1.0f64, "");
+        });
+
+        let text = metrics.render();
+        let count = samples(&text, "db_client_connection_wait_time_seconds_count", &[]);
+        assert!(count[0].ends_with(" 1"), "{}", text);
+    }
+}
@@ -1,16 +1,96 @@
 use axum::{
     extract::{Request, State},
-    http::StatusCode,
+    http::{header, HeaderMap, HeaderValue},
     middleware::Next,
     response::Response,
 };
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, errors::ErrorKind, DecodingKey, Validation};
 use std::sync::Arc;
+use uuid::Uuid;
 
+use crate::error::AppError;
+use crate::metrics::AuthRejection;
 use crate::models::{AppState, Claims};
+use crate::telemetry::record_caller;
+
+/// Header used to propagate the request ID between services and back to clients
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest client-supplied request ID that is accepted as-is
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Request ID of the current request, available in request extensions
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Request ID of the request being handled by the current task, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Accept an incoming request ID only if it is short and printable
+fn sanitize_request_id(value: Option<&HeaderValue>) -> Option<String> {
+    let value = value?.to_str().ok()?.trim();
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LENGTH
+        && value.chars().all(|c| c.is_ascii_graphic());
+    valid.then(|| value.to_string())
+}
+
+/// Propagate the caller's `X-Request-Id` or generate one, expose it to handlers
+/// and error responses, and echo it back in the response headers
+pub async fn request_id_middleware(mut req: Request, next: Next) -> Response {
+    let request_id = sanitize_request_id(req.headers().get(REQUEST_ID_HEADER))
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let mut response = REQUEST_ID.scope(request_id.clone(), next.run(req)).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// When the unversioned API was deprecated, as an RFC 9745 `@<unix-seconds>` date
+pub const LEGACY_API_DEPRECATED_AT: &str = "@1792281600";
+
+/// When the unversioned API will be removed, as an RFC 8594 HTTP-date
+pub const LEGACY_API_SUNSET: &str = "Fri, 30 Apr 2027 00:00:00 GMT";
+
+/// Mark responses of deprecated routes with `Deprecation` and `Sunset` headers
+/// and point clients at the versioned API
+pub async fn deprecation_middleware(req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+
+    let headers = response.headers_mut();
+    headers.insert(
+        "Deprecation",
+        HeaderValue::from_static(LEGACY_API_DEPRECATED_AT),
+    );
+    headers.insert("Sunset", HeaderValue::from_static(LEGACY_API_SUNSET));
+    // Appended so pagination links set by the handler are kept
+    headers.append(
+        header::LINK,
+        HeaderValue::from_static("</api/v1>; rel=\"successor-version\""),
+    );
+
+    response
+}
 
 /// Validate JWT token and extract claims
 pub fn validate_jwt_token_with_claims(token: &str, secret: &str) -> Result<Claims, String> {
+    decode_claims(token, secret).map_err(|e| format!("{}", e))
+}
+
+fn decode_claims(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     let validation = Validation::default();
     decode::<Claims>(
         token,
@@ -18,43 +98,132 @@ pub fn validate_jwt_token_with_claims(token: &str, secret: &str) -> Result<Claim
         &validation,
     )
     .map(|data| data.claims)
-    .map_err(|e| format!("{}", e))
+}
+
+/// Claims of the request's bearer token, if it carries a valid one. Only
+/// the signature and expiry are checked.
+pub(crate) fn bearer_claims(headers: &HeaderMap, secret: &str) -> Option<Claims> {
+    let token = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))?;
+    decode_claims(token, secret).ok()
+}
+
+/// Count a refused request under `reason` and pass its error on
+fn reject(state: &AppState, reason: AuthRejection, error: AppError) -> AppError {
+    state.metrics.record_auth_rejection(reason);
+    error
+}
+
+/// Validate the bearer token of a request and check email_verified and mfa_enabled
+fn authenticate(state: &AppState, req: &Request) -> Result<Claims, AppError> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or_else(|| {
+            reject(
+                state,
+                AuthRejection::MissingToken,
+                AppError::unauthorized("missing_token", "A bearer token is required"),
+            )
+        })?;
+
+    let claims = decode_claims(token, state.jwt_secret.get().expose()).map_err(|e| {
+        let reason = match e.kind() {
+            ErrorKind::ExpiredSignature => AuthRejection::Expired,
+            ErrorKind::InvalidSignature => AuthRejection::BadSignature,
+            _ => AuthRejection::MalformedToken,
+        };
+        reject(
+            state,
+            reason,
+            AppError::unauthorized("invalid_token", "The bearer token is invalid"),
+        )
+    })?;
+    record_caller(&claims);
+
+    if !claims.email_verified.unwrap_or(false) {
+        return Err(reject(
+            state,
+            AuthRejection::EmailNotVerified,
+            AppError::forbidden(
+                "email_not_verified",
+                "The email address has not been verified",
+            ),
+        ));
+    }
+    if !claims.mfa_enabled.unwrap_or(false) {
+        return Err(reject(
+            state,
+            AuthRejection::MissingMfa,
+            AppError::forbidden("mfa_not_enabled", "Multi-factor authentication is required"),
+        ));
+    }
+
+    Ok(claims)
+}
+
+/// Reject tokens of accounts an administrator has deactivated or deleted.
+/// Users that have not been onboarded yet have no row and are let through;
+/// deleted users are told apart by their entry in `deleted_users`.
+async fn ensure_active(state: &AppState, claims: &Claims) -> Result<(), AppError> {
+    let (deactivated_at, deleted): (Option<DateTime<Utc>>, bool) = sqlx::query_as(
+        "SELECT
+            (SELECT deactivated_at FROM users WHERE sub = $1),
+            EXISTS (SELECT 1 FROM deleted_users WHERE sub = $1)",
+    )
+    .bind(&claims.sub)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    if deleted {
+        return Err(reject(state, AuthRejection::Deleted, account_deleted()));
+    }
+    if deactivated_at.is_some() {
+        return Err(reject(
+            state,
+            AuthRejection::Deactivated,
+            AppError::forbidden("account_deactivated", "This account has been deactivated"),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn account_deleted() -> AppError {
+    AppError::forbidden("account_deleted", "This account has been deleted")
+}
+
+fn require_admin(state: &AppState, claims: &Claims) -> Result<(), AppError> {
+    if !claims.admin.unwrap_or(false) {
+        return Err(reject(
+            state,
+            AuthRejection::NotAdmin,
+            AppError::forbidden(
+                "admin_required",
+                "This operation requires administrator privileges",
+            ),
+        ));
+    }
+    Ok(())
 }
 
 /// Authentication middleware for protected routes
-/// Validates JWT token and checks email_verified and mfa_enabled
+/// Validates JWT token, checks email_verified and mfa_enabled and rejects
+/// deactivated accounts
 pub async fn auth_middleware(
     State(state): State<Arc<AppState>>,
     mut req: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok());
-
-    if let Some(auth_header) = auth_header {
-        if let Some(token) = auth_header.strip_prefix("Bearer ") {
-            match validate_jwt_token_with_claims(token, &state.jwt_secret) {
-                Ok(claims) => {
-                    // Check email_verified and mfa_enabled
-                    if !claims.email_verified.unwrap_or(false) {
-                        return Err(StatusCode::FORBIDDEN);
-                    }
-                    if !claims.mfa_enabled.unwrap_or(false) {
-                        return Err(StatusCode::FORBIDDEN);
-                    }
-
-                    // Insert claims into request extensions
-                    req.extensions_mut().insert(claims);
-                    return Ok(next.run(req).await);
-                }
-                Err(_) => return Err(StatusCode::UNAUTHORIZED),
-            }
-        }
-    }
+) -> Result<Response, AppError> {
+    let claims = authenticate(&state, &req)?;
+    ensure_active(&state, &claims).await?;
 
-    Err(StatusCode::UNAUTHORIZED)
+    // Insert claims into request extensions
+    req.extensions_mut().insert(claims);
+    Ok(next.run(req).await)
 }
 
 /// Admin-only middleware
@@ -63,39 +232,38 @@ pub async fn admin_middleware(
     State(state): State<Arc<AppState>>,
     mut req: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok());
-
-    if let Some(auth_header) = auth_header {
-        if let Some(token) = auth_header.strip_prefix("Bearer ") {
-            match validate_jwt_token_with_claims(token, &state.jwt_secret) {
-                Ok(claims) => {
-                    // Check email_verified and mfa_enabled
-                    if !claims.email_verified.unwrap_or(false) {
-                        return Err(StatusCode::FORBIDDEN);
-                    }
-                    if !claims.mfa_enabled.unwrap_or(false) {
-                        return Err(StatusCode::FORBIDDEN);
-                    }
-
-                    // Check if user is admin
-                    if !claims.admin.unwrap_or(false) {
-                        return Err(StatusCode::FORBIDDEN);
-                    }
-
-                    // Insert claims into request extensions
-                    req.extensions_mut().insert(claims);
-                    return Ok(next.run(req).await);
-                }
-                Err(_) => return Err(StatusCode::UNAUTHORIZED),
-            }
-        }
-    }
+) -> Result<Response, AppError> {
+    let claims = authenticate(&state, &req)?;
+    require_admin(&state, &claims)?;
+    ensure_active(&state, &claims).await?;
 
-    Err(StatusCode::UNAUTHORIZED)
+    // Insert claims into request extensions
+    req.extensions_mut().insert(claims);
+    Ok(next.run(req).await)
+}
+
+/// `auth_middleware` without the account check, for the mock router, which
+/// has no user table to consult
+pub async fn token_auth_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let claims = authenticate(&state, &req)?;
+    req.extensions_mut().insert(claims);
+    Ok(next.run(req).await)
+}
+
+/// `admin_middleware` without the account check, for the mock router
+pub async fn token_admin_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let claims = authenticate(&state, &req)?;
+    require_admin(&state, &claims)?;
+    req.extensions_mut().insert(claims);
+    Ok(next.run(req).await)
 }
 
 // Extractor for Claims from request extensions
@@ -104,7 +272,7 @@ impl<S> axum::extract::FromRequestParts<S> for Claims
 where
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, &'static str);
+    type Rejection = AppError;
 
     async fn from_request_parts(
         parts: &mut axum::http::request::Parts,
@@ -114,6 +282,121 @@ where
             .extensions
             .get::<Claims>()
             .cloned()
-            .ok_or((StatusCode::UNAUTHORIZED, "Unauthorized"))
+            .ok_or_else(|| AppError::unauthorized("missing_token", "Unauthorized"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_request_id() {
+        let valid = HeaderValue::from_static("abc-123");
+        let spaced = HeaderValue::from_static("abc 123");
+        let long = HeaderValue::from_str(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)).unwrap();
+
+        assert_eq!(
+            sanitize_request_id(Some(&valid)).as_deref(),
+            Some("abc-123")
+        );
+        assert_eq!(sanitize_request_id(Some(&spaced)), None);
+        assert_eq!(sanitize_request_id(Some(&long)), None);
+        assert_eq!(sanitize_request_id(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_current_request_id_is_scoped() {
+        assert_eq!(current_request_id(), None);
+
+        let inside = REQUEST_ID
+            .scope("req-42".to_string(), async { current_request_id() })
+            .await;
+
+        assert_eq!(inside.as_deref(), Some("req-42"));
+    }
+
+    #[tokio::test]
+    async fn test_rejections_are_counted_by_reason() {
+        use crate::metrics::Metrics;
+        use axum::{body::Body, routing::get, Router};
+        use jsonwebtoken::{encode, EncodingKey, Header};
+        use tower::ServiceExt;
+
+        let state = Arc::new(AppState {
+            jwt_secret: "secret".into(),
+            start_time: std::time::SystemTime::now(),
+            db_pool: sqlx::postgres::PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/unused")
+                .unwrap(),
+            metrics: Metrics::default(),
+            shutdown: Default::default(),
+            rate_limiter: Default::default(),
+            trusted_proxies: Default::default(),
+        });
+        let app = Router::new()
+            .route("/admin", get(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                token_admin_middleware,
+            ))
+            .with_state(state.clone());
+
+        let token = |exp: usize, mfa: bool, admin: bool, secret: &str| {
+            let claims = Claims {
+                sub: "someone".to_string(),
+                exp,
+                email_verified: Some(true),
+                mfa_enabled: Some(mfa),
+                email: None,
+                name: None,
+                admin: Some(admin),
+                organization: None,
+            };
+            let key = EncodingKey::from_secret(secret.as_bytes());
+            format!(
+                "Bearer {}",
+                encode(&Header::default(), &claims, &key).unwrap()
+            )
+        };
+        let valid_until = 4_102_444_800;
+        let authorizations = [
+            None,
+            Some(token(1_000_000_000, true, true, "secret")),
+            Some(token(valid_until, true, true, "other-secret")),
+            Some("Bearer not-a-jwt".to_string()),
+            Some(token(valid_until, false, true, "secret")),
+            Some(token(valid_until, true, false, "secret")),
+            Some(token(valid_until, true, true, "secret")),
+        ];
+        for authorization in authorizations {
+            let mut request = Request::get("/admin");
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let text = state.metrics.render();
+        for reason in [
+            "missing_token",
+            "expired",
+            "bad_signature",
+            "malformed_token",
+            "missing_mfa",
+            "not_admin",
+        ] {
+            let label = format!("reason=\"{}\"", reason);
+            assert!(
+                text.lines()
+                    .any(|line| line.starts_with("auth_rejections_total")
+                        && line.contains(&label)
+                        && line.ends_with(" 1")),
+                "{} not counted in\n{}",
+                reason,
+                text
+            );
+        }
     }
 }
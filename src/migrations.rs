@@ -1,3 +1,152 @@
+use sqlx::PgPool;
+
+/// A migration file embedded in the binary
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// All migrations, in the order they must be applied
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users_table",
+        sql: include_str!("../migrations/001_create_users_table.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "create_roles_and_permissions",
+        sql: include_str!("../migrations/002_create_roles_and_permissions.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "add_multi_tenancy",
+        sql: include_str!("../migrations/003_add_multi_tenancy.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "create_audit_events",
+        sql: include_str!("../migrations/004_create_audit_events.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "chain_audit_events",
+        sql: include_str!("../migrations/005_chain_audit_events.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "add_pagination_indexes",
+        sql: include_str!("../migrations/006_add_pagination_indexes.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "add_user_search_indexes",
+        sql: include_str!("../migrations/007_add_user_search_indexes.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "add_user_lifecycle",
+        sql: include_str!("../migrations/008_add_user_lifecycle.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "add_profile_updates",
+        sql: include_str!("../migrations/009_add_profile_updates.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "create_user_preferences",
+        sql: include_str!("../migrations/010_create_user_preferences.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "create_support_tickets",
+        sql: include_str!("../migrations/011_create_support_tickets.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "create_rate_limit_counters",
+        sql: include_str!("../migrations/012_create_rate_limit_counters.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "align_permission_organizations",
+        sql: include_str!("../migrations/013_align_permission_organizations.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "create_deleted_users",
+        sql: include_str!("../migrations/014_create_deleted_users.sql"),
+    },
+];
+
+/// Apply every migration that has not been recorded in `schema_migrations` yet.
+///
+/// Each migration runs in its own transaction together with its bookkeeping row,
+/// and an advisory lock keeps concurrently starting replicas from racing.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    for migration in MIGRATIONS {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('schema_migrations'))")
+            .execute(&mut *tx)
+            .await?;
+
+        let applied: Option<i64> =
+            sqlx::query_scalar("SELECT version FROM schema_migrations WHERE version = $1")
+                .bind(migration.version)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+        if applied.is_some() {
+            continue;
+        }
+
+        for statement in parse_sql_statements(migration.sql) {
+            sqlx::query(&statement).execute(&mut *tx).await?;
+        }
+
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        tracing::info!(
+            "Applied migration {:03}_{}",
+            migration.version,
+            migration.name
+        );
+    }
+
+    Ok(())
+}
+
+/// Embedded migrations not recorded in `schema_migrations`. Fails when the
+/// table does not exist yet.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<&'static Migration>, sqlx::Error> {
+    let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_migrations")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect())
+}
+
 /// Parse SQL statements from a migration file, respecting dollar-quoted strings.
 /// This handles PostgreSQL's DO $$ ... END $$; blocks correctly.
 pub fn parse_sql_statements(content: &str) -> Vec<String> {
@@ -116,6 +265,14 @@ mod tests {
         assert_eq!(statements[0], "CREATE TABLE test (id INT);");
     }
 
+    #[test]
+    fn test_migrations_are_sequential() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64 + 1);
+            assert!(!parse_sql_statements(migration.sql).is_empty());
+        }
+    }
+
     #[test]
     fn test_parse_sql_statements_real_migration() {
         // Test with actual migration content
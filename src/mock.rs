@@ -0,0 +1,443 @@
+use axum::{
+    http::{Method, StatusCode},
+    middleware as axum_middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{on, MethodFilter},
+    Router,
+};
+use serde_json::{json, Map, Value as JsonValue};
+use std::sync::Arc;
+use utoipa::OpenApi;
+
+use crate::middleware::{token_admin_middleware, token_auth_middleware};
+use crate::models::AppState;
+use crate::openapi::ApiDoc;
+use crate::routes::API_V1_PREFIX;
+
+/// Hand-written responses keyed by `operationId`; operations without a
+/// fixture answer with an example generated from their response schema
+const FIXTURES: &str = include_str!("../mock/fixtures.json");
+
+/// Recursion limit for self-referencing schemas
+const MAX_EXAMPLE_DEPTH: usize = 16;
+
+/// Canned response of one operation
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub body: Option<JsonValue>,
+}
+
+impl IntoResponse for MockResponse {
+    fn into_response(self) -> Response {
+        match self.body {
+            Some(body) => (self.status, Json(body)).into_response(),
+            None => self.status.into_response(),
+        }
+    }
+}
+
+/// One operation of the OpenAPI document, ready to be mounted
+#[derive(Debug)]
+pub struct MockOperation {
+    pub method: Method,
+    /// Path relative to `/api/v1`, in axum syntax (`/admin/roles/:role_id`)
+    pub path: String,
+    pub operation_id: String,
+    pub requires_auth: bool,
+    pub response: MockResponse,
+}
+
+/// Serve every operation of the OpenAPI document with fixture or generated
+/// responses. Protected and admin operations still check tokens, with the
+/// same rules as the real auth middleware minus the account lookup.
+pub fn mock_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let fixtures: Map<String, JsonValue> =
+        serde_json::from_str(FIXTURES).expect("mock/fixtures.json is a JSON object");
+
+    let mut public = Router::new();
+    let mut protected = Router::new();
+    let mut admin = Router::new();
+
+    for operation in mock_operations(&fixtures) {
+        let filter = MethodFilter::try_from(operation.method.clone())
+            .expect("OpenAPI methods are standard HTTP methods");
+        tracing::debug!(
+            "Mocking {} {} ({})",
+            operation.method,
+            operation.path,
+            operation.operation_id
+        );
+        let response = operation.response;
+        let route = on(filter, move || {
+            let response = response.clone();
+            async move { response }
+        });
+
+        if !operation.requires_auth {
+            public = public.route(&operation.path, route);
+        } else if operation.path.starts_with("/admin/") {
+            admin = admin.route(&operation.path, route);
+        } else {
+            protected = protected.route(&operation.path, route);
+        }
+    }
+
+    public
+        .merge(protected.route_layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            token_auth_middleware,
+        )))
+        .merge(admin.route_layer(axum_middleware::from_fn_with_state(
+            state,
+            token_admin_middleware,
+        )))
+}
+
+/// Collect the operations of the OpenAPI document with their mock responses
+pub fn mock_operations(fixtures: &Map<String, JsonValue>) -> Vec<MockOperation> {
+    let spec = serde_json::to_value(ApiDoc::openapi()).expect("OpenAPI document serializes");
+    let components = &spec["components"]["schemas"];
+
+    let mut operations = Vec::new();
+    let Some(paths) = spec["paths"].as_object() else {
+        return operations;
+    };
+
+    for (path, item) in paths {
+        let Some(relative) = path.strip_prefix(API_V1_PREFIX) else {
+            continue;
+        };
+        let Some(methods) = item.as_object() else {
+            continue;
+        };
+
+        for (method, operation) in methods {
+            let Ok(method) = Method::from_bytes(method.to_uppercase().as_bytes()) else {
+                continue;
+            };
+            let operation_id = operation["operationId"].as_str().unwrap_or_default();
+            let (status, schema) = success_response(operation);
+
+            let body = match fixtures.get(operation_id) {
+                Some(fixture) => Some(fixture.clone()),
+                None => schema.map(|schema| example_for(schema, components, "", 0)),
+            };
+
+            operations.push(MockOperation {
+                method,
+                path: axum_path(relative),
+                operation_id: operation_id.to_string(),
+                requires_auth: operation.get("security").is_some(),
+                response: MockResponse { status, body },
+            });
+        }
+    }
+
+    operations
+}
+
+/// First 2xx response of an operation and its JSON schema, if it has a body
+fn success_response(operation: &JsonValue) -> (StatusCode, Option<&JsonValue>) {
+    let responses = operation["responses"].as_object();
+    let success = responses.and_then(|responses| {
+        responses
+            .iter()
+            .filter(|(status, _)| status.starts_with('2'))
+            .min_by_key(|(status, _)| status.as_str())
+    });
+
+    match success {
+        Some((status, response)) => (
+            status.parse().unwrap_or(StatusCode::OK),
+            response["content"]["application/json"].get("schema"),
+        ),
+        None => (StatusCode::OK, None),
+    }
+}
+
+/// Convert OpenAPI path templates (`{role_id}`) into axum syntax (`:role_id`)
+fn axum_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix('{') {
+            Some(name) => format!(":{}", name.trim_end_matches('}')),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn resolve<'a>(schema: &'a JsonValue, components: &'a JsonValue) -> &'a JsonValue {
+    match schema["$ref"].as_str() {
+        Some(reference) => {
+            let name = reference.rsplit('/').next().unwrap_or_default();
+            &components[name]
+        }
+        None => schema,
+    }
+}
+
+/// Non-null type of a schema; OpenAPI 3.1 spells nullable types as `["string", "null"]`
+fn schema_type(schema: &JsonValue) -> Option<&str> {
+    match &schema["type"] {
+        JsonValue::String(t) => Some(t.as_str()),
+        JsonValue::Array(types) => types
+            .iter()
+            .filter_map(JsonValue::as_str)
+            .find(|t| *t != "null"),
+        _ => None,
+    }
+}
+
+/// Build a deterministic example value that conforms to a schema.
+/// `field` is the property name, used to pick plausible strings.
+pub fn example_for(
+    schema: &JsonValue,
+    components: &JsonValue,
+    field: &str,
+    depth: usize,
+) -> JsonValue {
+    let schema = resolve(schema, components);
+    if depth > MAX_EXAMPLE_DEPTH {
+        return JsonValue::Null;
+    }
+    if let Some(example) = schema.get("example") {
+        return example.clone();
+    }
+    if let Some(first) = schema["enum"].as_array().and_then(|values| values.first()) {
+        return first.clone();
+    }
+    if let Some(variants) = schema["oneOf"].as_array().or(schema["anyOf"].as_array()) {
+        let variant = variants
+            .iter()
+            .find(|v| resolve(v, components)["type"] != "null")
+            .or(variants.first());
+        return variant
+            .map(|v| example_for(v, components, field, depth + 1))
+            .unwrap_or(JsonValue::Null);
+    }
+
+    match schema_type(schema) {
+        Some("object") => {
+            let mut object = Map::new();
+            if let Some(properties) = schema["properties"].as_object() {
+                for (name, property) in properties {
+                    object.insert(
+                        name.clone(),
+                        example_for(property, components, name, depth + 1),
+                    );
+                }
+            }
+            JsonValue::Object(object)
+        }
+        Some("array") => json!([example_for(&schema["items"], components, field, depth + 1)]),
+        Some("string") => JsonValue::String(example_string(schema, field)),
+        Some("integer") => json!(schema["minimum"].as_i64().unwrap_or(0).max(1)),
+        Some("number") => json!(1.0),
+        Some("boolean") => json!(true),
+        Some("null") => JsonValue::Null,
+        // Untyped schemas (free-form JSON) accept anything
+        _ => json!({}),
+    }
+}
+
+fn example_string(schema: &JsonValue, field: &str) -> String {
+    match schema["format"].as_str() {
+        Some("uuid") => "00000000-0000-4000-8000-000000000001".to_string(),
+        Some("date-time") => "2026-01-01T00:00:00Z".to_string(),
+        Some("date") => "2026-01-01".to_string(),
+        _ if field.contains("email") => "user@example.com".to_string(),
+        _ if field.is_empty() => "example".to_string(),
+        _ => format!("example-{}", field.replace('_', "-")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use uuid::Uuid;
+
+    /// Check that a value is an instance of a schema, returning the first mismatch
+    fn check(
+        value: &JsonValue,
+        schema: &JsonValue,
+        components: &JsonValue,
+        path: &str,
+    ) -> Result<(), String> {
+        let schema = resolve(schema, components);
+
+        if let Some(variants) = schema["oneOf"].as_array() {
+            return variants
+                .iter()
+                .find(|v| check(value, v, components, path).is_ok())
+                .map(|_| ())
+                .ok_or_else(|| format!("{}: matches no variant", path));
+        }
+
+        if let Some(values) = schema["enum"].as_array() {
+            return if values.contains(value) {
+                Ok(())
+            } else {
+                Err(format!("{}: {} is not one of {:?}", path, value, values))
+            };
+        }
+
+        if value.is_null() {
+            let nullable = match &schema["type"] {
+                JsonValue::Array(types) => types.iter().any(|t| t == "null"),
+                JsonValue::String(t) => t == "null",
+                _ => true,
+            };
+            return if nullable {
+                Ok(())
+            } else {
+                Err(format!("{}: unexpected null", path))
+            };
+        }
+
+        match schema_type(schema) {
+            Some("object") => {
+                let object = value
+                    .as_object()
+                    .ok_or(format!("{}: expected object", path))?;
+                for required in schema["required"].as_array().into_iter().flatten() {
+                    let name = required.as_str().unwrap_or_default();
+                    if !object.contains_key(name) {
+                        return Err(format!("{}.{}: missing required field", path, name));
+                    }
+                }
+                let properties = schema["properties"].as_object();
+                for (name, field) in object {
+                    let property = properties
+                        .and_then(|p| p.get(name))
+                        .ok_or(format!("{}.{}: not in schema", path, name))?;
+                    check(field, property, components, &format!("{}.{}", path, name))?;
+                }
+                Ok(())
+            }
+            Some("array") => {
+                let items = value
+                    .as_array()
+                    .ok_or(format!("{}: expected array", path))?;
+                for (index, item) in items.iter().enumerate() {
+                    check(
+                        item,
+                        &schema["items"],
+                        components,
+                        &format!("{}[{}]", path, index),
+                    )?;
+                }
+                Ok(())
+            }
+            Some("string") => {
+                let text = value.as_str().ok_or(format!("{}: expected string", path))?;
+                let valid = match schema["format"].as_str() {
+                    Some("uuid") => Uuid::parse_str(text).is_ok(),
+                    Some("date-time") => DateTime::parse_from_rfc3339(text).is_ok(),
+                    _ => true,
+                };
+                valid
+                    .then_some(())
+                    .ok_or(format!("{}: invalid format", path))
+            }
+            Some("integer") => value
+                .as_i64()
+                .map(|_| ())
+                .ok_or(format!("{}: expected integer", path)),
+            Some("number") => value
+                .as_f64()
+                .map(|_| ())
+                .ok_or(format!("{}: expected number", path)),
+            Some("boolean") => value
+                .as_bool()
+                .map(|_| ())
+                .ok_or(format!("{}: expected boolean", path)),
+            _ => Ok(()),
+        }
+    }
+
+    fn spec() -> JsonValue {
+        serde_json::to_value(ApiDoc::openapi()).unwrap()
+    }
+
+    fn fixtures() -> Map<String, JsonValue> {
+        serde_json::from_str(FIXTURES).unwrap()
+    }
+
+    #[test]
+    fn test_axum_path() {
+        assert_eq!(
+            axum_path("/admin/users/{user_id}/roles/{role_id}"),
+            "/admin/users/:user_id/roles/:role_id"
+        );
+        assert_eq!(axum_path("/health"), "/health");
+    }
+
+    #[test]
+    fn test_every_operation_is_mocked() {
+        let spec = spec();
+        let operations = mock_operations(&fixtures());
+        let spec_operations: usize = spec["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|item| item.as_object().unwrap().len())
+            .sum();
+
+        assert_eq!(operations.len(), spec_operations);
+        let json_responses: Vec<&str> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .values()
+            .flat_map(|item| item.as_object().unwrap().values())
+            .filter(|operation| success_response(operation).1.is_some())
+            .map(|operation| operation["operationId"].as_str().unwrap())
+            .collect();
+        for operation in &operations {
+            // Downloads such as attachments are not JSON and get no generated body
+            let expects_body = operation.response.status != StatusCode::NO_CONTENT
+                && json_responses.contains(&operation.operation_id.as_str());
+            assert_eq!(
+                operation.response.body.is_some(),
+                expects_body,
+                "{}",
+                operation.operation_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_mock_responses_conform_to_schema() {
+        let spec = spec();
+        let components = &spec["components"]["schemas"];
+
+        for path in spec["paths"].as_object().unwrap().values() {
+            for operation in path.as_object().unwrap().values() {
+                let operation_id = operation["operationId"].as_str().unwrap();
+                let (_, schema) = success_response(operation);
+                let Some(schema) = schema else { continue };
+
+                let generated = example_for(schema, components, "", 0);
+                check(&generated, schema, components, operation_id).unwrap();
+
+                if let Some(fixture) = fixtures().get(operation_id) {
+                    check(fixture, schema, components, operation_id).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixtures_name_existing_operations() {
+        let operations = mock_operations(&fixtures());
+
+        for name in fixtures().keys() {
+            assert!(
+                operations.iter().any(|op| &op.operation_id == name),
+                "fixture {} does not match any operationId",
+                name
+            );
+        }
+    }
+}
@@ -1,7 +1,15 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value as JsonValue;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::validation::{
+    edit_implies_view, edit_ours_implies_view_ours, edit_own_implies_view_own, not_blank,
+    properties_object, unique_pages, valid_properties_schema, MAX_PAGE_NAME_LENGTH,
+    PAGE_NAME_PATTERN,
+};
 
 // JWT Claims structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,42 +27,48 @@ pub struct Claims {
 // Application state
 #[derive(Clone)]
 pub struct AppState {
-    pub jwt_secret: String,
+    /// Replaced when the key is rotated, see `secrets::refresh`
+    pub jwt_secret: crate::secrets::SharedSecret,
     pub start_time: std::time::SystemTime,
     pub db_pool: sqlx::PgPool,
+    pub metrics: crate::metrics::Metrics,
+    pub shutdown: crate::shutdown::Shutdown,
+    pub rate_limiter: crate::rate_limit::RateLimiter,
+    pub trusted_proxies: crate::client_ip::TrustedProxies,
 }
 
 // Response structures
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub message: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct VersionResponse {
     pub version: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct ValidateTokenRequest {
+    #[validate(length(min = 1, max = 8192))]
     pub token: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ValidateTokenResponse {
     pub valid: bool,
     pub message: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UptimeResponse {
     pub uptime_seconds: u64,
     pub uptime_formatted: String,
 }
 
 // Database models
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct User {
     pub id: Uuid,
     pub sub: String,
@@ -64,21 +78,27 @@ pub struct User {
     pub organization_id: Option<Uuid>,
     pub group_id: Option<Uuid>,
     pub properties: JsonValue,
+    /// Set while the account is deactivated
+    pub deactivated_at: Option<DateTime<Utc>>,
+    /// Set once personal data has been scrubbed
+    pub anonymized_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 #[allow(dead_code)]
 pub struct Organization {
     pub id: Uuid,
     pub name: String,
     pub description: Option<String>,
+    pub properties_schema: Option<JsonValue>,
+    pub default_preferences: JsonValue,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 #[allow(dead_code)]
 pub struct Group {
     pub id: Uuid,
@@ -89,7 +109,7 @@ pub struct Group {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow, Clone)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone, ToSchema)]
 pub struct Role {
     pub id: Uuid,
     pub name: String,
@@ -100,7 +120,7 @@ pub struct Role {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow, Clone)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone, ToSchema)]
 pub struct Permission {
     pub id: Uuid,
     pub role_id: Uuid,
@@ -116,7 +136,7 @@ pub struct Permission {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct UserRole {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -125,52 +145,242 @@ pub struct UserRole {
     pub assigned_by: Option<Uuid>,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub organization_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub actor_sub: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<Uuid>,
+    pub before: Option<JsonValue>,
+    pub after: Option<JsonValue>,
+    pub request_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub sequence: i64,
+    pub prev_hash: Option<String>,
+    pub hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditChainBreak {
+    pub sequence: i64,
+    pub event_id: Option<Uuid>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditChainVerification {
+    pub organization_id: Option<Uuid>,
+    pub valid: bool,
+    pub verified_events: u64,
+    pub legacy_events: u64,
+    /// Hash of the last event that verified; store it externally to detect truncation later
+    pub head_hash: Option<String>,
+    pub first_broken_link: Option<AuditChainBreak>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct OnboardingResponse {
     pub user_id: Uuid,
     pub message: String,
     pub is_new_user: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ProfileResponse {
     pub user: User,
 }
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
+/// Fields users may change on their own profile. Email, organization and
+/// group come from the identity provider or an administrator.
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfileRequest {
+    #[validate(length(max = 255), custom(function = "not_blank"))]
+    pub user_fullname: Option<String>,
+    /// Replaces the stored properties; must satisfy the organization's schema
+    #[validate(custom(function = "properties_object"))]
+    #[schema(value_type = Option<Object>)]
+    pub properties: Option<JsonValue>,
+}
+
+/// Color theme of the web app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+    System,
+}
+
+/// Interface language, one of the frontend's locales
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    En,
+    Pt,
+    Es,
+}
+
+/// A complete set of preferences
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Preferences {
+    pub theme: Theme,
+    pub language: Language,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            theme: Theme::System,
+            language: Language::En,
+        }
+    }
+}
+
+/// Preferences set at one level (user or organization). Unset keys fall back
+/// to the level below.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PreferenceOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<Language>,
+}
+
+/// Distinguish an absent key (`None`) from an explicit `null` (`Some(None)`)
+fn explicit_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Changes to the caller's preferences. Absent keys are left alone and `null`
+/// resets a key to the default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdatePreferencesRequest {
+    #[serde(
+        default,
+        deserialize_with = "explicit_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<Theme>)]
+    pub theme: Option<Option<Theme>>,
+    #[serde(
+        default,
+        deserialize_with = "explicit_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<Language>)]
+    pub language: Option<Option<Language>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PreferencesResponse {
+    /// Effective preferences
+    pub preferences: Preferences,
+    /// What applies to keys the user has not set: organization defaults over
+    /// the built-in defaults
+    pub defaults: Preferences,
+    /// Keys the user has set
+    pub overrides: PreferenceOverrides,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DefaultPreferencesResponse {
+    pub organization_id: Uuid,
+    /// Organization overrides of the built-in defaults
+    pub overrides: PreferenceOverrides,
+    /// Defaults members get for keys they have not set
+    pub defaults: Preferences,
 }
 
 // Admin API structures
-#[derive(Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RoleWithPermissions {
     pub role: Role,
     pub permissions: Vec<Permission>,
 }
 
-#[derive(Serialize)]
+/// Fields administrators may change on any user
+#[derive(Debug, Default, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateUserRequest {
+    #[validate(length(max = 255), custom(function = "not_blank"))]
+    pub user_fullname: Option<String>,
+    #[validate(length(max = 255), email)]
+    pub user_email: Option<String>,
+    /// Replaces the stored properties; must satisfy the organization's schema
+    #[validate(custom(function = "properties_object"))]
+    #[schema(value_type = Option<Object>)]
+    pub properties: Option<JsonValue>,
+}
+
+impl From<UpdateProfileRequest> for UpdateUserRequest {
+    fn from(request: UpdateProfileRequest) -> Self {
+        UpdateUserRequest {
+            user_fullname: request.user_fullname,
+            user_email: None,
+            properties: request.properties,
+        }
+    }
+}
+
+/// JSON Schema that members' `properties` must satisfy
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct PropertiesSchemaRequest {
+    /// A JSON Schema object, or `null` to allow any properties object
+    #[validate(custom(function = "valid_properties_schema"))]
+    #[schema(value_type = Option<Object>)]
+    pub properties_schema: Option<JsonValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PropertiesSchemaResponse {
+    pub organization_id: Uuid,
+    #[schema(value_type = Option<Object>)]
+    pub properties_schema: Option<JsonValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UserWithRoles {
     pub user: User,
     pub roles: Vec<Role>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct CreateRoleRequest {
+    #[validate(length(max = 100), custom(function = "not_blank"))]
     pub name: String,
+    #[validate(length(max = 1000))]
     pub description: Option<String>,
     pub is_admin: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct UpdateRoleRequest {
+    #[validate(length(max = 100), custom(function = "not_blank"))]
     pub name: Option<String>,
+    #[validate(length(max = 1000))]
     pub description: Option<String>,
     pub is_admin: Option<bool>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Validate, ToSchema)]
+// One schema rule per scope, each repeating `skip_on_field_errors`
+#[allow(clippy::duplicated_attributes)]
+#[validate(schema(function = "edit_implies_view", skip_on_field_errors = false))]
+#[validate(schema(function = "edit_own_implies_view_own", skip_on_field_errors = false))]
+#[validate(schema(function = "edit_ours_implies_view_ours", skip_on_field_errors = false))]
 pub struct SetPermissionRequest {
+    #[validate(length(min = 1, max = MAX_PAGE_NAME_LENGTH), regex(path = *PAGE_NAME_PATTERN))]
     pub page: String,
     pub can_view: bool,
     pub can_edit: bool,
@@ -180,13 +390,273 @@ pub struct SetPermissionRequest {
     pub can_edit_ours: bool,
 }
 
-#[derive(Deserialize)]
+/// Permission flags for a page given in the request path
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+pub struct PermissionFlags {
+    pub can_view: bool,
+    pub can_edit: bool,
+    pub can_view_own: bool,
+    pub can_edit_own: bool,
+    pub can_view_ours: bool,
+    pub can_edit_ours: bool,
+}
+
+impl PermissionFlags {
+    pub fn for_page(self, page: String) -> SetPermissionRequest {
+        SetPermissionRequest {
+            page,
+            can_view: self.can_view,
+            can_edit: self.can_edit,
+            can_view_own: self.can_view_own,
+            can_edit_own: self.can_edit_own,
+            can_view_ours: self.can_view_ours,
+            can_edit_ours: self.can_edit_ours,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+#[validate(schema(function = "unique_pages", skip_on_field_errors = false))]
+pub struct ReplacePermissionsRequest {
+    /// Version token from a previous read; the update is rejected if the matrix changed since
+    pub version: Option<String>,
+    #[validate(nested)]
+    pub permissions: Vec<SetPermissionRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PermissionMatrixResponse {
+    pub role_id: Uuid,
+    pub version: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PermissionChange {
+    pub page: String,
+    pub before: Permission,
+    pub after: Permission,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, ToSchema)]
+pub struct PermissionDiff {
+    pub added: Vec<Permission>,
+    pub updated: Vec<PermissionChange>,
+    pub removed: Vec<Permission>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReplacePermissionsResponse {
+    pub role_id: Uuid,
+    pub version: String,
+    pub permissions: Vec<Permission>,
+    pub diff: PermissionDiff,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct AssignRoleRequest {
     pub role_id: Uuid,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
 pub struct PaginationQuery {
+    /// Opaque cursor taken from the previous page's `next_cursor`
+    pub cursor: Option<String>,
+    /// Items per page (1-100, default 50)
+    pub limit: Option<u32>,
+    /// Also count every item of the collection
+    pub include_total: Option<bool>,
+}
+
+/// `page`/`per_page` of the unversioned collection paths
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LegacyPageQuery {
+    /// 1-based page number (default 1)
     pub page: Option<u32>,
+    /// Items per page (1-100, default 50)
     pub per_page: Option<u32>,
 }
+
+/// Longest accepted search term
+pub const MAX_SEARCH_LENGTH: u64 = 200;
+
+#[derive(Debug, Default, Serialize, Deserialize, Validate, IntoParams)]
+pub struct RoleListQuery {
+    /// Case-insensitive substring of the role name
+    #[validate(length(max = MAX_SEARCH_LENGTH))]
+    pub q: Option<String>,
+    /// `name` (default) or `created_at`; prefix with `-` for descending order
+    pub sort: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Validate, IntoParams)]
+pub struct UserListQuery {
+    /// Case-insensitive substring of the full name or email
+    #[validate(length(max = MAX_SEARCH_LENGTH))]
+    pub q: Option<String>,
+    /// Only users holding this role
+    pub role_id: Option<Uuid>,
+    pub organization_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+    /// Created at or after this instant
+    pub created_from: Option<DateTime<Utc>>,
+    /// Created before this instant
+    pub created_to: Option<DateTime<Utc>>,
+    /// `name` (default), `email` or `created_at`; prefix with `-` for descending order
+    pub sort: Option<String>,
+}
+
+/// One page of a collection, in a stable order
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the following page; `null` on the last page
+    pub next_cursor: Option<String>,
+    /// Number of items in the whole collection, when `include_total=true`
+    pub total: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct AuditEventQuery {
+    pub actor_id: Option<Uuid>,
+    pub actor_sub: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<Uuid>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct AuditChainQuery {
+    pub organization_id: Option<Uuid>,
+}
+
+// Support tickets
+
+/// Where a ticket stands; see `TicketStatus::can_transition_to` for the workflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "ticket_status", rename_all = "snake_case")]
+pub enum TicketStatus {
+    Open,
+    InProgress,
+    WaitingOnRequester,
+    Resolved,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "ticket_priority", rename_all = "snake_case")]
+pub enum TicketPriority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct SupportTicket {
+    pub id: Uuid,
+    pub organization_id: Option<Uuid>,
+    /// User who raised the ticket; `null` once their account is deleted
+    pub requester_id: Option<Uuid>,
+    /// Support staff member working on the ticket
+    pub assignee_id: Option<Uuid>,
+    pub subject: String,
+    pub status: TicketStatus,
+    pub priority: TicketPriority,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TicketComment {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    /// Comment this one replies to
+    pub parent_id: Option<Uuid>,
+    pub author_id: Option<Uuid>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Attachment metadata; the content is downloaded separately
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TicketAttachment {
+    pub id: Uuid,
+    pub ticket_id: Uuid,
+    pub uploaded_by: Option<Uuid>,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    /// Hex-encoded SHA-256 of the content
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TicketDetail {
+    pub ticket: SupportTicket,
+    /// Oldest first; the first comment is the message the ticket was opened with
+    pub comments: Vec<TicketComment>,
+    pub attachments: Vec<TicketAttachment>,
+}
+
+/// Longest accepted comment or opening message
+pub const MAX_TICKET_COMMENT_LENGTH: u64 = 10_000;
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateTicketRequest {
+    #[validate(length(max = 200), custom(function = "not_blank"))]
+    pub subject: String,
+    /// Becomes the ticket's first comment
+    #[validate(length(max = MAX_TICKET_COMMENT_LENGTH), custom(function = "not_blank"))]
+    pub message: String,
+    /// Defaults to `normal`
+    pub priority: Option<TicketPriority>,
+}
+
+/// Changes to a ticket. Requesters may only close their ticket or reopen a
+/// resolved one; priority and assignment are for support staff.
+#[derive(Debug, Default, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateTicketRequest {
+    pub status: Option<TicketStatus>,
+    pub priority: Option<TicketPriority>,
+    /// Staff member to assign, or `null` to unassign
+    #[serde(
+        default,
+        deserialize_with = "explicit_null",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<Uuid>)]
+    pub assignee_id: Option<Option<Uuid>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateTicketCommentRequest {
+    #[validate(length(max = MAX_TICKET_COMMENT_LENGTH), custom(function = "not_blank"))]
+    pub body: String,
+    /// Comment of the same ticket this one replies to
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+pub struct TicketListQuery {
+    pub status: Option<TicketStatus>,
+    pub priority: Option<TicketPriority>,
+    pub assignee_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, IntoParams)]
+pub struct AttachmentUploadQuery {
+    /// Name the file is offered for download under
+    #[validate(length(max = 255), custom(function = "not_blank"))]
+    pub filename: String,
+}
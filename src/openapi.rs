@@ -0,0 +1,131 @@
+use axum::{routing::get, Json, Router};
+use std::sync::Arc;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_redoc::{Redoc, Servable};
+
+use crate::handlers::{admin, support, system};
+use crate::models::AppState;
+
+/// OpenAPI document of the versioned API, generated from the handler
+/// annotations and the `ToSchema` models
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Rust Backend Template API",
+        description = "Versioned REST API. Errors are RFC 7807 problem documents.",
+        license(name = "MIT")
+    ),
+    paths(
+        system::health_check,
+        system::system_version,
+        system::validate_token,
+        system::system_uptime,
+        system::system_onboarding,
+        system::get_profile,
+        system::update_profile,
+        system::get_preferences,
+        system::update_preferences,
+        admin::list_roles,
+        admin::create_role,
+        admin::get_role,
+        admin::update_role,
+        admin::delete_role,
+        admin::get_role_permissions,
+        admin::replace_role_permissions,
+        admin::put_role_permission,
+        admin::list_users,
+        admin::update_user,
+        admin::deactivate_user,
+        admin::reactivate_user,
+        admin::anonymize_user,
+        admin::delete_user,
+        admin::get_user_roles,
+        admin::put_user_role,
+        admin::delete_user_role,
+        admin::get_properties_schema,
+        admin::put_properties_schema,
+        admin::get_default_preferences,
+        admin::put_default_preferences,
+        admin::list_audit_events,
+        admin::verify_audit_chain,
+        support::list_tickets,
+        support::create_ticket,
+        support::get_ticket,
+        support::update_ticket,
+        support::create_comment,
+        support::upload_attachment,
+        support::download_attachment,
+    ),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "system", description = "Health, version, token validation and the caller's profile and preferences"),
+        (name = "roles", description = "Roles and their page permissions (admin)"),
+        (name = "users", description = "Users, their lifecycle and role assignments (admin)"),
+        (name = "organizations", description = "Organization settings (admin)"),
+        (name = "audit", description = "Audit log of administrative changes (admin)"),
+        (name = "support", description = "Support tickets, their comments and attachments"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the JWT bearer scheme referenced by protected operations
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Routes serving the spec at `/openapi.json` and a Redoc UI at `/docs`
+pub fn openapi_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/openapi.json", get(|| async { Json(ApiDoc::openapi()) }))
+        .merge(Redoc::with_url("/docs", ApiDoc::openapi()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Committed copy of the spec; regenerate with `UPDATE_OPENAPI=1 cargo test`
+    const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    #[test]
+    fn test_openapi_spec_matches_snapshot() {
+        let generated = ApiDoc::openapi().to_pretty_json().expect("spec serializes") + "\n";
+
+        if std::env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(SNAPSHOT_PATH, &generated).expect("write openapi.json");
+            return;
+        }
+
+        let committed = std::fs::read_to_string(SNAPSHOT_PATH).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "openapi.json is out of date; run `UPDATE_OPENAPI=1 cargo test` and review the diff"
+        );
+    }
+
+    #[test]
+    fn test_protected_operations_require_bearer_auth() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        assert!(spec["components"]["securitySchemes"]["bearer_auth"].is_object());
+        assert!(spec["paths"]["/api/v1/admin/roles"]["get"]["security"].is_array());
+        assert!(spec["paths"]["/api/v1/health"]["get"]["security"].is_null());
+        assert!(spec["components"]["schemas"]["Role"]["properties"]["organization_id"].is_object());
+    }
+}
@@ -0,0 +1,415 @@
+//! Keyset pagination and sorting shared by the collection endpoints.
+//!
+//! Each collection is walked in a fixed order that ends with the row id, so
+//! every row has a unique position. A cursor is the sort key of the last row
+//! of a page, base64url-encoded JSON; clients treat it as opaque. Handlers
+//! fetch `limit + 1` rows after the cursor's key and hand them to
+//! [`split_page`], which tells whether another page follows.
+//!
+//! Collections that can be sorted by several columns declare them as a
+//! whitelist of [`SortField`]s. Only those column names ever reach the SQL
+//! text; the values clients send are always bound as parameters.
+
+use axum::{
+    http::{header, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::error::{AppError, FieldError};
+use crate::models::{LegacyPageQuery, Page, PaginationQuery};
+
+/// Page size when the request does not give a `limit`
+pub const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+/// Largest page size a client can request
+pub const MAX_PAGE_LIMIT: u32 = 100;
+
+/// Query parameter carrying the cursor
+const CURSOR_PARAM: &str = "cursor";
+
+impl PaginationQuery {
+    /// Requested page size, clamped to `1..=MAX_PAGE_LIMIT`
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT) as i64
+    }
+
+    /// Sort key of the last row of the previous page, if a cursor was given
+    pub fn after<K: DeserializeOwned>(&self) -> Result<Option<K>, AppError> {
+        self.cursor.as_deref().map(decode_cursor).transpose()
+    }
+
+    /// Like [`after`](Self::after) for a sorted collection. Cursors issued
+    /// for a different sort are rejected.
+    pub fn after_sorted(&self, sort: &Sort) -> Result<Option<SortKey>, AppError> {
+        let after: Option<SortKey> = self.after()?;
+        match after {
+            Some((spec, _, _)) if spec != sort.spec() => Err(invalid_cursor()),
+            after => Ok(after),
+        }
+    }
+
+    pub fn include_total(&self) -> bool {
+        self.include_total.unwrap_or(false)
+    }
+}
+
+impl LegacyPageQuery {
+    /// `LIMIT` and `OFFSET` of the requested page, with the same page size
+    /// bounds as cursor pagination
+    pub fn limit_offset(&self) -> (i64, i64) {
+        let per_page = self
+            .per_page
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT) as i64;
+        let page = self.page.unwrap_or(1).max(1) as i64;
+        (per_page, (page - 1) * per_page)
+    }
+}
+
+/// Cursor key of a sorted collection: the sort it was issued for, the sort
+/// column's value as text and the row id
+pub type SortKey = (String, String, Uuid);
+
+/// A column a collection can be sorted by
+#[derive(Debug)]
+pub struct SortField {
+    /// Name used in the `sort` query parameter
+    pub name: &'static str,
+    /// Column to order by
+    pub column: &'static str,
+    /// SQL type the cursor's text value is cast to when comparing
+    pub sql_type: &'static str,
+}
+
+/// A `sort` query parameter resolved against a collection's whitelist:
+/// a field name, prefixed with `-` for descending order
+#[derive(Debug, Clone, Copy)]
+pub struct Sort {
+    field: &'static SortField,
+    descending: bool,
+}
+
+impl Sort {
+    /// Resolve `param` against `fields`; the first field, ascending, is the default
+    pub fn parse(param: Option<&str>, fields: &'static [SortField]) -> Result<Sort, AppError> {
+        let Some(param) = param.map(str::trim).filter(|p| !p.is_empty()) else {
+            return Ok(Sort {
+                field: &fields[0],
+                descending: false,
+            });
+        };
+        let (name, descending) = match param.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (param, false),
+        };
+
+        fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| Sort { field, descending })
+            .ok_or_else(|| {
+                let names: Vec<&str> = fields.iter().map(|field| field.name).collect();
+                AppError::validation(vec![FieldError::new(
+                    "sort",
+                    "unknown_sort_field",
+                    format!(
+                        "Sort by one of: {}; prefix with - for descending order",
+                        names.join(", ")
+                    ),
+                )])
+            })
+    }
+
+    /// Name of the field sorted by
+    pub fn field(&self) -> &'static str {
+        self.field.name
+    }
+
+    /// The `sort` parameter value, e.g. `-created_at`
+    fn spec(&self) -> String {
+        let prefix = if self.descending { "-" } else { "" };
+        format!("{}{}", prefix, self.field.name)
+    }
+
+    /// Cursor key of a row whose sort column holds `value`
+    pub fn key(&self, value: String, id: Uuid) -> SortKey {
+        (self.spec(), value, id)
+    }
+
+    /// Restrict `query` (which already has a `WHERE` clause) to the rows that
+    /// follow the cursor's row in this order
+    pub fn push_after(&self, query: &mut QueryBuilder<'_, Postgres>, after: Option<SortKey>) {
+        let Some((_, value, id)) = after else { return };
+        let comparison = if self.descending { "<" } else { ">" };
+        query
+            .push(format!(
+                " AND ({}, id) {} (CAST(",
+                self.field.column, comparison
+            ))
+            .push_bind(value)
+            .push(format!(" AS {}), ", self.field.sql_type))
+            .push_bind(id)
+            .push(")");
+    }
+
+    pub fn push_order_by(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        query.push(format!(
+            " ORDER BY {} {}, id {}",
+            self.field.column, direction, direction
+        ));
+    }
+}
+
+/// `ILIKE` pattern matching `term` anywhere, with the wildcards in `term` escaped
+pub fn contains_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+pub fn encode_cursor<K: Serialize>(key: &K) -> String {
+    let json = serde_json::to_vec(key).expect("sort keys serialize to JSON");
+    URL_SAFE_NO_PAD.encode(json)
+}
+
+fn decode_cursor<K: DeserializeOwned>(cursor: &str) -> Result<K, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(invalid_cursor)
+}
+
+fn invalid_cursor() -> AppError {
+    AppError::bad_request(
+        "invalid_cursor",
+        "The cursor is malformed or belongs to a different collection or sort",
+    )
+}
+
+/// Trim `limit + 1` fetched rows down to one page and build the cursor of the
+/// next page from the last row kept, if more rows follow
+pub fn split_page<T, K, F>(mut rows: Vec<T>, limit: i64, key: F) -> (Vec<T>, Option<String>)
+where
+    K: Serialize,
+    F: Fn(&T) -> K,
+{
+    if rows.len() as i64 <= limit {
+        return (rows, None);
+    }
+    rows.truncate(limit as usize);
+    let next_cursor = rows.last().map(|row| encode_cursor(&key(row)));
+    (rows, next_cursor)
+}
+
+/// A page response with RFC 8288 `Link` headers pointing at the first and
+/// next pages. Other query parameters (filters, `limit`) are carried over.
+pub struct Paginated<T> {
+    page: Page<T>,
+    uri: Uri,
+}
+
+impl<T> Paginated<T> {
+    /// `uri` is the request's original URI, as seen before routers were nested
+    pub fn new(page: Page<T>, uri: Uri) -> Self {
+        Paginated { page, uri }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Paginated<T> {
+    fn into_response(self) -> Response {
+        let mut links = vec![format!("<{}>; rel=\"first\"", page_link(&self.uri, None))];
+        if let Some(cursor) = &self.page.next_cursor {
+            links.push(format!(
+                "<{}>; rel=\"next\"",
+                page_link(&self.uri, Some(cursor))
+            ));
+        }
+
+        let mut response = Json(self.page).into_response();
+        if let Ok(value) = HeaderValue::from_str(&links.join(", ")) {
+            response.headers_mut().insert(header::LINK, value);
+        }
+        response
+    }
+}
+
+/// The request URI with its cursor replaced
+fn page_link(uri: &Uri, cursor: Option<&str>) -> String {
+    let params = form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+        .filter(|(name, _)| name != CURSOR_PARAM);
+
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    query.extend_pairs(params);
+    if let Some(cursor) = cursor {
+        query.append_pair(CURSOR_PARAM, cursor);
+    }
+
+    let query = query.finish();
+    if query.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use uuid::Uuid;
+
+    fn query(cursor: Option<&str>, limit: Option<u32>) -> PaginationQuery {
+        PaginationQuery {
+            cursor: cursor.map(str::to_string),
+            limit,
+            include_total: None,
+        }
+    }
+
+    #[test]
+    fn test_limit_is_clamped() {
+        assert_eq!(query(None, None).limit(), DEFAULT_PAGE_LIMIT as i64);
+        assert_eq!(query(None, Some(0)).limit(), 1);
+        assert_eq!(query(None, Some(10_000)).limit(), MAX_PAGE_LIMIT as i64);
+    }
+
+    #[test]
+    fn test_legacy_pages_become_offsets() {
+        let legacy = |page, per_page| LegacyPageQuery { page, per_page }.limit_offset();
+
+        assert_eq!(legacy(None, None), (DEFAULT_PAGE_LIMIT as i64, 0));
+        assert_eq!(legacy(Some(3), Some(20)), (20, 40));
+        assert_eq!(legacy(Some(0), Some(10_000)), (MAX_PAGE_LIMIT as i64, 0));
+    }
+
+    #[test]
+    fn test_cursor_round_trips() {
+        let key = ("Ada Lovelace".to_string(), Uuid::new_v4());
+        let cursor = encode_cursor(&key);
+
+        let after: Option<(String, Uuid)> = query(Some(&cursor), None).after().unwrap();
+
+        assert_eq!(after, Some(key));
+        assert!(cursor
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn test_invalid_cursor_is_rejected() {
+        let wrong_shape = encode_cursor(&42);
+
+        for cursor in ["not base64!", wrong_shape.as_str()] {
+            let error = query(Some(cursor), None)
+                .after::<(String, Uuid)>()
+                .unwrap_err();
+            assert_eq!(error.status, StatusCode::BAD_REQUEST);
+            assert_eq!(error.code, "invalid_cursor");
+        }
+    }
+
+    #[test]
+    fn test_split_page_only_returns_cursor_when_more_rows_follow() {
+        let (rows, next) = split_page(vec![1, 2, 3], 3, |n| *n);
+        assert_eq!(rows, vec![1, 2, 3]);
+        assert_eq!(next, None);
+
+        let (rows, next) = split_page(vec![1, 2, 3, 4], 3, |n| *n);
+        assert_eq!(rows, vec![1, 2, 3]);
+        assert_eq!(next, Some(encode_cursor(&3)));
+    }
+
+    const FIELDS: &[SortField] = &[
+        SortField {
+            name: "name",
+            column: "user_fullname",
+            sql_type: "text",
+        },
+        SortField {
+            name: "created_at",
+            column: "created_at",
+            sql_type: "timestamptz",
+        },
+    ];
+
+    #[test]
+    fn test_sort_accepts_only_whitelisted_fields() {
+        assert_eq!(Sort::parse(None, FIELDS).unwrap().spec(), "name");
+        assert_eq!(
+            Sort::parse(Some("-created_at"), FIELDS).unwrap().spec(),
+            "-created_at"
+        );
+
+        let error = Sort::parse(Some("password; DROP TABLE users"), FIELDS).unwrap_err();
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.errors[0].field, "sort");
+        assert_eq!(error.errors[0].code, "unknown_sort_field");
+    }
+
+    #[test]
+    fn test_sorted_query_binds_cursor_values() {
+        let sort = Sort::parse(Some("-created_at"), FIELDS).unwrap();
+        let key = sort.key("2024-01-01T00:00:00Z".to_string(), Uuid::nil());
+        let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM users WHERE TRUE");
+
+        sort.push_after(&mut query, Some(key));
+        sort.push_order_by(&mut query);
+
+        assert_eq!(
+            query.sql(),
+            "SELECT * FROM users WHERE TRUE AND (created_at, id) < (CAST($1 AS timestamptz), $2) \
+             ORDER BY created_at DESC, id DESC"
+        );
+    }
+
+    #[test]
+    fn test_cursor_from_another_sort_is_rejected() {
+        let by_name = Sort::parse(Some("name"), FIELDS).unwrap();
+        let by_date = Sort::parse(Some("created_at"), FIELDS).unwrap();
+        let cursor = encode_cursor(&by_name.key("Ada".to_string(), Uuid::nil()));
+        let params = query(Some(&cursor), None);
+
+        assert!(params.after_sorted(&by_name).unwrap().is_some());
+        assert_eq!(
+            params.after_sorted(&by_date).unwrap_err().code,
+            "invalid_cursor"
+        );
+    }
+
+    #[test]
+    fn test_contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("ada"), "%ada%");
+        assert_eq!(contains_pattern("50%_off\\"), "%50\\%\\_off\\\\%");
+    }
+
+    #[test]
+    fn test_page_link_replaces_cursor_and_keeps_filters() {
+        let uri: Uri = "/api/v1/admin/audit-events?action=role.create&cursor=old&limit=10"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            page_link(&uri, Some("new")),
+            "/api/v1/admin/audit-events?action=role.create&limit=10&cursor=new"
+        );
+        assert_eq!(
+            page_link(&uri, None),
+            "/api/v1/admin/audit-events?action=role.create&limit=10"
+        );
+        assert_eq!(
+            page_link(&"/api/v1/admin/roles".parse().unwrap(), None),
+            "/api/v1/admin/roles"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! User preferences resolved in layers: what the user set, then the
+//! organization's defaults, then the built-in defaults.
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value as JsonValue};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::{
+    PreferenceOverrides, Preferences, PreferencesResponse, UpdatePreferencesRequest,
+};
+
+impl PreferenceOverrides {
+    /// Fill the keys set at this level into `base`
+    pub fn apply_to(&self, base: Preferences) -> Preferences {
+        Preferences {
+            theme: self.theme.unwrap_or(base.theme),
+            language: self.language.unwrap_or(base.language),
+        }
+    }
+
+    /// Apply a partial update: absent keys are kept, `null` clears a key
+    pub fn update(&mut self, update: &UpdatePreferencesRequest) {
+        if let Some(theme) = update.theme {
+            self.theme = theme;
+        }
+        if let Some(language) = update.language {
+            self.language = language;
+        }
+    }
+
+    /// Read overrides stored by this crate. Keys that no longer parse, such as
+    /// a language that was removed, are dropped rather than failing the request.
+    pub fn from_stored(value: JsonValue) -> Self {
+        let JsonValue::Object(map) = value else {
+            return PreferenceOverrides::default();
+        };
+        PreferenceOverrides {
+            theme: stored_key(&map, "theme"),
+            language: stored_key(&map, "language"),
+        }
+    }
+}
+
+fn stored_key<T: DeserializeOwned>(map: &Map<String, JsonValue>, name: &str) -> Option<T> {
+    map.get(name)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+}
+
+/// Resolve a user's effective preferences
+pub fn resolve(
+    organization: &PreferenceOverrides,
+    user: PreferenceOverrides,
+) -> PreferencesResponse {
+    let defaults = organization.apply_to(Preferences::default());
+    PreferencesResponse {
+        preferences: user.apply_to(defaults.clone()),
+        defaults,
+        overrides: user,
+    }
+}
+
+/// Load the overrides of an organization; users without one get none
+pub async fn organization_overrides(
+    conn: &mut PgConnection,
+    organization_id: Option<Uuid>,
+) -> Result<PreferenceOverrides, AppError> {
+    let Some(organization_id) = organization_id else {
+        return Ok(PreferenceOverrides::default());
+    };
+    let stored: Option<JsonValue> =
+        sqlx::query_scalar("SELECT default_preferences FROM organizations WHERE id = $1")
+            .bind(organization_id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(stored
+        .map(PreferenceOverrides::from_stored)
+        .unwrap_or_default())
+}
+
+/// Load the overrides a user has set
+pub async fn user_overrides(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<PreferenceOverrides, AppError> {
+    let stored: Option<JsonValue> =
+        sqlx::query_scalar("SELECT preferences FROM user_preferences WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(stored
+        .map(PreferenceOverrides::from_stored)
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Language, Theme};
+
+    #[test]
+    fn test_user_overrides_organization_overrides_builtin() {
+        let organization = PreferenceOverrides {
+            theme: Some(Theme::Dark),
+            language: Some(Language::Pt),
+        };
+        let user = PreferenceOverrides {
+            theme: None,
+            language: Some(Language::Es),
+        };
+
+        let resolved = resolve(&organization, user.clone());
+
+        assert_eq!(resolved.defaults.theme, Theme::Dark);
+        assert_eq!(resolved.defaults.language, Language::Pt);
+        assert_eq!(resolved.preferences.theme, Theme::Dark);
+        assert_eq!(resolved.preferences.language, Language::Es);
+        assert_eq!(resolved.overrides, user);
+
+        let resolved = resolve(
+            &PreferenceOverrides::default(),
+            PreferenceOverrides::default(),
+        );
+        assert_eq!(resolved.preferences, Preferences::default());
+    }
+
+    #[test]
+    fn test_update_distinguishes_absent_and_null() {
+        let mut overrides = PreferenceOverrides {
+            theme: Some(Theme::Light),
+            language: Some(Language::Pt),
+        };
+        let update: UpdatePreferencesRequest =
+            serde_json::from_value(serde_json::json!({"theme": null})).unwrap();
+
+        overrides.update(&update);
+
+        assert_eq!(overrides.theme, None);
+        assert_eq!(overrides.language, Some(Language::Pt));
+    }
+
+    #[test]
+    fn test_unknown_values_are_rejected_in_requests() {
+        let update = serde_json::from_value::<UpdatePreferencesRequest>(
+            serde_json::json!({"theme": "pink"}),
+        );
+        assert!(update.is_err());
+
+        let update = serde_json::from_value::<UpdatePreferencesRequest>(
+            serde_json::json!({"font_size": 12}),
+        );
+        assert!(update.is_err());
+    }
+
+    #[test]
+    fn test_stale_stored_values_are_dropped() {
+        let stored = serde_json::json!({"theme": "dark", "language": "klingon"});
+
+        let overrides = PreferenceOverrides::from_stored(stored);
+
+        assert_eq!(overrides.theme, Some(Theme::Dark));
+        assert_eq!(overrides.language, None);
+    }
+}
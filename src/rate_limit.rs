@@ -0,0 +1,862 @@
+//! Rate limiting.
+//!
+//! Each request is matched against the configured rules, the first match
+//! applying. A rule counts requests per client, identified by IP address,
+//! token `sub`, API key or organization, falling back to the IP address when
+//! the request does not carry that identity. Its quota is enforced with a
+//! token bucket, which allows bursts up to the limit, or a sliding window,
+//! which approximates a rolling count from the current and previous fixed
+//! window.
+//!
+//! Responses carry `RateLimit-Limit`, `RateLimit-Remaining`,
+//! `RateLimit-Reset` and `RateLimit-Policy`. Refused requests get a 429
+//! problem with `Retry-After`. Counters live in memory, per process, or in
+//! PostgreSQL, shared by every replica. When the store fails the request is
+//! let through, unless the rule fails closed: rules guarding credential
+//! checks answer 503 instead, so an outage or an exhausted pool does not
+//! switch the brute-force protection off.
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::config::RateLimitConfig;
+use crate::error::AppError;
+use crate::middleware::bearer_claims;
+use crate::models::AppState;
+use crate::shutdown::Shutdown;
+
+/// How often expired counters are deleted
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `Retry-After` of requests refused because the store failed, in seconds
+pub const STORE_FAILURE_RETRY_AFTER: u64 = 5;
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("ratelimit-reset");
+const POLICY_HEADER: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    TokenBucket,
+    SlidingWindow,
+}
+
+/// What requests are counted by. Only identities the server verifies are
+/// offered: a client-chosen header such as `X-API-Key` would let a client
+/// start a fresh bucket by changing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyKind {
+    Ip,
+    /// `sub` of a valid bearer token
+    Sub,
+    /// `organization` of a valid bearer token, then its `sub`
+    Organization,
+}
+
+/// Where counters are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Memory,
+    Postgres,
+}
+
+/// A quota for the routes it names
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitRule {
+    /// Label in counter keys and metrics
+    pub name: String,
+    /// Route templates as registered, e.g. `/api/v1/admin/roles/:role_id`,
+    /// or prefixes ending in `/*`
+    pub routes: Vec<String>,
+    /// Methods the rule applies to, all when empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+    pub key: KeyKind,
+    pub algorithm: Algorithm,
+    /// Requests allowed per window
+    pub limit: u32,
+    pub window_secs: u64,
+    /// Answer 503 while the store is failing instead of letting requests
+    /// through
+    #[serde(default)]
+    pub fail_closed: bool,
+}
+
+impl RateLimitRule {
+    pub fn matches(&self, method: &Method, route: &str) -> bool {
+        let method_matches = self.methods.is_empty()
+            || self
+                .methods
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(method.as_str()));
+        method_matches
+            && self
+                .routes
+                .iter()
+                .any(|pattern| match pattern.strip_suffix("/*") {
+                    Some(prefix) => {
+                        route == prefix
+                            || route
+                                .strip_prefix(prefix)
+                                .is_some_and(|rest| rest.starts_with('/'))
+                    }
+                    None => pattern == route,
+                })
+    }
+
+    pub fn quota(&self) -> Quota {
+        Quota {
+            algorithm: self.algorithm,
+            limit: self.limit,
+            window: self.window_secs as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub algorithm: Algorithm,
+    pub limit: u32,
+    /// Seconds
+    pub window: f64,
+}
+
+/// Stored state of one client under one rule. A zeroed counter is a fresh
+/// one for both algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Counter {
+    /// Tokens left, or hits in the current window
+    pub value: f64,
+    /// Hits in the previous window
+    pub previous: f64,
+    /// Last refill, or start of the current window, in Unix seconds
+    pub since: f64,
+}
+
+/// Outcome of one request against a quota; times in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the quota is fully available again
+    pub reset: f64,
+    /// Until the next request would be allowed, 0 when this one was
+    pub retry_after: f64,
+}
+
+impl Quota {
+    /// Count a request at `now`, in Unix seconds
+    pub fn hit(&self, counter: &mut Counter, now: f64) -> Decision {
+        match self.algorithm {
+            Algorithm::TokenBucket => self.take_token(counter, now),
+            Algorithm::SlidingWindow => self.count_in_window(counter, now),
+        }
+    }
+
+    /// How long a counter must be kept after its last update
+    pub fn ttl(&self) -> f64 {
+        2.0 * self.window
+    }
+
+    fn take_token(&self, counter: &mut Counter, now: f64) -> Decision {
+        let limit = f64::from(self.limit);
+        let rate = limit / self.window;
+        let elapsed = (now - counter.since).max(0.0);
+        let mut tokens = (counter.value + elapsed * rate).min(limit);
+        let allowed = tokens >= 1.0;
+        if allowed {
+            tokens -= 1.0;
+        }
+        *counter = Counter {
+            value: tokens,
+            previous: 0.0,
+            since: now,
+        };
+
+        Decision {
+            allowed,
+            limit: self.limit,
+            remaining: tokens.floor() as u32,
+            reset: (limit - tokens) / rate,
+            retry_after: if allowed { 0.0 } else { (1.0 - tokens) / rate },
+        }
+    }
+
+    fn count_in_window(&self, counter: &mut Counter, now: f64) -> Decision {
+        let limit = f64::from(self.limit);
+        let window = self.window;
+        let start = (now / window).floor() * window;
+        if counter.since != start {
+            let consecutive = (start - window - counter.since).abs() < 1e-6;
+            counter.previous = if consecutive { counter.value } else { 0.0 };
+            counter.value = 0.0;
+            counter.since = start;
+        }
+
+        // The previous window's hits, weighted by how much of it still
+        // falls within the rolling window
+        let weight = 1.0 - (now - start) / window;
+        let used = counter.previous * weight + counter.value;
+        let allowed = used + 1.0 <= limit;
+        if allowed {
+            counter.value += 1.0;
+        }
+
+        let end = start + window;
+        let retry_at = if counter.value + 1.0 <= limit && counter.previous > 0.0 {
+            start + window * (1.0 - (limit - counter.value - 1.0) / counter.previous)
+        } else if counter.value + 1.0 > limit {
+            end + window * (1.0 - (limit - 1.0) / counter.value)
+        } else {
+            end
+        };
+
+        Decision {
+            allowed,
+            limit: self.limit,
+            remaining: (limit - used - if allowed { 1.0 } else { 0.0 }).max(0.0) as u32,
+            reset: end - now,
+            retry_after: if allowed {
+                0.0
+            } else {
+                (retry_at - now).max(0.0)
+            },
+        }
+    }
+}
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// Where counters are kept
+pub trait RateLimitStore: Send + Sync {
+    /// Count a request under `key` against `quota`
+    fn hit<'a>(&'a self, key: &'a str, quota: Quota) -> StoreFuture<'a, Decision>;
+
+    /// Delete expired counters, returning how many
+    fn purge(&self) -> StoreFuture<'_, u64>;
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Counters of this process only
+#[derive(Default)]
+pub struct MemoryStore {
+    counters: Mutex<HashMap<String, (Counter, f64)>>,
+}
+
+impl MemoryStore {
+    /// Count a request at `now`, in Unix seconds
+    pub fn hit_at(&self, key: &str, quota: Quota, now: f64) -> Decision {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let (counter, expires_at) = counters.entry(key.to_string()).or_default();
+        let decision = quota.hit(counter, now);
+        *expires_at = now + quota.ttl();
+        decision
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn hit<'a>(&'a self, key: &'a str, quota: Quota) -> StoreFuture<'a, Decision> {
+        let decision = self.hit_at(key, quota, unix_now());
+        Box::pin(async move { Ok(decision) })
+    }
+
+    fn purge(&self) -> StoreFuture<'_, u64> {
+        let now = unix_now();
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let before = counters.len();
+        counters.retain(|_, (_, expires_at)| *expires_at > now);
+        let purged = (before - counters.len()) as u64;
+        Box::pin(async move { Ok(purged) })
+    }
+}
+
+/// Counters in the `rate_limit_counters` table, shared by every replica.
+/// Each hit locks its row, and the database clock is used so replicas with
+/// skewed clocks agree.
+pub struct PostgresStore {
+    pool: sqlx::PgPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        PostgresStore { pool }
+    }
+
+    async fn hit_row(&self, key: &str, quota: Quota) -> Result<Decision, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO rate_limit_counters (bucket_key, value, previous, since, expires_at)
+             VALUES ($1, 0, 0, 0, NOW())
+             ON CONFLICT (bucket_key) DO NOTHING",
+        )
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+
+        let (value, previous, since, now): (f64, f64, f64, f64) = sqlx::query_as(
+            "SELECT value, previous, since, EXTRACT(EPOCH FROM clock_timestamp())::FLOAT8
+             FROM rate_limit_counters
+             WHERE bucket_key = $1
+             FOR UPDATE",
+        )
+        .bind(key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let mut counter = Counter {
+            value,
+            previous,
+            since,
+        };
+        let decision = quota.hit(&mut counter, now);
+
+        sqlx::query(
+            "UPDATE rate_limit_counters
+             SET value = $2, previous = $3, since = $4, expires_at = to_timestamp($5)
+             WHERE bucket_key = $1",
+        )
+        .bind(key)
+        .bind(counter.value)
+        .bind(counter.previous)
+        .bind(counter.since)
+        .bind(now + quota.ttl())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(decision)
+    }
+}
+
+impl RateLimitStore for PostgresStore {
+    fn hit<'a>(&'a self, key: &'a str, quota: Quota) -> StoreFuture<'a, Decision> {
+        Box::pin(async move { self.hit_row(key, quota).await.map_err(|e| e.to_string()) })
+    }
+
+    fn purge(&self) -> StoreFuture<'_, u64> {
+        Box::pin(async move {
+            sqlx::query("DELETE FROM rate_limit_counters WHERE expires_at < NOW()")
+                .execute(&self.pool)
+                .await
+                .map(|result| result.rows_affected())
+                .map_err(|e| e.to_string())
+        })
+    }
+}
+
+struct Limits {
+    rules: Vec<RateLimitRule>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+/// Rules and their store, shared through `AppState`. The default limits
+/// nothing.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    limits: Option<Arc<Limits>>,
+}
+
+impl RateLimiter {
+    pub fn new(rules: Vec<RateLimitRule>, store: Arc<dyn RateLimitStore>) -> Self {
+        RateLimiter {
+            limits: Some(Arc::new(Limits { rules, store })),
+        }
+    }
+
+    /// The configured limiter; the PostgreSQL backend keeps its counters in
+    /// `pool`
+    pub fn from_config(config: &RateLimitConfig, pool: &sqlx::PgPool) -> Self {
+        if !config.enabled {
+            return RateLimiter::default();
+        }
+        let store: Arc<dyn RateLimitStore> = match config.backend {
+            Backend::Memory => Arc::new(MemoryStore::default()),
+            Backend::Postgres => Arc::new(PostgresStore::new(pool.clone())),
+        };
+        RateLimiter::new(config.rules.clone(), store)
+    }
+
+    fn rule(&self, method: &Method, route: &str) -> Option<(&RateLimitRule, &dyn RateLimitStore)> {
+        let limits = self.limits.as_ref()?;
+        let rule = limits
+            .rules
+            .iter()
+            .find(|rule| rule.matches(method, route))?;
+        Some((rule, limits.store.as_ref()))
+    }
+}
+
+/// Delete expired counters every [`PURGE_INTERVAL`] until shutdown begins
+pub async fn purge_expired(limiter: RateLimiter, shutdown: Shutdown) {
+    let Some(limits) = limiter.limits else {
+        return;
+    };
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(PURGE_INTERVAL) => {}
+            _ = shutdown.begun() => return,
+        }
+        if let Err(e) = limits.store.purge().await {
+            tracing::warn!("Failed to purge rate limit counters: {}", e);
+        }
+    }
+}
+
+/// Count the request against the first matching rule, answering 429 once
+/// its quota is used up. Add it with `Router::layer` so the matched route
+/// is known.
+pub async fn rate_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let Some((rule, store)) = state.rate_limiter.rule(req.method(), &route) else {
+        return next.run(req).await;
+    };
+
+    let key = bucket_key(rule, &state, &req);
+    let decision = match store.hit(&key, rule.quota()).await {
+        Ok(decision) => decision,
+        Err(e) if rule.fail_closed => {
+            tracing::warn!(rule = %rule.name, "Rate limit store failed, refusing the request: {}", e);
+            let mut response = AppError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "rate_limit_unavailable",
+                "Requests to this endpoint cannot be counted right now, retry later",
+            )
+            .into_response();
+            response.headers_mut().insert(
+                axum::http::header::RETRY_AFTER,
+                HeaderValue::from(STORE_FAILURE_RETRY_AFTER),
+            );
+            return response;
+        }
+        Err(e) => {
+            tracing::warn!(rule = %rule.name, "Rate limit store failed, letting the request through: {}", e);
+            return next.run(req).await;
+        }
+    };
+
+    if !decision.allowed {
+        state.metrics.record_rate_limited(&rule.name);
+        let retry_after = decision.retry_after.ceil().max(1.0) as u64;
+        let mut response = AppError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            format!("Too many requests, retry in {} seconds", retry_after),
+        )
+        .into_response();
+        set_headers(response.headers_mut(), rule, &decision);
+        response.headers_mut().insert(
+            axum::http::header::RETRY_AFTER,
+            HeaderValue::from(retry_after),
+        );
+        return response;
+    }
+
+    let mut response = next.run(req).await;
+    set_headers(response.headers_mut(), rule, &decision);
+    response
+}
+
+/// `<rule>:<kind>:<client>`, the kind being the one actually used
+fn bucket_key(rule: &RateLimitRule, state: &AppState, req: &Request) -> String {
+    let headers = req.headers();
+    let ip = || {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0);
+        (
+            "ip",
+            state
+                .trusted_proxies
+                .client_ip(headers, peer)
+                .map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+        )
+    };
+    let claims = || bearer_claims(headers, state.jwt_secret.get().expose());
+
+    let (kind, client) = match rule.key {
+        KeyKind::Ip => ip(),
+        KeyKind::Sub => claims()
+            .map(|claims| ("sub", claims.sub))
+            .unwrap_or_else(ip),
+        KeyKind::Organization => match claims() {
+            Some(claims) => match claims.organization {
+                Some(organization) => ("organization", organization),
+                None => ("sub", claims.sub),
+            },
+            None => ip(),
+        },
+    };
+    format!("{}:{}:{}", rule.name, kind, client)
+}
+
+fn set_headers(headers: &mut HeaderMap, rule: &RateLimitRule, decision: &Decision) {
+    headers.insert(LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(decision.remaining));
+    headers.insert(
+        RESET_HEADER,
+        HeaderValue::from(decision.reset.ceil().max(0.0) as u64),
+    );
+    if let Ok(policy) = HeaderValue::from_str(&format!("{};w={}", rule.limit, rule.window_secs)) {
+        headers.insert(POLICY_HEADER, policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_ip::TrustedProxies;
+    use crate::metrics::Metrics;
+    use axum::{body::Body, routing::post, Router};
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    fn quota(algorithm: Algorithm, limit: u32, window: f64) -> Quota {
+        Quota {
+            algorithm,
+            limit,
+            window,
+        }
+    }
+
+    #[test]
+    fn test_token_bucket_allows_bursts_then_refills() {
+        let quota = quota(Algorithm::TokenBucket, 3, 60.0);
+        let mut counter = Counter::default();
+
+        for remaining in [2, 1, 0] {
+            let decision = quota.hit(&mut counter, 1000.0);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let refused = quota.hit(&mut counter, 1000.0);
+        assert!(!refused.allowed);
+        assert!((refused.retry_after - 20.0).abs() < 1e-9);
+        assert!((refused.reset - 60.0).abs() < 1e-9);
+
+        // One token comes back every 20 seconds
+        assert!(quota.hit(&mut counter, 1020.0).allowed);
+        assert!(!quota.hit(&mut counter, 1021.0).allowed);
+    }
+
+    #[test]
+    fn test_sliding_window_weighs_the_previous_window() {
+        let quota = quota(Algorithm::SlidingWindow, 10, 60.0);
+        let mut counter = Counter::default();
+
+        for _ in 0..10 {
+            assert!(quota.hit(&mut counter, 1230.0).allowed);
+        }
+        let refused = quota.hit(&mut counter, 1235.0);
+        assert!(!refused.allowed);
+        assert_eq!(refused.remaining, 0);
+        assert!((refused.reset - 25.0).abs() < 1e-9);
+
+        // 15 seconds into the next window, 75% of the previous 10 hits count
+        let decision = quota.hit(&mut counter, 1275.0);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+        assert!(quota.hit(&mut counter, 1275.0).allowed);
+        let refused = quota.hit(&mut counter, 1275.0);
+        assert!(!refused.allowed);
+        // 9.5 of 10 are used, and the previous window's weight loses half a
+        // hit in 3 seconds
+        assert!((refused.retry_after - 3.0).abs() < 1e-9);
+        assert!(quota.hit(&mut counter, 1281.0).allowed);
+
+        // Two windows later nothing is left
+        let decision = quota.hit(&mut counter, 1400.0);
+        assert_eq!(decision.remaining, 9);
+    }
+
+    #[test]
+    fn test_rules_match_route_templates() {
+        let rule = RateLimitRule {
+            name: "admin".to_string(),
+            routes: vec!["/api/v1/admin/*".to_string(), "/validate-token".to_string()],
+            methods: vec!["post".to_string()],
+            key: KeyKind::Ip,
+            algorithm: Algorithm::TokenBucket,
+            limit: 1,
+            window_secs: 1,
+            fail_closed: false,
+        };
+        assert!(rule.matches(&Method::POST, "/api/v1/admin/roles/:role_id"));
+        assert!(rule.matches(&Method::POST, "/api/v1/admin"));
+        assert!(rule.matches(&Method::POST, "/validate-token"));
+        assert!(!rule.matches(&Method::GET, "/api/v1/admin/roles"));
+        assert!(!rule.matches(&Method::POST, "/api/v1/administrators"));
+        assert!(!rule.matches(&Method::POST, "/api/v1/validate-token"));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_purges_expired_counters() {
+        let store = MemoryStore::default();
+        let quota = quota(Algorithm::SlidingWindow, 5, 1.0);
+        store.hit_at("old", quota, 0.0);
+        store.hit_at("fresh", quota, unix_now());
+
+        assert_eq!(store.counters.lock().unwrap().len(), 2);
+        assert_eq!(store.purge().await.unwrap(), 1);
+        assert!(store.counters.lock().unwrap().contains_key("fresh"));
+    }
+
+    fn limited_state(limiter: RateLimiter) -> Arc<AppState> {
+        Arc::new(AppState {
+            jwt_secret: "secret".into(),
+            start_time: SystemTime::now(),
+            db_pool: PgPoolOptions::new()
+                .connect_lazy("postgres://unused@127.0.0.1:1/unused")
+                .unwrap(),
+            metrics: Metrics::default(),
+            shutdown: Default::default(),
+            rate_limiter: limiter,
+            // Where the gateway connects from
+            trusted_proxies: TrustedProxies::parse(&["10.0.0.0/8"]).unwrap(),
+        })
+    }
+
+    fn limited_app(state: Arc<AppState>) -> Router {
+        Router::new()
+            .route("/validate-token", post(|| async { "checked" }))
+            .route("/anything", post(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                rate_limit_middleware,
+            ))
+            .with_state(state)
+    }
+
+    fn rule(
+        name: &str,
+        route: &str,
+        key: KeyKind,
+        algorithm: Algorithm,
+        limit: u32,
+        fail_closed: bool,
+    ) -> RateLimitRule {
+        RateLimitRule {
+            name: name.to_string(),
+            routes: vec![route.to_string()],
+            methods: Vec::new(),
+            key,
+            algorithm,
+            limit,
+            window_secs: 60,
+            fail_closed,
+        }
+    }
+
+    fn ip_rule(name: &str, route: &str, limit: u32, fail_closed: bool) -> RateLimitRule {
+        rule(
+            name,
+            route,
+            KeyKind::Ip,
+            Algorithm::SlidingWindow,
+            limit,
+            fail_closed,
+        )
+    }
+
+    /// A store whose database is always down
+    struct FailingStore;
+
+    impl RateLimitStore for FailingStore {
+        fn hit<'a>(&'a self, _key: &'a str, _quota: Quota) -> StoreFuture<'a, Decision> {
+            Box::pin(async { Err("connection refused".to_string()) })
+        }
+
+        fn purge(&self) -> StoreFuture<'_, u64> {
+            Box::pin(async { Err("connection refused".to_string()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_answers_429_with_headers() {
+        let state = limited_state(RateLimiter::new(
+            vec![ip_rule("validate_token", "/validate-token", 2, false)],
+            Arc::new(MemoryStore::default()),
+        ));
+        let app = limited_app(state);
+        let call = |path: &'static str, ip: &'static str| {
+            let peer: SocketAddr = format!("{}:4000", ip).parse().unwrap();
+            app.clone().oneshot(
+                Request::post(path)
+                    .extension(ConnectInfo(peer))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let first = call("/validate-token", "10.0.0.1").await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()["ratelimit-limit"], "2");
+        assert_eq!(first.headers()["ratelimit-remaining"], "1");
+        assert_eq!(first.headers()["ratelimit-policy"], "2;w=60");
+
+        call("/validate-token", "10.0.0.1").await.unwrap();
+        let refused = call("/validate-token", "10.0.0.1").await.unwrap();
+        assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(refused.headers()["ratelimit-remaining"], "0");
+        assert!(refused.headers().contains_key("retry-after"));
+
+        let other_client = call("/validate-token", "10.0.0.2").await.unwrap();
+        assert_eq!(other_client.status(), StatusCode::OK);
+        let unlimited = call("/anything", "10.0.0.1").await.unwrap();
+        assert!(!unlimited.headers().contains_key("ratelimit-limit"));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_store_lets_requests_through() {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://unused@127.0.0.1:1/unused")
+            .unwrap();
+        let state = limited_state(RateLimiter::new(
+            vec![rule(
+                "api",
+                "/*",
+                KeyKind::Sub,
+                Algorithm::TokenBucket,
+                1,
+                false,
+            )],
+            Arc::new(PostgresStore::new(pool)),
+        ));
+
+        let response = limited_app(state)
+            .oneshot(Request::post("/anything").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_forwarding_headers_share_the_peer_bucket() {
+        let state = limited_state(RateLimiter::new(
+            vec![ip_rule("validate_token", "/validate-token", 2, true)],
+            Arc::new(MemoryStore::default()),
+        ));
+        let app = limited_app(state);
+        let call = |peer: &str, claimed: &str| {
+            let peer: SocketAddr = format!("{}:4000", peer).parse().unwrap();
+            app.clone().oneshot(
+                Request::post("/validate-token")
+                    .extension(ConnectInfo(peer))
+                    .header("X-Real-IP", claimed)
+                    .header("X-Forwarded-For", claimed)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        // A client connecting directly claims a new address every time
+        let mut statuses = Vec::new();
+        for claimed in ["198.51.100.1", "198.51.100.2", "198.51.100.3"] {
+            statuses.push(call("203.0.113.9", claimed).await.unwrap().status());
+        }
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS
+            ]
+        );
+
+        // Behind the gateway every forwarded client has its own bucket
+        for claimed in ["198.51.100.1", "198.51.100.2", "198.51.100.3"] {
+            assert_eq!(
+                call("10.0.0.5", claimed).await.unwrap().status(),
+                StatusCode::OK
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotating_api_keys_keep_the_bucket() {
+        let state = limited_state(RateLimiter::new(
+            vec![ip_rule("validate_token", "/validate-token", 2, true)],
+            Arc::new(MemoryStore::default()),
+        ));
+        let app = limited_app(state);
+
+        let mut statuses = Vec::new();
+        for api_key in ["key-1", "key-2", "key-3"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/validate-token")
+                        .extension(ConnectInfo(SocketAddr::from(([203, 0, 113, 9], 4000))))
+                        .header("X-API-Key", api_key)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            statuses.push(response.status());
+        }
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS
+            ]
+        );
+        assert!(serde_json::from_str::<KeyKind>(r#""api_key""#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_store_failures_refuse_fail_closed_rules() {
+        let state = limited_state(RateLimiter::new(
+            vec![
+                ip_rule("validate_token", "/validate-token", 10, true),
+                ip_rule("api", "/anything", 10, false),
+            ],
+            Arc::new(FailingStore),
+        ));
+        let app = limited_app(state);
+        let call = |path: &'static str| {
+            app.clone()
+                .oneshot(Request::post(path).body(Body::empty()).unwrap())
+        };
+
+        let refused = call("/validate-token").await.unwrap();
+        assert_eq!(refused.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(refused.headers()["retry-after"], "5");
+        assert!(!refused.headers().contains_key("ratelimit-limit"));
+
+        let open = call("/anything").await.unwrap();
+        assert_eq!(open.status(), StatusCode::OK);
+    }
+}
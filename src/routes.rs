@@ -0,0 +1,223 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware as axum_middleware,
+    routing::{get, patch, post, put},
+    Router,
+};
+use std::sync::Arc;
+
+use crate::handlers::{admin, support, system};
+use crate::health;
+use crate::metrics::{http_metrics_middleware, metrics_handler};
+use crate::middleware::{
+    admin_middleware, auth_middleware, deprecation_middleware, request_id_middleware,
+};
+use crate::mock::mock_routes;
+use crate::models::AppState;
+use crate::openapi::openapi_routes;
+use crate::rate_limit::rate_limit_middleware;
+use crate::support::MAX_ATTACHMENT_BYTES;
+use crate::telemetry::http_trace_layer;
+
+/// Prefix of the current, versioned API
+pub const API_V1_PREFIX: &str = "/api/v1";
+
+/// Build the application router.
+///
+/// The versioned API lives under `/api/v1`. The original unversioned paths
+/// remain available as deprecated aliases until their sunset date. `main`
+/// wraps the router in the configured CORS layer, see [`crate::cors`].
+pub fn build_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        // Probes keep their unversioned path and are not deprecated
+        .route("/health", get(system::health_check))
+        .route("/livez", get(health::livez))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics_handler))
+        .merge(openapi_routes())
+        .nest(API_V1_PREFIX, v1_routes(state.clone()))
+        .merge(legacy_routes(state.clone()))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_middleware,
+        ))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            http_metrics_middleware,
+        ))
+        .layer(http_trace_layer())
+        .layer(axum_middleware::from_fn(request_id_middleware))
+        .with_state(state)
+}
+
+/// Router for `MOCK_MODE`: the versioned API answers with fixtures or
+/// schema-generated examples instead of touching the database
+pub fn build_mock_router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/health", get(system::health_check))
+        .route("/livez", get(health::livez))
+        .route("/readyz", get(health::mock_readyz))
+        .route("/metrics", get(metrics_handler))
+        .merge(openapi_routes())
+        .nest(API_V1_PREFIX, mock_routes(state.clone()))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_middleware,
+        ))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            http_metrics_middleware,
+        ))
+        .layer(http_trace_layer())
+        .layer(axum_middleware::from_fn(request_id_middleware))
+        .with_state(state)
+}
+
+/// Versioned REST API: PATCH for partial updates, PUT for idempotent upserts
+/// and DELETE for deletes
+fn v1_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let protected_routes = Router::new()
+        .route("/system/uptime", get(system::system_uptime))
+        .route("/system/onboarding", post(system::system_onboarding))
+        .route(
+            "/profile",
+            get(system::get_profile).patch(system::update_profile),
+        )
+        .route(
+            "/preferences",
+            get(system::get_preferences).patch(system::update_preferences),
+        )
+        .route(
+            "/support/tickets",
+            get(support::list_tickets).post(support::create_ticket),
+        )
+        .route(
+            "/support/tickets/:ticket_id",
+            get(support::get_ticket).patch(support::update_ticket),
+        )
+        .route(
+            "/support/tickets/:ticket_id/comments",
+            post(support::create_comment),
+        )
+        .route(
+            "/support/tickets/:ticket_id/attachments",
+            post(support::upload_attachment).layer(DefaultBodyLimit::max(MAX_ATTACHMENT_BYTES)),
+        )
+        .route(
+            "/support/tickets/:ticket_id/attachments/:attachment_id",
+            get(support::download_attachment),
+        )
+        .route_layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ));
+
+    let admin_routes = Router::new()
+        .route(
+            "/admin/roles",
+            get(admin::list_roles).post(admin::create_role),
+        )
+        .route(
+            "/admin/roles/:role_id",
+            get(admin::get_role)
+                .patch(admin::update_role)
+                .delete(admin::delete_role),
+        )
+        .route(
+            "/admin/roles/:role_id/permissions",
+            get(admin::get_role_permissions).put(admin::replace_role_permissions),
+        )
+        .route(
+            "/admin/roles/:role_id/permissions/:page",
+            put(admin::put_role_permission),
+        )
+        .route("/admin/users", get(admin::list_users))
+        .route(
+            "/admin/users/:user_id",
+            patch(admin::update_user).delete(admin::delete_user),
+        )
+        .route(
+            "/admin/users/:user_id/deactivate",
+            post(admin::deactivate_user),
+        )
+        .route(
+            "/admin/users/:user_id/reactivate",
+            post(admin::reactivate_user),
+        )
+        .route(
+            "/admin/users/:user_id/anonymize",
+            post(admin::anonymize_user),
+        )
+        .route("/admin/users/:user_id/roles", get(admin::get_user_roles))
+        .route(
+            "/admin/users/:user_id/roles/:role_id",
+            put(admin::put_user_role).delete(admin::delete_user_role),
+        )
+        .route(
+            "/admin/organizations/:organization_id/properties-schema",
+            get(admin::get_properties_schema).put(admin::put_properties_schema),
+        )
+        .route(
+            "/admin/organizations/:organization_id/default-preferences",
+            get(admin::get_default_preferences).put(admin::put_default_preferences),
+        )
+        .route("/admin/audit-events", get(admin::list_audit_events))
+        .route("/admin/audit-events/verify", get(admin::verify_audit_chain))
+        .route_layer(axum_middleware::from_fn_with_state(state, admin_middleware));
+
+    Router::new()
+        .route("/health", get(system::health_check))
+        .route("/system/version", get(system::system_version))
+        .route("/validate-token", post(system::validate_token))
+        .merge(protected_routes)
+        .merge(admin_routes)
+}
+
+/// Unversioned paths from before `/api/v1`, answered with `Deprecation` and
+/// `Sunset` headers
+fn legacy_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let protected_routes = Router::new()
+        .route("/system/uptime", get(system::system_uptime))
+        .route("/system/onboarding", post(system::system_onboarding))
+        .route("/profile", get(system::get_profile))
+        .route_layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ));
+
+    let admin_routes = Router::new()
+        .route(
+            "/admin/roles",
+            get(admin::legacy_list_roles).post(admin::create_role),
+        )
+        .route(
+            "/admin/roles/:role_id",
+            get(admin::get_role).post(admin::update_role),
+        )
+        .route("/admin/roles/:role_id/delete", post(admin::delete_role))
+        .route(
+            "/admin/roles/:role_id/permissions",
+            get(admin::get_role_permissions)
+                .post(admin::set_role_permission)
+                .put(admin::replace_role_permissions),
+        )
+        .route("/admin/users", get(admin::legacy_list_users))
+        .route(
+            "/admin/users/:user_id/roles",
+            get(admin::legacy_get_user_roles).post(admin::assign_user_role),
+        )
+        .route(
+            "/admin/users/:user_id/roles/remove",
+            post(admin::remove_user_role),
+        )
+        .route("/admin/audit-events", get(admin::legacy_list_audit_events))
+        .route("/admin/audit-events/verify", get(admin::verify_audit_chain))
+        .route_layer(axum_middleware::from_fn_with_state(state, admin_middleware));
+
+    Router::new()
+        .route("/system/version", get(system::system_version))
+        .route("/validate-token", post(system::validate_token))
+        .merge(protected_routes)
+        .merge(admin_routes)
+        .layer(axum_middleware::from_fn(deprecation_middleware))
+}
@@ -0,0 +1,479 @@
+//! Secrets from files and secret managers.
+//!
+//! The database URL and the JWT signing key are looked up in a chain of
+//! [`SecretProvider`]s, the first holding a value winning:
+//!
+//! 1. Vault, when `VAULT_ADDR` is set: the `database_url` and `jwt_secret`
+//!    fields of a KV version 2 secret
+//! 2. files named by `DATABASE_URL_FILE` and `JWT_SECRET_FILE`, as mounted
+//!    by Docker and Kubernetes secrets
+//! 3. the plain configuration values (`DATABASE_URL`, `JWT_SECRET`)
+//!
+//! Values are held in zeroizing [`Secret`]s. Every `SECRETS_REFRESH_SECS`
+//! the chain is read again: a rotated signing key replaces the one in
+//! [`SharedSecret`], and a rotated database URL is used for new pool
+//! connections.
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+use sqlx::postgres::PgConnectOptions;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::config::{Config, Secret, VaultConfig};
+use crate::shutdown::Shutdown;
+
+/// Longest a Vault request may take
+const VAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Secrets the server looks up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SecretKey {
+    DatabaseUrl,
+    JwtSecret,
+}
+
+impl SecretKey {
+    /// Field name in secret managers
+    pub fn name(self) -> &'static str {
+        match self {
+            SecretKey::DatabaseUrl => "database_url",
+            SecretKey::JwtSecret => "jwt_secret",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SecretError {
+    pub provider: String,
+    pub detail: String,
+}
+
+impl std::fmt::Display for SecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.provider, self.detail)
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+pub type SecretFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<Secret>, SecretError>> + Send + 'a>>;
+
+/// A source of secrets
+pub trait SecretProvider: Send + Sync {
+    /// Shown in logs and errors
+    fn name(&self) -> String;
+
+    /// The current value of `key`, `None` when this provider does not hold it
+    fn fetch(&self, key: SecretKey) -> SecretFuture<'_>;
+}
+
+/// Values known up front, from the configuration
+pub struct StaticProvider {
+    values: HashMap<SecretKey, Secret>,
+}
+
+impl StaticProvider {
+    pub fn new(values: HashMap<SecretKey, Secret>) -> Self {
+        StaticProvider { values }
+    }
+}
+
+impl SecretProvider for StaticProvider {
+    fn name(&self) -> String {
+        "configuration".to_string()
+    }
+
+    fn fetch(&self, key: SecretKey) -> SecretFuture<'_> {
+        let value = self.values.get(&key).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+}
+
+/// One file per secret, read on every fetch so replaced files are noticed
+pub struct FileProvider {
+    paths: HashMap<SecretKey, PathBuf>,
+}
+
+impl FileProvider {
+    pub fn new(paths: HashMap<SecretKey, PathBuf>) -> Self {
+        FileProvider { paths }
+    }
+}
+
+impl SecretProvider for FileProvider {
+    fn name(&self) -> String {
+        "file".to_string()
+    }
+
+    fn fetch(&self, key: SecretKey) -> SecretFuture<'_> {
+        Box::pin(async move {
+            match self.paths.get(&key) {
+                Some(path) => read_secret_file(path).await.map(Some),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+/// The file's content without the trailing newline editors and `echo` add
+async fn read_secret_file(path: &Path) -> Result<Secret, SecretError> {
+    let error = |detail: String| SecretError {
+        provider: format!("file {}", path.display()),
+        detail,
+    };
+    let bytes = Zeroizing::new(
+        tokio::fs::read(path)
+            .await
+            .map_err(|e| error(e.to_string()))?,
+    );
+    let text = std::str::from_utf8(&bytes).map_err(|_| error("not UTF-8".to_string()))?;
+    let value = text.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        return Err(error("empty".to_string()));
+    }
+    Ok(Secret::new(value))
+}
+
+/// A KV version 2 secret of a Vault-compatible server
+pub struct VaultProvider {
+    client: reqwest::Client,
+    url: String,
+    token: Option<Secret>,
+    token_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct VaultResponse {
+    data: VaultData,
+}
+
+#[derive(Deserialize)]
+struct VaultData {
+    data: HashMap<String, Secret>,
+}
+
+impl VaultProvider {
+    /// `None` unless `config.address` is set
+    pub fn new(config: &VaultConfig) -> Option<Self> {
+        let address = config.address.as_ref()?;
+        Some(VaultProvider {
+            client: reqwest::Client::builder()
+                .timeout(VAULT_TIMEOUT)
+                .build()
+                .expect("Failed to build the Vault HTTP client"),
+            url: format!(
+                "{}/v1/{}/data/{}",
+                address.trim_end_matches('/'),
+                config.mount.trim_matches('/'),
+                config.path.trim_matches('/')
+            ),
+            token: config.token.clone(),
+            token_file: config.token_file.clone(),
+        })
+    }
+
+    fn error(&self, detail: impl Into<String>) -> SecretError {
+        SecretError {
+            provider: self.name(),
+            detail: detail.into(),
+        }
+    }
+
+    async fn token(&self) -> Result<Secret, SecretError> {
+        match (&self.token_file, &self.token) {
+            (Some(path), _) => read_secret_file(path).await,
+            (None, Some(token)) => Ok(token.clone()),
+            (None, None) => Err(self.error("no token configured")),
+        }
+    }
+}
+
+impl SecretProvider for VaultProvider {
+    fn name(&self) -> String {
+        format!("vault {}", self.url)
+    }
+
+    fn fetch(&self, key: SecretKey) -> SecretFuture<'_> {
+        Box::pin(async move {
+            let token = self.token().await?;
+            let response = self
+                .client
+                .get(&self.url)
+                .header("X-Vault-Token", token.expose())
+                .send()
+                .await
+                .map_err(|e| self.error(e.to_string()))?;
+            if response.status() != StatusCode::OK {
+                return Err(self.error(format!("answered {}", response.status())));
+            }
+            let body = Zeroizing::new(
+                response
+                    .bytes()
+                    .await
+                    .map_err(|e| self.error(e.to_string()))?
+                    .to_vec(),
+            );
+            let mut secret: VaultResponse = serde_json::from_slice(&body)
+                .map_err(|e| self.error(format!("unexpected response: {}", e)))?;
+            Ok(secret.data.data.remove(key.name()))
+        })
+    }
+}
+
+/// Providers asked in order, the first holding a value winning
+pub struct Secrets {
+    providers: Vec<Box<dyn SecretProvider>>,
+}
+
+impl Secrets {
+    pub fn new(providers: Vec<Box<dyn SecretProvider>>) -> Self {
+        Secrets { providers }
+    }
+
+    /// Vault, then files, then plain values, as configured
+    pub fn from_config(config: &Config) -> Self {
+        let mut providers: Vec<Box<dyn SecretProvider>> = Vec::new();
+        if let Some(vault) = VaultProvider::new(&config.secrets.vault) {
+            providers.push(Box::new(vault));
+        }
+
+        let files: HashMap<SecretKey, PathBuf> = [
+            (SecretKey::DatabaseUrl, config.database.url_file.clone()),
+            (SecretKey::JwtSecret, config.auth.jwt_secret_file.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, path)| Some((key, path?)))
+        .collect();
+        if !files.is_empty() {
+            providers.push(Box::new(FileProvider::new(files)));
+        }
+
+        let values: HashMap<SecretKey, Secret> = [
+            (SecretKey::DatabaseUrl, config.database.url.clone()),
+            (SecretKey::JwtSecret, config.auth.jwt_secret.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect();
+        providers.push(Box::new(StaticProvider::new(values)));
+
+        Secrets { providers }
+    }
+
+    pub async fn fetch(&self, key: SecretKey) -> Result<Option<Secret>, SecretError> {
+        for provider in &self.providers {
+            if let Some(value) = provider.fetch(key).await? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fill in the database URL and signing key of `config`
+    pub async fn resolve(&self, config: &mut Config) -> Result<(), SecretError> {
+        config.database.url = self.fetch(SecretKey::DatabaseUrl).await?;
+        config.auth.jwt_secret = self.fetch(SecretKey::JwtSecret).await?;
+        Ok(())
+    }
+}
+
+/// A secret that can be replaced while requests read it
+#[derive(Clone)]
+pub struct SharedSecret(Arc<RwLock<Secret>>);
+
+impl SharedSecret {
+    pub fn new(secret: Secret) -> Self {
+        SharedSecret(Arc::new(RwLock::new(secret)))
+    }
+
+    pub fn get(&self) -> Secret {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the value, returning whether it changed
+    pub fn replace(&self, secret: Secret) -> bool {
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+        if *current == secret {
+            return false;
+        }
+        *current = secret;
+        true
+    }
+}
+
+impl From<&str> for SharedSecret {
+    fn from(secret: &str) -> Self {
+        SharedSecret::new(Secret::new(secret))
+    }
+}
+
+/// Read the secrets again every `interval` until shutdown begins, applying
+/// rotated ones. Failed reads keep the current values.
+pub async fn refresh(
+    secrets: Secrets,
+    interval: Duration,
+    jwt_secret: SharedSecret,
+    pool: Option<sqlx::PgPool>,
+    shutdown: Shutdown,
+) {
+    let mut database_url = match &pool {
+        Some(_) => secrets.fetch(SecretKey::DatabaseUrl).await.ok().flatten(),
+        None => None,
+    };
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.begun() => return,
+        }
+
+        match secrets.fetch(SecretKey::JwtSecret).await {
+            Ok(Some(secret)) => {
+                if jwt_secret.replace(secret) {
+                    tracing::info!("JWT signing key rotated");
+                }
+            }
+            Ok(None) => tracing::warn!("JWT signing key disappeared, keeping the current one"),
+            Err(e) => tracing::warn!("Failed to refresh the JWT signing key: {}", e),
+        }
+
+        let Some(pool) = &pool else { continue };
+        match secrets.fetch(SecretKey::DatabaseUrl).await {
+            Ok(Some(url)) if Some(&url) != database_url.as_ref() => {
+                match PgConnectOptions::from_str(url.expose()) {
+                    Ok(options) => {
+                        pool.set_connect_options(options);
+                        database_url = Some(url);
+                        tracing::info!("Database credentials rotated, used for new connections");
+                    }
+                    Err(e) => tracing::warn!("Rotated database URL is invalid: {}", e),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to refresh the database URL: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Path, http::HeaderMap, routing::get, Json, Router};
+    use serde_json::json;
+
+    /// Serve a KV version 2 secret at `secret/app` for token `root`
+    async fn vault_stub(fields: serde_json::Value) -> String {
+        let app = Router::new().route(
+            "/v1/secret/data/:path",
+            get(move |Path(path): Path<String>, headers: HeaderMap| {
+                let fields = fields.clone();
+                async move {
+                    if headers.get("X-Vault-Token").map(|t| t.as_bytes()) != Some(b"root") {
+                        return Err(StatusCode::FORBIDDEN);
+                    }
+                    if path != "app" {
+                        return Err(StatusCode::NOT_FOUND);
+                    }
+                    Ok(Json(
+                        json!({ "data": { "data": fields, "metadata": { "version": 1 } } }),
+                    ))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        address
+    }
+
+    fn vault_config(address: String, token: &str) -> VaultConfig {
+        VaultConfig {
+            address: Some(address),
+            token: Some(Secret::new(token)),
+            token_file: None,
+            mount: "secret".to_string(),
+            path: "app".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vault_provider_reads_fields() {
+        let address = vault_stub(json!({ "jwt_secret": "from-vault" })).await;
+        let vault = VaultProvider::new(&vault_config(address.clone(), "root")).unwrap();
+
+        let secret = vault.fetch(SecretKey::JwtSecret).await.unwrap();
+        assert_eq!(secret.unwrap().expose(), "from-vault");
+        assert!(vault.fetch(SecretKey::DatabaseUrl).await.unwrap().is_none());
+
+        let denied = VaultProvider::new(&vault_config(address, "wrong")).unwrap();
+        let error = denied.fetch(SecretKey::JwtSecret).await.unwrap_err();
+        assert!(error.detail.contains("403"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_chain_prefers_vault_then_files() {
+        let address = vault_stub(json!({ "jwt_secret": "from-vault" })).await;
+        let dir = std::env::temp_dir().join(format!("secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let url_file = dir.join("database_url");
+        std::fs::write(&url_file, "postgres://app:from-file@db/app\n").unwrap();
+
+        let mut config = Config::defaults(crate::config::Profile::Dev);
+        config.secrets.vault = vault_config(address, "root");
+        config.database.url_file = Some(url_file);
+        Secrets::from_config(&config)
+            .resolve(&mut config)
+            .await
+            .unwrap();
+
+        assert_eq!(config.auth.jwt_secret.unwrap().expose(), "from-vault");
+        assert_eq!(
+            config.database.url.unwrap().expose(),
+            "postgres://app:from-file@db/app"
+        );
+
+        let mut missing = Config::defaults(crate::config::Profile::Dev);
+        missing.auth.jwt_secret_file = Some(dir.join("absent"));
+        let error = Secrets::from_config(&missing)
+            .resolve(&mut missing)
+            .await
+            .unwrap_err();
+        assert!(error.provider.starts_with("file"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refresh_picks_up_rotated_key() {
+        let dir = std::env::temp_dir().join(format!("secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("jwt_secret");
+        std::fs::write(&key_file, "first").unwrap();
+
+        let mut config = Config::defaults(crate::config::Profile::Dev);
+        config.auth.jwt_secret_file = Some(key_file.clone());
+        let shared = SharedSecret::from("first");
+        let shutdown = Shutdown::default();
+        let task = tokio::spawn(refresh(
+            Secrets::from_config(&config),
+            Duration::from_millis(20),
+            shared.clone(),
+            None,
+            shutdown.clone(),
+        ));
+
+        std::fs::write(&key_file, "second").unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(shared.get().expose(), "second");
+
+        shutdown.begin();
+        task.await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+//! Graceful shutdown.
+//!
+//! On SIGTERM or Ctrl+C the server first fails its readiness probe, keeps
+//! serving for `shutdown.readiness_delay_secs` so load balancers stop sending
+//! new requests, then stops accepting connections and lets in-flight
+//! requests finish. Requests still running after `shutdown.timeout_secs` are
+//! cancelled with a 503: their handlers are dropped, which rolls back their
+//! open transactions. `main` then closes the database pool and flushes
+//! telemetry.
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+use crate::error::AppError;
+
+/// Default time in-flight requests get to finish
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time cancelled requests get to send their 503 before the server stops
+const CANCEL_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Serving,
+    Draining,
+    Cancelling,
+}
+
+/// Where the server is in its shutdown, shared through `AppState`
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    phase: Arc<watch::Sender<Phase>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            phase: Arc::new(watch::Sender::new(Phase::Serving)),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Start shutting down; readiness fails from now on
+    pub fn begin(&self) {
+        self.advance(Phase::Draining);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.phase.borrow() >= Phase::Draining
+    }
+
+    /// Resolves once [`Shutdown::begin`] has been called
+    pub async fn begun(&self) {
+        self.reached(Phase::Draining).await;
+    }
+
+    /// Give up on the requests still running
+    fn cancel(&self) {
+        self.advance(Phase::Cancelling);
+    }
+
+    fn advance(&self, phase: Phase) {
+        self.phase.send_if_modified(|current| {
+            let later = phase > *current;
+            if later {
+                *current = phase;
+            }
+            later
+        });
+    }
+
+    async fn reached(&self, phase: Phase) {
+        let mut current = self.phase.subscribe();
+        // The sender lives in `self`, so the channel cannot close
+        let _ = current.wait_for(|current| *current >= phase).await;
+    }
+}
+
+/// Answer 503 instead of waiting for the handler once the drain deadline
+/// has passed. Dropping the handler rolls back its open transaction.
+async fn cancel_on_deadline(shutdown: Shutdown, req: Request, next: Next) -> Response {
+    tokio::select! {
+        response = next.run(req) => response,
+        _ = shutdown.reached(Phase::Cancelling) => AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "shutting_down",
+            "The server shut down before the request completed",
+        )
+        .into_response(),
+    }
+}
+
+/// Timing of a graceful shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSettings {
+    /// How long to keep accepting requests after readiness starts failing
+    pub readiness_delay: Duration,
+    /// How long in-flight requests get to finish once no more are accepted
+    pub drain_timeout: Duration,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        ShutdownSettings {
+            readiness_delay: Duration::ZERO,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+}
+
+/// Resolves on Ctrl+C, or SIGTERM on Unix
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Serve `app` until `signal` resolves, then shut down as described in the
+/// module documentation. Returns once every connection is closed or the
+/// drain deadline has passed.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    shutdown: Shutdown,
+    settings: ShutdownSettings,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    let stop_accepting = {
+        let shutdown = shutdown.clone();
+        async move {
+            signal.await;
+            tracing::info!("Shutdown requested, failing readiness");
+            shutdown.begin();
+            tokio::time::sleep(settings.readiness_delay).await;
+            tracing::info!("No longer accepting connections, draining in-flight requests");
+        }
+    };
+
+    let app = app.layer(middleware::from_fn({
+        let shutdown = shutdown.clone();
+        move |req, next| cancel_on_deadline(shutdown.clone(), req, next)
+    }));
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(stop_accepting);
+    let mut server = tokio::spawn(async move { server.await });
+
+    tokio::select! {
+        result = &mut server => return result.unwrap_or(Ok(())),
+        _ = shutdown.begun() => {}
+    }
+
+    let deadline = settings.readiness_delay + settings.drain_timeout;
+    match tokio::time::timeout(deadline, &mut server).await {
+        Ok(result) => {
+            tracing::info!("All connections drained");
+            result.unwrap_or(Ok(()))
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Requests still running after {:?}, cancelling them",
+                settings.drain_timeout
+            );
+            shutdown.cancel();
+            match tokio::time::timeout(CANCEL_GRACE, &mut server).await {
+                Ok(result) => result.unwrap_or(Ok(())),
+                Err(_) => {
+                    server.abort();
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::time::Instant;
+    use tokio::sync::oneshot;
+
+    /// Serve a route answering after `delay` until the returned sender fires
+    async fn slow_server(
+        delay: Duration,
+        settings: ShutdownSettings,
+    ) -> (
+        String,
+        Shutdown,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/slow", listener.local_addr().unwrap());
+        let app = Router::new().route(
+            "/slow",
+            get(move || async move {
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        );
+        let shutdown = Shutdown::default();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            shutdown.clone(),
+            settings,
+            async move {
+                let _ = stopped.await;
+            },
+        ));
+        (url, shutdown, stop, server)
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_finish() {
+        let settings = ShutdownSettings::default();
+        let (url, shutdown, stop, server) = slow_server(Duration::from_millis(300), settings).await;
+
+        let request = tokio::spawn(reqwest::get(url));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stop.send(()).unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+        assert!(shutdown.is_draining());
+    }
+
+    #[tokio::test]
+    async fn test_drain_deadline_cancels_requests() {
+        let settings = ShutdownSettings {
+            readiness_delay: Duration::ZERO,
+            drain_timeout: Duration::from_millis(200),
+        };
+        let (url, _shutdown, stop, server) = slow_server(Duration::from_secs(30), settings).await;
+
+        let request = tokio::spawn(reqwest::get(url));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let started = Instant::now();
+        stop.send(()).unwrap();
+
+        server.await.unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
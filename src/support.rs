@@ -0,0 +1,296 @@
+//! Support tickets: who may see them and how their status moves.
+//!
+//! Access follows the permission matrix of the `support` page. `can_view`
+//! makes a user support staff, who see and work every ticket of their
+//! organization; `can_view_own` lets a user raise tickets and follow their
+//! own. Tokens with the admin claim count as staff.
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::{Claims, SupportTicket, TicketStatus, User};
+
+/// Page of the permission matrix that governs support tickets
+pub const SUPPORT_PAGE: &str = "support";
+
+/// Largest accepted attachment
+pub const MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+
+/// Content type stored when an upload does not declare one
+pub const DEFAULT_ATTACHMENT_CONTENT_TYPE: &str = "application/octet-stream";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupportRole {
+    /// Sees every ticket of the organization, assigns and triages them
+    Staff,
+    /// Sees the tickets they raised
+    Requester,
+}
+
+/// The onboarded user behind a request and what they may do with tickets
+#[derive(Debug)]
+pub struct SupportCaller {
+    pub user: User,
+    pub role: SupportRole,
+}
+
+impl SupportCaller {
+    pub fn is_staff(&self) -> bool {
+        self.role == SupportRole::Staff
+    }
+}
+
+impl TicketStatus {
+    /// Whether the workflow allows moving from `self` to `next`
+    pub fn can_transition_to(self, next: TicketStatus) -> bool {
+        use TicketStatus::*;
+        matches!(
+            (self, next),
+            (Open, InProgress | WaitingOnRequester | Resolved | Closed)
+                | (InProgress, WaitingOnRequester | Resolved | Closed)
+                | (WaitingOnRequester, InProgress | Resolved | Closed)
+                | (Resolved, Open | Closed)
+                | (Closed, Open)
+        )
+    }
+}
+
+/// Check a status change requested by `role`. Requesters may only close
+/// their ticket or reopen one that was resolved.
+pub fn check_status_change(
+    role: SupportRole,
+    current: TicketStatus,
+    next: TicketStatus,
+) -> Result<(), AppError> {
+    if current == next {
+        return Ok(());
+    }
+    if !current.can_transition_to(next) {
+        return Err(AppError::conflict(
+            "invalid_status_transition",
+            format!(
+                "A ticket cannot move from {} to {}",
+                status_name(current),
+                status_name(next)
+            ),
+        ));
+    }
+    let requester_allowed = next == TicketStatus::Closed
+        || (current == TicketStatus::Resolved && next == TicketStatus::Open);
+    if role == SupportRole::Requester && !requester_allowed {
+        return Err(staff_required());
+    }
+    Ok(())
+}
+
+fn status_name(status: TicketStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+pub fn staff_required() -> AppError {
+    AppError::forbidden(
+        "support_staff_required",
+        "Only support staff can make this change",
+    )
+}
+
+/// Resolve the caller of a support endpoint
+pub async fn caller(conn: &mut PgConnection, claims: &Claims) -> Result<SupportCaller, AppError> {
+    let user: User = sqlx::query_as::<_, User>("SELECT * FROM users WHERE sub = $1")
+        .bind(&claims.sub)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("user_not_found", "User not found"))?;
+
+    let (can_view, can_view_own) = page_access(conn, user.id, user.organization_id).await?;
+    let role = if can_view || claims.admin.unwrap_or(false) {
+        SupportRole::Staff
+    } else if can_view_own {
+        SupportRole::Requester
+    } else {
+        return Err(AppError::forbidden(
+            "support_access_denied",
+            "The user's roles do not grant access to support tickets",
+        ));
+    };
+
+    Ok(SupportCaller { user, role })
+}
+
+/// `can_view` and `can_view_own` on the support page across a user's roles
+async fn page_access(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    organization_id: Option<Uuid>,
+) -> Result<(bool, bool), AppError> {
+    let access: (bool, bool) = sqlx::query_as(
+        "SELECT COALESCE(bool_or(p.can_view), FALSE), COALESCE(bool_or(p.can_view_own), FALSE)
+         FROM user_roles ur
+         JOIN permissions p ON p.role_id = ur.role_id
+         WHERE ur.user_id = $1 AND p.page = $2
+           AND (p.organization_id IS NULL OR p.organization_id = $3)",
+    )
+    .bind(user_id)
+    .bind(SUPPORT_PAGE)
+    .bind(organization_id)
+    .fetch_one(conn)
+    .await?;
+    Ok(access)
+}
+
+/// Whether a user can be assigned tickets of `organization_id`: an active
+/// member of the organization with `can_view` on the support page
+pub async fn is_staff_member(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    organization_id: Option<Uuid>,
+) -> Result<bool, AppError> {
+    let member: Option<bool> = sqlx::query_scalar(
+        "SELECT TRUE FROM users
+         WHERE id = $1 AND organization_id IS NOT DISTINCT FROM $2 AND deactivated_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(organization_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    if member.is_none() {
+        return Ok(false);
+    }
+    let (can_view, _) = page_access(conn, user_id, organization_id).await?;
+    Ok(can_view)
+}
+
+/// Load a ticket the caller can see, optionally locking it for an update.
+/// Tickets of other organizations, and for requesters other people's
+/// tickets, are reported as missing.
+pub async fn visible_ticket(
+    conn: &mut PgConnection,
+    caller: &SupportCaller,
+    ticket_id: Uuid,
+    for_update: bool,
+) -> Result<SupportTicket, AppError> {
+    let mut query =
+        sqlx::QueryBuilder::<sqlx::Postgres>::new("SELECT * FROM support_tickets WHERE id = ");
+    query.push_bind(ticket_id);
+    push_visibility(&mut query, caller);
+    if for_update {
+        query.push(" FOR UPDATE");
+    }
+
+    query
+        .build_query_as::<SupportTicket>()
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("ticket_not_found", "Ticket not found"))
+}
+
+/// Append the conditions restricting tickets to those the caller can see
+pub fn push_visibility(query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, caller: &SupportCaller) {
+    query
+        .push(" AND organization_id IS NOT DISTINCT FROM ")
+        .push_bind(caller.user.organization_id);
+    if !caller.is_staff() {
+        query.push(" AND requester_id = ").push_bind(caller.user.id);
+    }
+}
+
+/// Name an upload is stored under: the last path segment of what the client
+/// sent, without control characters
+pub fn clean_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    base.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// `Content-Disposition` value offering a download under `filename`: an
+/// ASCII fallback plus the exact name in RFC 5987 encoding
+pub fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use TicketStatus::*;
+
+    #[test]
+    fn test_workflow_transitions() {
+        assert!(Open.can_transition_to(InProgress));
+        assert!(InProgress.can_transition_to(WaitingOnRequester));
+        assert!(WaitingOnRequester.can_transition_to(InProgress));
+        assert!(Resolved.can_transition_to(Open));
+        assert!(Closed.can_transition_to(Open));
+
+        assert!(!Closed.can_transition_to(Resolved));
+        assert!(!Resolved.can_transition_to(InProgress));
+        assert!(!InProgress.can_transition_to(Open));
+    }
+
+    #[test]
+    fn test_requesters_may_only_close_or_reopen() {
+        let requester = SupportRole::Requester;
+
+        assert!(check_status_change(requester, InProgress, Closed).is_ok());
+        assert!(check_status_change(requester, Resolved, Open).is_ok());
+        assert!(check_status_change(requester, Open, Open).is_ok());
+
+        let error = check_status_change(requester, Open, Resolved).unwrap_err();
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+        let error = check_status_change(requester, Closed, Open).unwrap_err();
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+
+        assert!(check_status_change(SupportRole::Staff, Closed, Open).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_transitions_conflict() {
+        let error = check_status_change(SupportRole::Staff, Closed, Resolved).unwrap_err();
+
+        assert_eq!(error.status, StatusCode::CONFLICT);
+        assert_eq!(error.code, "invalid_status_transition");
+        assert!(error.detail.contains("closed to resolved"));
+    }
+
+    #[test]
+    fn test_filenames_are_cleaned_and_encoded() {
+        assert_eq!(clean_filename("C:\\Users\\me\\report.pdf"), "report.pdf");
+        assert_eq!(clean_filename("../../etc/passwd"), "passwd");
+        assert_eq!(clean_filename(" log\n.txt "), "log.txt");
+
+        assert_eq!(
+            content_disposition("résumé \"v2\".pdf"),
+            "attachment; filename=\"r_sum_ _v2_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%22v2%22.pdf"
+        );
+    }
+}
@@ -0,0 +1,653 @@
+//! Tracing and OpenTelemetry.
+//!
+//! Logs always go to stdout, filtered by `telemetry.log_filter` (`RUST_LOG`,
+//! default `info`), as compact text or, with `LOG_FORMAT=json`, one flat
+//! JSON object per line
+//! carrying the fields of the enclosing spans. Every request span holds the
+//! request ID and, once authenticated, the caller's `sub` and organization,
+//! so each line logged while handling a request can be tied to it. When
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set, spans are also exported over OTLP
+//! gRPC: one server span per HTTP request, continuing the caller's W3C
+//! `traceparent`, with a client span per SQL statement below it. Statement
+//! spans are built from the `sqlx::query` events sqlx emits when a query
+//! finishes, so they need no changes at the call sites.
+//!
+//! Metric instruments are set up here too, see [`crate::metrics`].
+
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{HeaderMap, Request, Response},
+};
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    trace::{SpanBuilder, SpanKind, Tracer, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{SdkTracer, SdkTracerProvider},
+    Resource,
+};
+use opentelemetry_semantic_conventions::attribute::{
+    DB_OPERATION_NAME, DB_QUERY_TEXT, DB_SYSTEM_NAME,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use std::time::{Duration, SystemTime};
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{DefaultOnRequest, TraceLayer},
+};
+use tracing::{field::Visit, Level, Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::{
+        format::{self, FormatEvent, FormatFields, JsonFields},
+        FmtContext, FormattedFields, MakeWriter,
+    },
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::config::TelemetryConfig;
+use crate::metrics::{Metrics, PoolMetricsLayer, SQLX_ACQUIRE_TARGET};
+use crate::middleware::RequestId;
+use crate::models::Claims;
+
+/// Name of the tracer and the default `service.name`
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Target of the event sqlx emits for every finished statement
+const SQLX_QUERY_TARGET: &str = "sqlx::query";
+
+/// Installed tracing and metrics pipelines; call [`Telemetry::shutdown`]
+/// before exiting so buffered spans and the last metric values are exported
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    metrics: Metrics,
+}
+
+impl Telemetry {
+    /// Install the global subscriber and the metric instruments, and the OTLP
+    /// exporters when an endpoint is configured
+    pub fn init(
+        config: &TelemetryConfig,
+    ) -> Result<Telemetry, opentelemetry_otlp::ExporterBuildError> {
+        let resource = resource(&config.service_name);
+        let endpoint = config.otlp_endpoint.as_deref();
+        let provider = match endpoint {
+            Some(endpoint) => Some(otlp_tracer_provider(endpoint, resource.clone())?),
+            None => None,
+        };
+        let metrics = Metrics::new(resource, endpoint)?;
+
+        // Validated with the rest of the configuration
+        let env_filter =
+            EnvFilter::try_new(&config.log_filter).unwrap_or_else(|_| EnvFilter::new("info"));
+        tracing_subscriber::registry()
+            .with(log_layer(config.log_format, std::io::stdout).with_filter(env_filter))
+            .with(
+                provider
+                    .as_ref()
+                    .map(|provider| trace_layers(provider.tracer(SERVICE_NAME))),
+            )
+            .with(
+                PoolMetricsLayer::new(metrics.clone())
+                    .with_filter(Targets::new().with_target(SQLX_ACQUIRE_TARGET, Level::TRACE)),
+            )
+            .init();
+
+        Ok(Telemetry { provider, metrics })
+    }
+
+    /// Instruments to record into, shared with the router through `AppState`
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Export the spans and metric values still buffered and stop the
+    /// exporters
+    pub async fn shutdown(self) {
+        let provider = self.provider;
+        let metrics = self.metrics;
+        // Shutting down blocks until the last batch is exported over the runtime
+        let result = tokio::task::spawn_blocking(move || {
+            if let Err(e) = metrics.shutdown() {
+                eprintln!("Failed to flush metrics: {}", e);
+            }
+            provider.map(|provider| provider.shutdown())
+        })
+        .await;
+        if let Ok(Some(Err(e))) = result {
+            eprintln!("Failed to flush spans: {}", e);
+        }
+    }
+}
+
+/// How log lines are written, `text` or `json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Compact human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+/// Log output in `format`, written to `writer`
+pub fn log_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_target(false)
+            .compact()
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(FlatJson)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+/// Writes an event as a single JSON object: timestamp, level and target, the
+/// fields of each enclosing span from the root down, then the event's own
+/// fields. Loki and similar stores can index every key without unnesting.
+struct FlatJson;
+
+impl<S, N> FormatEvent<S, N> for FlatJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+        let mut line = JsonMap::new();
+        line.insert(
+            "timestamp".to_string(),
+            chrono::Utc::now()
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+                .into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                line.insert("span".to_string(), span.name().into());
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<N>>() else {
+                    continue;
+                };
+                // Span fields were formatted as a JSON object by `JsonFields`
+                if let Ok(JsonValue::Object(fields)) = serde_json::from_str(fields) {
+                    line.extend(
+                        fields
+                            .into_iter()
+                            .filter(|(key, _)| !key.starts_with("otel.")),
+                    );
+                }
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut line));
+        writeln!(writer, "{}", JsonValue::Object(line))
+    }
+}
+
+/// Copies event fields into a JSON object
+struct JsonVisitor<'a>(&'a mut JsonMap<String, JsonValue>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+/// Service name and version. `OTEL_RESOURCE_ATTRIBUTES` is honored.
+fn resource(service_name: &str) -> Resource {
+    Resource::builder()
+        .with_service_name(service_name.to_string())
+        .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Batch-export spans to an OTLP gRPC endpoint
+fn otlp_tracer_provider(
+    endpoint: &str,
+    resource: Resource,
+) -> Result<SdkTracerProvider, opentelemetry_otlp::ExporterBuildError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Layers exporting spans through `tracer`: `info` and above from tracing,
+/// plus one client span per SQL statement
+pub fn trace_layers<S>(tracer: SdkTracer) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(tracer.clone())
+        .with_filter(LevelFilter::INFO)
+        .and_then(
+            SqlxSpanLayer { tracer }
+                .with_filter(Targets::new().with_target(SQLX_QUERY_TARGET, Level::DEBUG)),
+        )
+}
+
+/// `TraceLayer` configured by [`http_trace_layer`]
+pub type HttpTraceLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    fn(&Request<Body>) -> Span,
+    DefaultOnRequest,
+    fn(&Response<Body>, Duration, &Span),
+>;
+
+/// Open a server span per request. Add it with `Router::layer` so the
+/// matched route is known, inside `request_id_middleware` so the request ID
+/// is.
+pub fn http_trace_layer() -> HttpTraceLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(make_request_span as fn(&Request<Body>) -> Span)
+        .on_response(record_response as fn(&Response<Body>, Duration, &Span))
+}
+
+fn make_request_span(request: &Request<Body>) -> Span {
+    let method = request.method().as_str();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+    let name = match route {
+        Some(route) => format!("{} {}", method, route),
+        None => method.to_string(),
+    };
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = name,
+        otel.kind = "server",
+        otel.status_code = tracing::field::Empty,
+        http.request.method = method,
+        http.route = route,
+        url.path = request.uri().path(),
+        http.response.status_code = tracing::field::Empty,
+        request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.as_str()),
+        user.sub = tracing::field::Empty,
+        user.organization = tracing::field::Empty,
+    );
+
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // Fails only when no OpenTelemetry layer is installed
+    let _ = span.set_parent(parent);
+    span
+}
+
+/// Name the authenticated caller on the current request span
+pub fn record_caller(claims: &Claims) {
+    let span = Span::current();
+    span.record("user.sub", claims.sub.as_str());
+    if let Some(organization) = &claims.organization {
+        span.record("user.organization", organization.as_str());
+    }
+}
+
+fn record_response(response: &Response<Body>, _latency: Duration, span: &Span) {
+    let status = response.status();
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+/// Reads W3C trace context headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Turns sqlx's per-statement events into client spans, backdated by the
+/// statement's elapsed time and parented to the span entered at the time
+struct SqlxSpanLayer {
+    tracer: SdkTracer,
+}
+
+impl<S> Layer<S> for SqlxSpanLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != SQLX_QUERY_TARGET {
+            return;
+        }
+        let mut statement = StatementFields::default();
+        event.record(&mut statement);
+
+        let end = SystemTime::now();
+        let start = end - Duration::from_secs_f64(statement.elapsed_secs.max(0.0));
+        let text = statement.text();
+        let operation = text
+            .split_whitespace()
+            .next()
+            .unwrap_or("query")
+            .to_uppercase();
+
+        // The OpenTelemetry layer activates the context of the span it enters,
+        // while `Span::current` is unavailable inside a subscriber callback
+        let parent = opentelemetry::Context::current();
+        let builder = SpanBuilder::from_name(operation.clone())
+            .with_kind(SpanKind::Client)
+            .with_start_time(start)
+            .with_end_time(end)
+            .with_attributes([
+                KeyValue::new(DB_SYSTEM_NAME, "postgresql"),
+                KeyValue::new(DB_OPERATION_NAME, operation),
+                KeyValue::new(DB_QUERY_TEXT, text),
+            ]);
+        // The end time is preset, so the span is complete when dropped
+        drop(self.tracer.build_with_context(builder, &parent));
+    }
+}
+
+/// Fields of a `sqlx::query` event
+#[derive(Default)]
+struct StatementFields {
+    summary: String,
+    statement: String,
+    elapsed_secs: f64,
+}
+
+impl StatementFields {
+    /// Full SQL text. sqlx leaves `db.statement` empty when the summary
+    /// already is the whole statement.
+    fn text(&self) -> String {
+        let statement = self.statement.trim();
+        if statement.is_empty() {
+            self.summary.trim_end_matches('…').trim().to_string()
+        } else {
+            statement.to_string()
+        }
+    }
+}
+
+impl Visit for StatementFields {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_string(),
+            "db.statement" => self.statement = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = value;
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        // `summary` and `db.statement` arrive as `String`s through Debug
+        match field.name() {
+            "summary" | "db.statement" => {
+                let text = format!("{:?}", value);
+                let text = text
+                    .strip_prefix('"')
+                    .and_then(|t| t.strip_suffix('"'))
+                    .unwrap_or(&text)
+                    .replace("\\n", "\n")
+                    .replace("\\\"", "\"");
+                self.record_str(field, &text);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::get, Router};
+    use opentelemetry::trace::{SpanId, Status, TraceId};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SpanData};
+    use tower::ServiceExt;
+
+    /// Subscriber exporting to memory, with the provider to flush it
+    fn in_memory() -> (
+        impl Subscriber + Send + Sync,
+        InMemorySpanExporter,
+        SdkTracerProvider,
+    ) {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry().with(trace_layers(provider.tracer("test")));
+        (subscriber, exporter, provider)
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<String> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_request_span_continues_traceparent() {
+        let (subscriber, exporter, provider) = in_memory();
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = Router::new()
+            .route("/items/:id", get(|| async { "ok" }))
+            .route(
+                "/broken",
+                get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            )
+            .layer(http_trace_layer());
+
+        let request = Request::get("/items/42")
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap();
+        let request = Request::get("/broken").body(Body::empty()).unwrap();
+        app.oneshot(request).await.unwrap();
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans.iter().find(|s| s.name == "GET /items/:id").unwrap();
+        assert_eq!(span.span_kind, SpanKind::Server);
+        assert_eq!(
+            span.span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            span.parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+        assert_eq!(attribute(span, "http.route").as_deref(), Some("/items/:id"));
+        assert_eq!(
+            attribute(span, "http.response.status_code").as_deref(),
+            Some("200")
+        );
+
+        let span = spans.iter().find(|s| s.name == "GET /broken").unwrap();
+        assert_eq!(span.parent_span_id, SpanId::INVALID);
+        assert!(matches!(span.status, Status::Error { .. }));
+    }
+
+    #[test]
+    fn test_sqlx_events_become_client_spans() {
+        let (subscriber, exporter, provider) = in_memory();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::debug!(
+                    target: "sqlx::query",
+                    summary = "select * from users …",
+                    db.statement = "\n\nselect * from users where sub = $1\n",
+                    rows_affected = 0u64,
+                    rows_returned = 1u64,
+                    elapsed_secs = 0.25f64,
+                    "",
+                );
+            });
+        });
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let request = spans.iter().find(|s| s.name == "request").unwrap();
+        let query = spans.iter().find(|s| s.name == "SELECT").unwrap();
+        assert_eq!(query.span_kind, SpanKind::Client);
+        assert_eq!(query.parent_span_id, request.span_context.span_id());
+        assert_eq!(
+            attribute(query, DB_QUERY_TEXT).as_deref(),
+            Some("select * from users where sub = $1")
+        );
+        assert_eq!(
+            attribute(query, DB_SYSTEM_NAME).as_deref(),
+            Some("postgresql")
+        );
+        let duration = query.end_time.duration_since(query.start_time).unwrap();
+        assert!(duration >= Duration::from_millis(249), "{:?}", duration);
+    }
+
+    /// Log sink the test reads back
+    #[derive(Clone, Default)]
+    struct Buffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Buffer {
+            self.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_logs_carry_request_id_and_caller() {
+        let buffer = Buffer::default();
+        let subscriber =
+            tracing_subscriber::registry().with(log_layer(LogFormat::Json, buffer.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = Router::new()
+            .route(
+                "/items/:id",
+                get(|| async {
+                    record_caller(&Claims {
+                        sub: "someone".to_string(),
+                        exp: 0,
+                        email_verified: None,
+                        mfa_enabled: None,
+                        email: None,
+                        name: None,
+                        admin: None,
+                        organization: Some("Acme".to_string()),
+                    });
+                    tracing::info!(answer = 42, "handled");
+                    "ok"
+                }),
+            )
+            .layer(http_trace_layer())
+            .layer(axum::middleware::from_fn(
+                crate::middleware::request_id_middleware,
+            ));
+
+        let request = Request::get("/items/7")
+            .header("X-Request-Id", "req-7")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: JsonValue = output
+            .lines()
+            .map(|line| serde_json::from_str::<JsonValue>(line).unwrap())
+            .find(|line| line["message"] == "handled")
+            .unwrap_or_else(|| panic!("no log line in {}", output));
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["span"], "request");
+        assert_eq!(line["request_id"], "req-7");
+        assert_eq!(line["user.sub"], "someone");
+        assert_eq!(line["user.organization"], "Acme");
+        assert_eq!(line["http.route"], "/items/:id");
+        assert_eq!(line["answer"], 42);
+        assert!(line.get("otel.name").is_none());
+    }
+
+    #[test]
+    fn test_short_statements_use_the_summary() {
+        let statement = StatementFields {
+            summary: "SELECT 1".to_string(),
+            statement: String::new(),
+            elapsed_secs: 0.0,
+        };
+        assert_eq!(statement.text(), "SELECT 1");
+    }
+}
@@ -0,0 +1,506 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+    Json,
+};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::error::{AppError, FieldError};
+use crate::models::{ReplacePermissionsRequest, SetPermissionRequest};
+
+/// Maximum length of a permission page name (matches the column size)
+pub const MAX_PAGE_NAME_LENGTH: u64 = 255;
+
+/// Page identifiers: lowercase letters, digits, `-` and `_`, starting with a letter or digit
+pub static PAGE_NAME_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z0-9][a-z0-9_-]*$").expect("valid page name pattern"));
+
+/// Maximum size of a user's `properties` document, serialized
+pub const MAX_PROPERTIES_BYTES: usize = 16 * 1024;
+
+/// Key under which validator reports struct-level (schema) errors
+const STRUCT_LEVEL_KEY: &str = "__all__";
+
+/// JSON body extractor that deserializes and then validates the payload.
+///
+/// Malformed bodies are rejected with the status axum's `Json` would use,
+/// and validation failures become a 422 listing every invalid field.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        validate(&value)?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Run the validation rules of a value assembled outside of `ValidatedJson`
+pub fn validate<T: Validate>(value: &T) -> Result<(), AppError> {
+    value
+        .validate()
+        .map_err(|errors| AppError::validation(field_errors(&errors)))
+}
+
+/// Flatten validator's nested error tree into field errors with JSON paths
+/// such as `permissions[2].page`, ordered by field and list index
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut result = Vec::new();
+    collect_field_errors(errors, "", &mut result);
+    result.sort_by_cached_key(|error| path_sort_key(&error.field));
+    result
+}
+
+/// Sort key that orders `items[2]` before `items[10]`
+fn path_sort_key(path: &str) -> String {
+    let mut key = String::with_capacity(path.len());
+    let mut digits = String::new();
+    for c in path.chars().chain(std::iter::once('\0')) {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if !digits.is_empty() {
+            key.push_str(&format!("{:0>10}", digits));
+            digits.clear();
+        }
+        if c != '\0' {
+            key.push(c);
+        }
+    }
+    key
+}
+
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    for (key, kind) in errors.errors() {
+        let path = join_path(prefix, key);
+        match kind {
+            ValidationErrorsKind::Field(list) => {
+                for error in list {
+                    // Struct-level rules name the offending field in their params
+                    let field = match error.params.get("field").and_then(JsonValue::as_str) {
+                        Some(field) if key == STRUCT_LEVEL_KEY => join_path(prefix, field),
+                        _ if key == STRUCT_LEVEL_KEY => prefix.to_string(),
+                        _ => path.clone(),
+                    };
+                    out.push(FieldError::new(
+                        field,
+                        &error.code,
+                        error_message(key, error),
+                    ));
+                }
+            }
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(nested, &path, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(nested, &format!("{}[{}]", path, index), out);
+                }
+            }
+        }
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Human-readable message, derived from the rule parameters when none was given
+fn error_message(key: &str, error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+    match error.code.as_ref() {
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("{} must be {} to {} characters long", key, min, max),
+            (None, Some(max)) => format!("{} must be at most {} characters long", key, max),
+            (Some(min), None) => format!("{} must be at least {} characters long", key, min),
+            (None, None) => format!("{} has an invalid length", key),
+        },
+        "regex" => format!("{} has an invalid format", key),
+        "email" => format!("{} must be a valid email address", key),
+        code => format!("{} is invalid ({})", key, code),
+    }
+}
+
+fn struct_error(code: &'static str, field: &str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Owned(message));
+    error.add_param(Cow::Borrowed("field"), &field);
+    error
+}
+
+/// Reject strings that are empty after trimming whitespace
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        let mut error = ValidationError::new("blank");
+        error.message = Some(Cow::Borrowed("must not be blank"));
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Editing a scope requires being able to view it. Each scope is its own
+/// schema rule on `SetPermissionRequest`, so every violating pair is reported.
+pub fn edit_implies_view(permission: &SetPermissionRequest) -> Result<(), ValidationError> {
+    edit_requires_view(
+        "can_edit",
+        permission.can_edit,
+        permission.can_view,
+        "can_view",
+    )
+}
+
+pub fn edit_own_implies_view_own(permission: &SetPermissionRequest) -> Result<(), ValidationError> {
+    edit_requires_view(
+        "can_edit_own",
+        permission.can_edit_own,
+        permission.can_view_own,
+        "can_view_own",
+    )
+}
+
+pub fn edit_ours_implies_view_ours(
+    permission: &SetPermissionRequest,
+) -> Result<(), ValidationError> {
+    edit_requires_view(
+        "can_edit_ours",
+        permission.can_edit_ours,
+        permission.can_view_ours,
+        "can_view_ours",
+    )
+}
+
+fn edit_requires_view(
+    edit_field: &str,
+    can_edit: bool,
+    can_view: bool,
+    view_field: &str,
+) -> Result<(), ValidationError> {
+    if can_edit && !can_view {
+        return Err(struct_error(
+            "edit_requires_view",
+            edit_field,
+            format!("{} requires {}", edit_field, view_field),
+        ));
+    }
+    Ok(())
+}
+
+/// Each page may appear only once in a permission matrix
+pub fn unique_pages(request: &ReplacePermissionsRequest) -> Result<(), ValidationError> {
+    let mut seen = HashSet::new();
+    for (index, permission) in request.permissions.iter().enumerate() {
+        if !seen.insert(permission.page.as_str()) {
+            return Err(struct_error(
+                "duplicate",
+                &format!("permissions[{}].page", index),
+                format!("page '{}' is listed more than once", permission.page),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// User properties are a JSON object of bounded size
+pub fn properties_object(value: &JsonValue) -> Result<(), ValidationError> {
+    if !value.is_object() {
+        let mut error = ValidationError::new("type");
+        error.message = Some(Cow::Borrowed("must be a JSON object"));
+        return Err(error);
+    }
+    if value.to_string().len() > MAX_PROPERTIES_BYTES {
+        let mut error = ValidationError::new("too_large");
+        error.message = Some(Cow::Owned(format!(
+            "must be at most {} bytes when serialized",
+            MAX_PROPERTIES_BYTES
+        )));
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// A properties schema must be a JSON Schema object that compiles without
+/// fetching remote references
+pub fn valid_properties_schema(value: &JsonValue) -> Result<(), ValidationError> {
+    if !value.is_object() {
+        let mut error = ValidationError::new("type");
+        error.message = Some(Cow::Borrowed("must be a JSON Schema object"));
+        return Err(error);
+    }
+    if let Err(invalid) = jsonschema::validator_for(value) {
+        let mut error = ValidationError::new("invalid_schema");
+        error.message = Some(Cow::Owned(invalid.to_string()));
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Check user properties against an organization's schema, reporting each
+/// violation at its path below `properties`
+pub fn check_properties(schema: &JsonValue, properties: &JsonValue) -> Result<(), AppError> {
+    // Schemas are checked when they are stored, so this only fails if one was
+    // written to the database directly
+    let validator = jsonschema::validator_for(schema).map_err(|error| {
+        tracing::error!("Invalid organization properties schema: {}", error);
+        AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "invalid_properties_schema",
+            "The organization's properties schema is invalid",
+        )
+    })?;
+
+    let mut errors: Vec<FieldError> = validator
+        .iter_errors(properties)
+        .map(|error| {
+            FieldError::new(
+                pointer_to_path("properties", error.instance_path().as_str()),
+                &snake_case(error.kind().keyword()),
+                error.to_string(),
+            )
+        })
+        .collect();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.sort_by_cached_key(|error| path_sort_key(&error.field));
+    Err(AppError::validation(errors))
+}
+
+/// Turn a JSON pointer such as `/tags/0` into a path such as `properties.tags[0]`
+fn pointer_to_path(prefix: &str, pointer: &str) -> String {
+    let mut path = prefix.to_string();
+    for segment in pointer.split('/').skip(1) {
+        let segment = segment.replace("~1", "/").replace("~0", "~");
+        if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+            path.push_str(&format!("[{}]", segment));
+        } else {
+            path = join_path(&path, &segment);
+        }
+    }
+    path
+}
+
+/// `additionalProperties` -> `additional_properties`
+fn snake_case(keyword: &str) -> String {
+    let mut code = String::with_capacity(keyword.len() + 4);
+    for c in keyword.chars() {
+        if c.is_ascii_uppercase() {
+            code.push('_');
+            code.push(c.to_ascii_lowercase());
+        } else {
+            code.push(c);
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateRoleRequest;
+
+    fn permission(page: &str) -> SetPermissionRequest {
+        SetPermissionRequest {
+            page: page.to_string(),
+            can_view: true,
+            can_edit: false,
+            can_view_own: false,
+            can_edit_own: false,
+            can_view_ours: false,
+            can_edit_ours: false,
+        }
+    }
+
+    #[test]
+    fn test_create_role_reports_every_field() {
+        let request = CreateRoleRequest {
+            name: "   ".to_string(),
+            description: Some("x".repeat(2000)),
+            is_admin: false,
+        };
+
+        let errors = field_errors(&request.validate().unwrap_err());
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].field, "description");
+        assert_eq!(errors[0].code, "length");
+        assert_eq!(errors[1].field, "name");
+        assert_eq!(errors[1].code, "blank");
+    }
+
+    #[test]
+    fn test_page_name_pattern() {
+        assert!(permission("support").validate().is_ok());
+        assert!(permission("audit-log_2").validate().is_ok());
+
+        let errors = field_errors(&permission("my page").validate().unwrap_err());
+        assert_eq!(errors[0].field, "page");
+        assert_eq!(errors[0].code, "regex");
+    }
+
+    #[test]
+    fn test_edit_implies_view_points_at_field() {
+        let mut request = permission("support");
+        request.can_edit_own = true;
+
+        let errors = field_errors(&request.validate().unwrap_err());
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "can_edit_own");
+        assert_eq!(errors[0].code, "edit_requires_view");
+    }
+
+    #[test]
+    fn test_edit_implies_view_reports_every_pair() {
+        let mut request = permission("support");
+        request.can_view = false;
+        request.can_edit = true;
+        request.can_edit_own = true;
+        request.can_edit_ours = true;
+
+        let errors = field_errors(&request.validate().unwrap_err());
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+
+        assert_eq!(fields, vec!["can_edit", "can_edit_ours", "can_edit_own"]);
+    }
+
+    #[test]
+    fn test_nested_permission_paths() {
+        let mut invalid = permission("users");
+        invalid.can_edit = true;
+        invalid.can_view = false;
+        let request = ReplacePermissionsRequest {
+            version: None,
+            permissions: vec![permission("dashboard"), permission("Bad Page"), invalid],
+        };
+
+        let errors = field_errors(&request.validate().unwrap_err());
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+
+        assert_eq!(
+            fields,
+            vec!["permissions[1].page", "permissions[2].can_edit"]
+        );
+    }
+
+    #[test]
+    fn test_path_sort_key_orders_indices_numerically() {
+        assert!(path_sort_key("permissions[2].page") < path_sort_key("permissions[10].page"));
+        assert!(path_sort_key("description") < path_sort_key("name"));
+    }
+
+    #[test]
+    fn test_duplicate_pages_rejected() {
+        let request = ReplacePermissionsRequest {
+            version: None,
+            permissions: vec![permission("dashboard"), permission("dashboard")],
+        };
+
+        let errors = field_errors(&request.validate().unwrap_err());
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "permissions[1].page");
+        assert_eq!(errors[0].code, "duplicate");
+    }
+
+    #[test]
+    fn test_permission_matrix_reports_every_row() {
+        let request = ReplacePermissionsRequest {
+            version: None,
+            permissions: vec![
+                permission("dashboard"),
+                permission("  "),
+                permission("dashboard"),
+                permission(&"x".repeat(MAX_PAGE_NAME_LENGTH as usize + 1)),
+            ],
+        };
+
+        let errors = field_errors(&request.validate().unwrap_err());
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+
+        assert_eq!(
+            fields,
+            vec![
+                "permissions[1].page",
+                "permissions[2].page",
+                "permissions[3].page"
+            ]
+        );
+        assert_eq!(errors[1].code, "duplicate");
+    }
+
+    #[test]
+    fn test_properties_must_be_a_small_object() {
+        assert!(properties_object(&serde_json::json!({"theme": "dark"})).is_ok());
+        assert_eq!(
+            properties_object(&serde_json::json!(["dark"]))
+                .unwrap_err()
+                .code,
+            "type"
+        );
+        let large = serde_json::json!({ "notes": "x".repeat(MAX_PROPERTIES_BYTES) });
+        assert_eq!(properties_object(&large).unwrap_err().code, "too_large");
+    }
+
+    #[test]
+    fn test_invalid_properties_schema_rejected() {
+        let schema = serde_json::json!({"type": "object"});
+        assert!(valid_properties_schema(&schema).is_ok());
+
+        let schema = serde_json::json!({"type": "no-such-type"});
+        assert_eq!(
+            valid_properties_schema(&schema).unwrap_err().code,
+            "invalid_schema"
+        );
+    }
+
+    #[test]
+    fn test_schema_violations_point_at_properties() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "additionalProperties": false
+        });
+        let properties = serde_json::json!({"tags": ["a", 1], "shoe_size": 42});
+
+        let problem = check_properties(&schema, &properties)
+            .unwrap_err()
+            .problem();
+
+        let fields: Vec<(&str, &str)> = problem
+            .errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.code.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("properties", "additional_properties"),
+                ("properties.tags[1]", "type"),
+            ]
+        );
+        assert!(check_properties(&schema, &serde_json::json!({"tags": []})).is_ok());
+    }
+}
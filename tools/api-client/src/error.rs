@@ -0,0 +1,79 @@
+use reqwest::StatusCode;
+use rust_backend_template::error::ProblemDetails;
+
+/// Error returned by every client call
+#[derive(Debug)]
+pub enum ClientError {
+    /// The API rejected the request with an RFC 7807 problem document
+    Api {
+        status: StatusCode,
+        problem: Box<ProblemDetails>,
+    },
+    /// The API answered with an error status but without a problem document
+    UnexpectedStatus { status: StatusCode, body: String },
+    /// The request could not be sent or the response could not be read
+    Transport(reqwest::Error),
+    /// The response body did not match the expected type
+    Decode(serde_json::Error),
+    /// The base URL cannot be used to build request URLs
+    InvalidUrl(String),
+}
+
+impl ClientError {
+    /// HTTP status of the failed response, if the server answered
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Api { status, .. } | ClientError::UnexpectedStatus { status, .. } => {
+                Some(*status)
+            }
+            ClientError::Transport(e) => e.status(),
+            ClientError::Decode(_) | ClientError::InvalidUrl(_) => None,
+        }
+    }
+
+    /// Stable machine-readable error code from the problem document
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Api { problem, .. } => Some(&problem.code),
+            _ => None,
+        }
+    }
+
+    /// The problem document, when the API sent one
+    pub fn problem(&self) -> Option<&ProblemDetails> {
+        match self {
+            ClientError::Api { problem, .. } => Some(problem.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Api { status, problem } => {
+                write!(f, "{} ({}): {}", status, problem.code, problem.detail)
+            }
+            ClientError::UnexpectedStatus { status, body } => write!(f, "{}: {}", status, body),
+            ClientError::Transport(e) => write!(f, "transport error: {}", e),
+            ClientError::Decode(e) => write!(f, "invalid response body: {}", e),
+            ClientError::InvalidUrl(url) => write!(f, "invalid base URL: {}", url),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Transport(e) => Some(e),
+            ClientError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Transport(e)
+    }
+}
@@ -0,0 +1,605 @@
+//! Typed client for the backend's `/api/v1` API.
+//!
+//! Request and response types are the backend's own `models`, so the client
+//! cannot drift from the server. Calls attach the bearer token, retry
+//! transient failures according to a [`RetryPolicy`] and return
+//! [`ClientError`] with the server's problem document on failure.
+//!
+//! Collection endpoints return one [`models::Page`] at a time; pass its
+//! `next_cursor` back in [`models::PaginationQuery`] to fetch the next one.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), api_client::ClientError> {
+//! let client = api_client::ApiClient::builder("http://localhost:3000")
+//!     .token("eyJ...")
+//!     .build()?;
+//! let roles = client
+//!     .list_roles(&Default::default(), &Default::default())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod error;
+mod retry;
+
+pub use error::ClientError;
+pub use retry::RetryPolicy;
+pub use rust_backend_template::error::{FieldError, ProblemDetails};
+pub use rust_backend_template::models;
+
+use reqwest::{Method, RequestBuilder, Response, Url};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use uuid::Uuid;
+
+use models::{
+    AuditChainQuery, AuditChainVerification, AuditEvent, AuditEventQuery, CreateRoleRequest,
+    CreateTicketCommentRequest, CreateTicketRequest, DefaultPreferencesResponse, HealthResponse,
+    OnboardingResponse, Page, PaginationQuery, Permission, PermissionFlags,
+    PermissionMatrixResponse, PreferenceOverrides, PreferencesResponse, ProfileResponse,
+    PropertiesSchemaRequest, PropertiesSchemaResponse, ReplacePermissionsRequest,
+    ReplacePermissionsResponse, Role, RoleListQuery, RoleWithPermissions, SupportTicket,
+    TicketAttachment, TicketComment, TicketDetail, TicketListQuery, UpdatePreferencesRequest,
+    UpdateProfileRequest, UpdateRoleRequest, UpdateTicketRequest, UpdateUserRequest,
+    UptimeResponse, User, UserListQuery, UserRole, UserWithRoles, ValidateTokenRequest,
+    ValidateTokenResponse, VersionResponse,
+};
+
+/// Path segments of the versioned API prefix
+const API_V1_SEGMENTS: [&str; 2] = ["api", "v1"];
+
+/// Default timeout of a single attempt
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configures an [`ApiClient`]
+pub struct ApiClientBuilder {
+    base_url: String,
+    token: Option<String>,
+    retry: RetryPolicy,
+    timeout: Duration,
+    http: Option<reqwest::Client>,
+}
+
+impl ApiClientBuilder {
+    /// Bearer token sent with every request
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Timeout of a single attempt; ignored when a custom HTTP client is given
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Use a preconfigured reqwest client (proxies, TLS roots, ...)
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    pub fn build(self) -> Result<ApiClient, ClientError> {
+        let base_url = Url::parse(&self.base_url)
+            .ok()
+            .filter(|url| !url.cannot_be_a_base())
+            .ok_or_else(|| ClientError::InvalidUrl(self.base_url.clone()))?;
+
+        let http = match self.http {
+            Some(http) => http,
+            None => reqwest::Client::builder().timeout(self.timeout).build()?,
+        };
+
+        Ok(ApiClient {
+            http,
+            base_url,
+            token: self.token,
+            retry: self.retry,
+        })
+    }
+}
+
+/// Client for the backend API. Cheap to clone; clones share the connection pool.
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: Url,
+    token: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl ApiClient {
+    /// Start configuring a client for the server at `base_url` (without `/api/v1`)
+    pub fn builder(base_url: impl Into<String>) -> ApiClientBuilder {
+        ApiClientBuilder {
+            base_url: base_url.into(),
+            token: None,
+            retry: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            http: None,
+        }
+    }
+
+    /// Same client acting with a different bearer token
+    pub fn with_token(&self, token: impl Into<String>) -> Self {
+        ApiClient {
+            token: Some(token.into()),
+            ..self.clone()
+        }
+    }
+
+    // ==================== System ====================
+
+    /// `GET /health`
+    pub async fn health(&self) -> Result<HealthResponse, ClientError> {
+        self.get(&["health"]).await
+    }
+
+    /// `GET /system/version`
+    pub async fn version(&self) -> Result<VersionResponse, ClientError> {
+        self.get(&["system", "version"]).await
+    }
+
+    /// `POST /validate-token`
+    pub async fn validate_token(&self, token: &str) -> Result<ValidateTokenResponse, ClientError> {
+        let body = ValidateTokenRequest {
+            token: token.to_string(),
+        };
+        self.json(Method::POST, &["validate-token"], |r| r.json(&body))
+            .await
+    }
+
+    /// `GET /system/uptime`
+    pub async fn uptime(&self) -> Result<UptimeResponse, ClientError> {
+        self.get(&["system", "uptime"]).await
+    }
+
+    /// `POST /system/onboarding`
+    pub async fn onboard(&self) -> Result<OnboardingResponse, ClientError> {
+        self.json(Method::POST, &["system", "onboarding"], |r| r)
+            .await
+    }
+
+    /// `GET /profile`
+    pub async fn profile(&self) -> Result<ProfileResponse, ClientError> {
+        self.get(&["profile"]).await
+    }
+
+    /// `PATCH /profile`
+    pub async fn update_profile(
+        &self,
+        update: &UpdateProfileRequest,
+    ) -> Result<ProfileResponse, ClientError> {
+        self.json(Method::PATCH, &["profile"], |r| r.json(update))
+            .await
+    }
+
+    /// `GET /preferences`
+    pub async fn preferences(&self) -> Result<PreferencesResponse, ClientError> {
+        self.get(&["preferences"]).await
+    }
+
+    /// `PATCH /preferences`
+    pub async fn update_preferences(
+        &self,
+        update: &UpdatePreferencesRequest,
+    ) -> Result<PreferencesResponse, ClientError> {
+        self.json(Method::PATCH, &["preferences"], |r| r.json(update))
+            .await
+    }
+
+    // ==================== Roles ====================
+
+    /// `GET /admin/roles`
+    pub async fn list_roles(
+        &self,
+        filter: &RoleListQuery,
+        page: &PaginationQuery,
+    ) -> Result<Page<RoleWithPermissions>, ClientError> {
+        self.json(Method::GET, &["admin", "roles"], |r| {
+            r.query(filter).query(page)
+        })
+        .await
+    }
+
+    /// `POST /admin/roles`
+    pub async fn create_role(&self, role: &CreateRoleRequest) -> Result<Role, ClientError> {
+        self.json(Method::POST, &["admin", "roles"], |r| r.json(role))
+            .await
+    }
+
+    /// `GET /admin/roles/:role_id`
+    pub async fn get_role(&self, role_id: Uuid) -> Result<RoleWithPermissions, ClientError> {
+        self.get(&["admin", "roles", &role_id.to_string()]).await
+    }
+
+    /// `PATCH /admin/roles/:role_id`
+    pub async fn update_role(
+        &self,
+        role_id: Uuid,
+        update: &UpdateRoleRequest,
+    ) -> Result<Role, ClientError> {
+        let path = ["admin", "roles", &role_id.to_string()];
+        self.json(Method::PATCH, &path, |r| r.json(update)).await
+    }
+
+    /// `DELETE /admin/roles/:role_id`
+    pub async fn delete_role(&self, role_id: Uuid) -> Result<(), ClientError> {
+        self.empty(Method::DELETE, &["admin", "roles", &role_id.to_string()])
+            .await
+    }
+
+    /// `GET /admin/roles/:role_id/permissions`
+    pub async fn get_role_permissions(
+        &self,
+        role_id: Uuid,
+    ) -> Result<PermissionMatrixResponse, ClientError> {
+        self.get(&["admin", "roles", &role_id.to_string(), "permissions"])
+            .await
+    }
+
+    /// `PUT /admin/roles/:role_id/permissions`
+    pub async fn replace_role_permissions(
+        &self,
+        role_id: Uuid,
+        matrix: &ReplacePermissionsRequest,
+    ) -> Result<ReplacePermissionsResponse, ClientError> {
+        let path = ["admin", "roles", &role_id.to_string(), "permissions"];
+        self.json(Method::PUT, &path, |r| r.json(matrix)).await
+    }
+
+    /// `PUT /admin/roles/:role_id/permissions/:page`
+    pub async fn set_role_permission(
+        &self,
+        role_id: Uuid,
+        page: &str,
+        flags: &PermissionFlags,
+    ) -> Result<Permission, ClientError> {
+        let path = ["admin", "roles", &role_id.to_string(), "permissions", page];
+        self.json(Method::PUT, &path, |r| r.json(flags)).await
+    }
+
+    // ==================== Users ====================
+
+    /// `GET /admin/users`
+    pub async fn list_users(
+        &self,
+        filter: &UserListQuery,
+        page: &PaginationQuery,
+    ) -> Result<Page<UserWithRoles>, ClientError> {
+        self.json(Method::GET, &["admin", "users"], |r| {
+            r.query(filter).query(page)
+        })
+        .await
+    }
+
+    /// `POST /admin/users/:user_id/deactivate`
+    pub async fn deactivate_user(&self, user_id: Uuid) -> Result<User, ClientError> {
+        let path = ["admin", "users", &user_id.to_string(), "deactivate"];
+        self.json(Method::POST, &path, |r| r).await
+    }
+
+    /// `POST /admin/users/:user_id/reactivate`
+    pub async fn reactivate_user(&self, user_id: Uuid) -> Result<User, ClientError> {
+        let path = ["admin", "users", &user_id.to_string(), "reactivate"];
+        self.json(Method::POST, &path, |r| r).await
+    }
+
+    /// `POST /admin/users/:user_id/anonymize`
+    pub async fn anonymize_user(&self, user_id: Uuid) -> Result<User, ClientError> {
+        let path = ["admin", "users", &user_id.to_string(), "anonymize"];
+        self.json(Method::POST, &path, |r| r).await
+    }
+
+    /// `PATCH /admin/users/:user_id`
+    pub async fn update_user(
+        &self,
+        user_id: Uuid,
+        update: &UpdateUserRequest,
+    ) -> Result<User, ClientError> {
+        let path = ["admin", "users", &user_id.to_string()];
+        self.json(Method::PATCH, &path, |r| r.json(update)).await
+    }
+
+    /// `DELETE /admin/users/:user_id`
+    pub async fn delete_user(&self, user_id: Uuid) -> Result<(), ClientError> {
+        self.empty(Method::DELETE, &["admin", "users", &user_id.to_string()])
+            .await
+    }
+
+    /// `GET /admin/users/:user_id/roles`
+    pub async fn get_user_roles(
+        &self,
+        user_id: Uuid,
+        page: &PaginationQuery,
+    ) -> Result<Page<Role>, ClientError> {
+        let path = ["admin", "users", &user_id.to_string(), "roles"];
+        self.json(Method::GET, &path, |r| r.query(page)).await
+    }
+
+    /// `PUT /admin/users/:user_id/roles/:role_id`
+    pub async fn assign_user_role(
+        &self,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<UserRole, ClientError> {
+        let path = [
+            "admin",
+            "users",
+            &user_id.to_string(),
+            "roles",
+            &role_id.to_string(),
+        ];
+        self.json(Method::PUT, &path, |r| r).await
+    }
+
+    /// `DELETE /admin/users/:user_id/roles/:role_id`
+    pub async fn remove_user_role(&self, user_id: Uuid, role_id: Uuid) -> Result<(), ClientError> {
+        let path = [
+            "admin",
+            "users",
+            &user_id.to_string(),
+            "roles",
+            &role_id.to_string(),
+        ];
+        self.empty(Method::DELETE, &path).await
+    }
+
+    // ==================== Organizations ====================
+
+    /// `GET /admin/organizations/:organization_id/properties-schema`
+    pub async fn get_properties_schema(
+        &self,
+        organization_id: Uuid,
+    ) -> Result<PropertiesSchemaResponse, ClientError> {
+        self.get(&[
+            "admin",
+            "organizations",
+            &organization_id.to_string(),
+            "properties-schema",
+        ])
+        .await
+    }
+
+    /// `PUT /admin/organizations/:organization_id/properties-schema`
+    pub async fn put_properties_schema(
+        &self,
+        organization_id: Uuid,
+        schema: &PropertiesSchemaRequest,
+    ) -> Result<PropertiesSchemaResponse, ClientError> {
+        let path = [
+            "admin",
+            "organizations",
+            &organization_id.to_string(),
+            "properties-schema",
+        ];
+        self.json(Method::PUT, &path, |r| r.json(schema)).await
+    }
+
+    /// `GET /admin/organizations/:organization_id/default-preferences`
+    pub async fn get_default_preferences(
+        &self,
+        organization_id: Uuid,
+    ) -> Result<DefaultPreferencesResponse, ClientError> {
+        self.get(&[
+            "admin",
+            "organizations",
+            &organization_id.to_string(),
+            "default-preferences",
+        ])
+        .await
+    }
+
+    /// `PUT /admin/organizations/:organization_id/default-preferences`
+    pub async fn put_default_preferences(
+        &self,
+        organization_id: Uuid,
+        overrides: &PreferenceOverrides,
+    ) -> Result<DefaultPreferencesResponse, ClientError> {
+        let path = [
+            "admin",
+            "organizations",
+            &organization_id.to_string(),
+            "default-preferences",
+        ];
+        self.json(Method::PUT, &path, |r| r.json(overrides)).await
+    }
+
+    // ==================== Audit Log ====================
+
+    /// `GET /admin/audit-events`
+    pub async fn list_audit_events(
+        &self,
+        filter: &AuditEventQuery,
+        page: &PaginationQuery,
+    ) -> Result<Page<AuditEvent>, ClientError> {
+        self.json(Method::GET, &["admin", "audit-events"], |r| {
+            r.query(filter).query(page)
+        })
+        .await
+    }
+
+    /// `GET /admin/audit-events/verify`
+    pub async fn verify_audit_chain(
+        &self,
+        chain: &AuditChainQuery,
+    ) -> Result<AuditChainVerification, ClientError> {
+        let path = ["admin", "audit-events", "verify"];
+        self.json(Method::GET, &path, |r| r.query(chain)).await
+    }
+
+    // ==================== Support ====================
+
+    /// `GET /support/tickets`
+    pub async fn list_tickets(
+        &self,
+        filter: &TicketListQuery,
+        page: &PaginationQuery,
+    ) -> Result<Page<SupportTicket>, ClientError> {
+        self.json(Method::GET, &["support", "tickets"], |r| {
+            r.query(filter).query(page)
+        })
+        .await
+    }
+
+    /// `POST /support/tickets`
+    pub async fn create_ticket(
+        &self,
+        ticket: &CreateTicketRequest,
+    ) -> Result<SupportTicket, ClientError> {
+        self.json(Method::POST, &["support", "tickets"], |r| r.json(ticket))
+            .await
+    }
+
+    /// `GET /support/tickets/:ticket_id`
+    pub async fn get_ticket(&self, ticket_id: Uuid) -> Result<TicketDetail, ClientError> {
+        self.get(&["support", "tickets", &ticket_id.to_string()])
+            .await
+    }
+
+    /// `PATCH /support/tickets/:ticket_id`
+    pub async fn update_ticket(
+        &self,
+        ticket_id: Uuid,
+        changes: &UpdateTicketRequest,
+    ) -> Result<SupportTicket, ClientError> {
+        let path = ["support", "tickets", &ticket_id.to_string()];
+        self.json(Method::PATCH, &path, |r| r.json(changes)).await
+    }
+
+    /// `POST /support/tickets/:ticket_id/comments`
+    pub async fn create_ticket_comment(
+        &self,
+        ticket_id: Uuid,
+        comment: &CreateTicketCommentRequest,
+    ) -> Result<TicketComment, ClientError> {
+        let path = ["support", "tickets", &ticket_id.to_string(), "comments"];
+        self.json(Method::POST, &path, |r| r.json(comment)).await
+    }
+
+    /// `POST /support/tickets/:ticket_id/attachments`
+    pub async fn upload_ticket_attachment(
+        &self,
+        ticket_id: Uuid,
+        filename: &str,
+        content_type: &str,
+        content: &[u8],
+    ) -> Result<TicketAttachment, ClientError> {
+        let path = ["support", "tickets", &ticket_id.to_string(), "attachments"];
+        self.json(Method::POST, &path, |r| {
+            r.query(&[("filename", filename)])
+                .header(reqwest::header::CONTENT_TYPE, content_type)
+                .body(content.to_vec())
+        })
+        .await
+    }
+
+    /// `GET /support/tickets/:ticket_id/attachments/:attachment_id`, the file content
+    pub async fn download_ticket_attachment(
+        &self,
+        ticket_id: Uuid,
+        attachment_id: Uuid,
+    ) -> Result<Vec<u8>, ClientError> {
+        let path = [
+            "support",
+            "tickets",
+            &ticket_id.to_string(),
+            "attachments",
+            &attachment_id.to_string(),
+        ];
+        let response = self.execute(Method::GET, &path, |r| r).await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    // ==================== Transport ====================
+
+    async fn get<T: DeserializeOwned>(&self, path: &[&str]) -> Result<T, ClientError> {
+        self.json(Method::GET, path, |r| r).await
+    }
+
+    /// Send a request and decode its JSON response body
+    async fn json<T, F>(&self, method: Method, path: &[&str], build: F) -> Result<T, ClientError>
+    where
+        T: DeserializeOwned,
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let response = self.execute(method, path, build).await?;
+        let bytes = response.bytes().await?;
+        serde_json::from_slice(&bytes).map_err(ClientError::Decode)
+    }
+
+    /// Send a request whose successful response has no body
+    async fn empty(&self, method: Method, path: &[&str]) -> Result<(), ClientError> {
+        self.execute(method, path, |r| r).await.map(|_| ())
+    }
+
+    fn url(&self, path: &[&str]) -> Result<Url, ClientError> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| ClientError::InvalidUrl(self.base_url.to_string()))?
+            .pop_if_empty()
+            .extend(API_V1_SEGMENTS)
+            .extend(path);
+        Ok(url)
+    }
+
+    /// Send a request, retrying per the policy, and turn error statuses into `ClientError`
+    async fn execute<F>(
+        &self,
+        method: Method,
+        path: &[&str],
+        build: F,
+    ) -> Result<Response, ClientError>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let url = self.url(path)?;
+        let mut attempt = 0;
+
+        loop {
+            let mut request = build(self.http.request(method.clone(), url.clone()));
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+
+            let outcome = request.send().await;
+            let retry = match &outcome {
+                Ok(response) if retry::should_retry_status(&method, response.status()) => {
+                    Some(retry::retry_after(response))
+                }
+                Err(error) if retry::should_retry_error(&method, error) => Some(None),
+                _ => None,
+            };
+
+            match retry {
+                Some(hint) if attempt < self.retry.max_retries => {
+                    tokio::time::sleep(self.retry.delay(attempt, hint)).await;
+                    attempt += 1;
+                }
+                _ => return error_for_status(outcome?).await,
+            }
+        }
+    }
+}
+
+async fn error_for_status(response: Response) -> Result<Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await?;
+    match serde_json::from_str::<ProblemDetails>(&body) {
+        Ok(problem) => Err(ClientError::Api {
+            status,
+            problem: Box::new(problem),
+        }),
+        Err(_) => Err(ClientError::UnexpectedStatus { status, body }),
+    }
+}
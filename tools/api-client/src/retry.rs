@@ -0,0 +1,123 @@
+use reqwest::{header, Method, Response, StatusCode};
+use std::time::Duration;
+
+/// When and how long to wait before retrying a failed request.
+///
+/// Rate-limited requests (429) are retried for every method. Transport
+/// failures and 502/503/504 are only retried for idempotent methods, since
+/// the server may already have applied a non-idempotent request.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based): exponential backoff,
+    /// or the server's `Retry-After` hint, capped at `max_backoff`
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = retry_after.unwrap_or_else(|| {
+            self.initial_backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+        });
+        backoff.min(self.max_backoff)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    )
+}
+
+/// Whether a response status is worth retrying for this method
+pub(crate) fn should_retry_status(method: &Method, status: StatusCode) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            is_idempotent(method)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a transport failure is worth retrying for this method
+pub(crate) fn should_retry_error(method: &Method, error: &reqwest::Error) -> bool {
+    is_idempotent(method) && (error.is_connect() || error.is_timeout())
+}
+
+/// `Retry-After` given in seconds; HTTP-date values are ignored
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_backs_off_exponentially_up_to_cap() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+
+        assert_eq!(policy.delay(0, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(400));
+        assert_eq!(policy.delay(3, None), Duration::from_millis(500));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(60))),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn test_only_idempotent_methods_retry_server_errors() {
+        assert!(should_retry_status(
+            &Method::GET,
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(!should_retry_status(
+            &Method::POST,
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(should_retry_status(
+            &Method::POST,
+            StatusCode::TOO_MANY_REQUESTS
+        ));
+        assert!(!should_retry_status(
+            &Method::GET,
+            StatusCode::INTERNAL_SERVER_ERROR
+        ));
+    }
+}
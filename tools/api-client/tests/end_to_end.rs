@@ -0,0 +1,1014 @@
+//! Exercises the client against the backend router served in-process.
+//!
+//! The mock router covers every endpoint without PostgreSQL. Tests of the real
+//! router need `DATABASE_URL`, since every authenticated request checks that
+//! the account is still active, and are skipped without it.
+
+use api_client::models::{
+    AppState, AuditChainQuery, AuditEventQuery, Claims, CreateRoleRequest,
+    CreateTicketCommentRequest, CreateTicketRequest, Language, PaginationQuery, PermissionFlags,
+    PreferenceOverrides, PropertiesSchemaRequest, ReplacePermissionsRequest, RoleListQuery, Theme,
+    TicketListQuery, TicketPriority, TicketStatus, UpdatePreferencesRequest, UpdateProfileRequest,
+    UpdateRoleRequest, UpdateTicketRequest, UpdateUserRequest, UserListQuery, VersionResponse,
+};
+use api_client::{ApiClient, ClientError, RetryPolicy};
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use rust_backend_template::metrics::Metrics;
+use rust_backend_template::migrations::run_migrations;
+use rust_backend_template::rate_limit::{
+    Algorithm, KeyKind, PostgresStore, RateLimitRule, RateLimiter,
+};
+use rust_backend_template::routes::{build_mock_router, build_router};
+use sqlx::postgres::PgPoolOptions;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+const JWT_SECRET: &str = "end-to-end-secret";
+
+fn state() -> Arc<AppState> {
+    Arc::new(AppState {
+        jwt_secret: JWT_SECRET.into(),
+        start_time: SystemTime::now(),
+        // Never connected: the mock router does not use it
+        db_pool: PgPoolOptions::new()
+            .connect_lazy("postgres://unused@127.0.0.1:1/unused")
+            .unwrap(),
+        metrics: Metrics::default(),
+        shutdown: Default::default(),
+        rate_limiter: Default::default(),
+        trusted_proxies: Default::default(),
+    })
+}
+
+/// State backed by the database at `DATABASE_URL`, migrated, if one is set
+async fn database_state() -> Option<Arc<AppState>> {
+    let Ok(database_url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set; skipping a test of the real router");
+        return None;
+    };
+    let db_pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&database_url)
+        .await
+        .unwrap();
+    run_migrations(&db_pool).await.unwrap();
+
+    Some(Arc::new(AppState {
+        jwt_secret: JWT_SECRET.into(),
+        start_time: SystemTime::now(),
+        db_pool,
+        metrics: Metrics::default(),
+        shutdown: Default::default(),
+        rate_limiter: Default::default(),
+        trusted_proxies: Default::default(),
+    }))
+}
+
+fn token(admin: bool) -> String {
+    token_for("client-test", None, admin)
+}
+
+fn token_for(sub: &str, organization: Option<&str>, admin: bool) -> String {
+    let claims = Claims {
+        sub: sub.to_string(),
+        exp: 4_102_444_800,
+        email_verified: Some(true),
+        mfa_enabled: Some(true),
+        email: Some("client@example.com".to_string()),
+        name: Some("Client Test".to_string()),
+        admin: Some(admin),
+        organization: organization.map(str::to_string),
+    };
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+/// Serve a router on an ephemeral port and return its base URL
+async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+fn client(base_url: &str) -> ApiClient {
+    ApiClient::builder(base_url)
+        .token(token(true))
+        .retry_policy(RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(5),
+            max_backoff: Duration::from_millis(20),
+        })
+        .build()
+        .unwrap()
+}
+
+fn flags() -> PermissionFlags {
+    PermissionFlags {
+        can_view: true,
+        can_edit: false,
+        can_view_own: false,
+        can_edit_own: false,
+        can_view_ours: false,
+        can_edit_ours: false,
+    }
+}
+
+#[tokio::test]
+async fn test_every_endpoint_decodes() {
+    let client = client(&serve(build_mock_router(state())).await);
+    let id = Uuid::new_v4();
+
+    client.health().await.unwrap();
+    client.version().await.unwrap();
+    client.validate_token("token").await.unwrap();
+    client.uptime().await.unwrap();
+    client.onboard().await.unwrap();
+    client.profile().await.unwrap();
+    client
+        .update_profile(&UpdateProfileRequest {
+            user_fullname: Some("Client Test".to_string()),
+            properties: Some(serde_json::json!({"theme": "dark"})),
+        })
+        .await
+        .unwrap();
+    client.preferences().await.unwrap();
+    client
+        .update_preferences(&UpdatePreferencesRequest {
+            theme: Some(Some(Theme::Dark)),
+            language: Some(None),
+        })
+        .await
+        .unwrap();
+
+    let roles = client
+        .list_roles(&RoleListQuery::default(), &PaginationQuery::default())
+        .await
+        .unwrap();
+    assert!(!roles.items.is_empty());
+    client
+        .create_role(&CreateRoleRequest {
+            name: "Support".to_string(),
+            description: None,
+            is_admin: false,
+        })
+        .await
+        .unwrap();
+    client.get_role(id).await.unwrap();
+    client
+        .update_role(
+            id,
+            &UpdateRoleRequest {
+                name: None,
+                description: Some("Updated".to_string()),
+                is_admin: None,
+            },
+        )
+        .await
+        .unwrap();
+    client.delete_role(id).await.unwrap();
+    client.get_role_permissions(id).await.unwrap();
+    client
+        .replace_role_permissions(
+            id,
+            &ReplacePermissionsRequest {
+                version: None,
+                permissions: vec![flags().for_page("dashboard".to_string())],
+            },
+        )
+        .await
+        .unwrap();
+    client
+        .set_role_permission(id, "support tickets", &flags())
+        .await
+        .unwrap();
+
+    let page = PaginationQuery {
+        cursor: None,
+        limit: Some(10),
+        include_total: Some(true),
+    };
+    let filter = UserListQuery {
+        q: Some("ada".to_string()),
+        sort: Some("-created_at".to_string()),
+        ..Default::default()
+    };
+    client.list_users(&filter, &page).await.unwrap();
+    client
+        .update_user(
+            id,
+            &UpdateUserRequest {
+                user_email: Some("renamed@example.com".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    client.deactivate_user(id).await.unwrap();
+    client.reactivate_user(id).await.unwrap();
+    client.anonymize_user(id).await.unwrap();
+    client.delete_user(id).await.unwrap();
+    client.get_user_roles(id, &page).await.unwrap();
+    client.assign_user_role(id, id).await.unwrap();
+    client.remove_user_role(id, id).await.unwrap();
+
+    client.get_default_preferences(id).await.unwrap();
+    client
+        .put_default_preferences(
+            id,
+            &PreferenceOverrides {
+                language: Some(Language::Pt),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    client.get_properties_schema(id).await.unwrap();
+    client
+        .put_properties_schema(
+            id,
+            &PropertiesSchemaRequest {
+                properties_schema: Some(serde_json::json!({"type": "object"})),
+            },
+        )
+        .await
+        .unwrap();
+
+    let filter = AuditEventQuery {
+        actor_id: None,
+        actor_sub: Some("user123".to_string()),
+        action: None,
+        target_type: None,
+        target_id: None,
+        from: None,
+        to: None,
+    };
+    client.list_audit_events(&filter, &page).await.unwrap();
+    client
+        .verify_audit_chain(&AuditChainQuery {
+            organization_id: None,
+        })
+        .await
+        .unwrap();
+
+    let filter = TicketListQuery {
+        status: Some(TicketStatus::Open),
+        ..Default::default()
+    };
+    client.list_tickets(&filter, &page).await.unwrap();
+    client
+        .create_ticket(&CreateTicketRequest {
+            subject: "Export fails".to_string(),
+            message: "The export button does nothing".to_string(),
+            priority: Some(TicketPriority::High),
+        })
+        .await
+        .unwrap();
+    client.get_ticket(id).await.unwrap();
+    client
+        .update_ticket(
+            id,
+            &UpdateTicketRequest {
+                status: Some(TicketStatus::InProgress),
+                assignee_id: Some(None),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    client
+        .create_ticket_comment(
+            id,
+            &CreateTicketCommentRequest {
+                body: "Looking into it".to_string(),
+                parent_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    client
+        .upload_ticket_attachment(id, "log.txt", "text/plain", b"log")
+        .await
+        .unwrap();
+    client.download_ticket_attachment(id, id).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_auth_failures_are_typed() {
+    let base_url = serve(build_mock_router(state())).await;
+    let anonymous = ApiClient::builder(&base_url).build().unwrap();
+    let user = anonymous.with_token(token(false));
+
+    let error = anonymous.profile().await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+    assert_eq!(error.code(), Some("missing_token"));
+
+    let error = user
+        .list_roles(&RoleListQuery::default(), &PaginationQuery::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::FORBIDDEN));
+    assert_eq!(error.code(), Some("admin_required"));
+    assert!(error.problem().unwrap().request_id.is_some());
+
+    // Non-admin endpoints accept the same token
+    user.profile().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_invalid_tokens_get_the_same_answer() {
+    // The token check needs no database
+    let client = client(&serve(build_router(state())).await);
+    let claims = |exp: usize| Claims {
+        sub: "client-test".to_string(),
+        exp,
+        email_verified: Some(true),
+        mfa_enabled: Some(true),
+        email: None,
+        name: None,
+        admin: None,
+        organization: None,
+    };
+    let sign = |claims: &Claims, secret: &str| {
+        encode(
+            &Header::default(),
+            claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    };
+
+    let expired = sign(&claims(1_000_000_000), JWT_SECRET);
+    let forged = sign(&claims(4_102_444_800), "another-secret");
+    for token in [expired.as_str(), forged.as_str(), "not-a-token"] {
+        let response = client.validate_token(token).await.unwrap();
+        assert!(!response.valid);
+        assert_eq!(response.message, "Token is invalid");
+    }
+    assert!(client.validate_token(&token(false)).await.unwrap().valid);
+}
+
+#[tokio::test]
+async fn test_validation_errors_list_fields() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let client = client(&serve(build_router(state)).await);
+
+    let error = client
+        .create_role(&CreateRoleRequest {
+            name: "  ".to_string(),
+            description: None,
+            is_admin: false,
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    let problem = error.problem().unwrap();
+    assert_eq!(problem.code, "validation_failed");
+    assert_eq!(problem.errors[0].field, "name");
+}
+
+#[tokio::test]
+async fn test_invalid_cursor_is_rejected() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let client = client(&serve(build_router(state)).await);
+
+    let error = client
+        .list_users(
+            &UserListQuery::default(),
+            &PaginationQuery {
+                cursor: Some("not-a-cursor".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+    assert_eq!(error.code(), Some("invalid_cursor"));
+}
+
+#[tokio::test]
+async fn test_unknown_sort_field_is_rejected() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let client = client(&serve(build_router(state)).await);
+
+    let error = client
+        .list_users(
+            &UserListQuery {
+                sort: Some("sub".to_string()),
+                ..Default::default()
+            },
+            &PaginationQuery::default(),
+        )
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.status(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    assert_eq!(
+        error.problem().unwrap().errors[0].code,
+        "unknown_sort_field"
+    );
+}
+
+/// Router whose version and token endpoints fail with 503 `failures` times first
+fn flaky_router(failures: usize, calls: Arc<AtomicUsize>) -> Router {
+    let handler = move || {
+        let calls = calls.clone();
+        async move {
+            if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                Err(StatusCode::SERVICE_UNAVAILABLE)
+            } else {
+                Ok(Json(VersionResponse {
+                    version: "1.2.3".to_string(),
+                }))
+            }
+        }
+    };
+    Router::new()
+        .route("/api/v1/system/version", get(handler.clone()))
+        .route("/api/v1/validate-token", post(handler))
+}
+
+#[tokio::test]
+async fn test_idempotent_requests_are_retried() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let client = client(&serve(flaky_router(2, calls.clone())).await);
+
+    let version = client.version().await.unwrap();
+
+    assert_eq!(version.version, "1.2.3");
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_retries_give_up_after_policy_limit() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let client = client(&serve(flaky_router(10, calls.clone())).await);
+
+    let error = client.version().await.unwrap_err();
+
+    assert!(matches!(
+        error,
+        ClientError::UnexpectedStatus {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            ..
+        }
+    ));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_non_idempotent_requests_are_not_retried() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let client = client(&serve(flaky_router(1, calls.clone())).await);
+
+    let error = client.validate_token("token").await.unwrap_err();
+
+    assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_profile_rejects_admin_only_fields() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let base_url = serve(build_router(state)).await;
+
+    // The typed request has no email field, so send the raw body
+    let response = reqwest::Client::new()
+        .patch(format!("{}/api/v1/profile", base_url))
+        .bearer_auth(token(false))
+        .json(&serde_json::json!({"user_email": "someone-else@example.com"}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "invalid_body");
+}
+
+#[tokio::test]
+async fn test_ticket_attachments_round_trip() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let client = client(&serve(build_router(state)).await);
+    client.onboard().await.unwrap();
+
+    let ticket = client
+        .create_ticket(&CreateTicketRequest {
+            subject: "Attachment round trip".to_string(),
+            message: "See the attached log".to_string(),
+            priority: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(ticket.status, TicketStatus::Open);
+    assert_eq!(ticket.priority, TicketPriority::Normal);
+
+    let attachment = client
+        .upload_ticket_attachment(ticket.id, "logs/app.log", "text/plain", b"line 1\nline 2")
+        .await
+        .unwrap();
+    assert_eq!(attachment.filename, "app.log");
+    assert_eq!(attachment.size_bytes, 13);
+
+    let content = client
+        .download_ticket_attachment(ticket.id, attachment.id)
+        .await
+        .unwrap();
+    assert_eq!(content, b"line 1\nline 2");
+
+    let detail = client.get_ticket(ticket.id).await.unwrap();
+    assert_eq!(detail.comments.len(), 1);
+    assert_eq!(detail.attachments.len(), 1);
+}
+
+#[tokio::test]
+async fn test_migrated_database_is_ready() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let base_url = serve(build_router(state)).await;
+
+    let response = reqwest::get(format!("{}/readyz", base_url)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let readiness: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(readiness["status"], "ready");
+    for component in ["database", "migrations", "signing_key"] {
+        assert_eq!(
+            readiness["checks"][component]["status"], "up",
+            "{}",
+            component
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_replicas_share_postgres_rate_limits() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    // Unique per run, so counters left by earlier runs do not count
+    let rule = RateLimitRule {
+        name: format!("validate_token_{}", Uuid::new_v4()),
+        routes: vec!["/api/v1/validate-token".to_string()],
+        methods: Vec::new(),
+        key: KeyKind::Ip,
+        algorithm: Algorithm::SlidingWindow,
+        limit: 3,
+        window_secs: 60,
+        fail_closed: true,
+    };
+    let replica = |pool: sqlx::PgPool| {
+        Arc::new(AppState {
+            jwt_secret: JWT_SECRET.into(),
+            start_time: SystemTime::now(),
+            rate_limiter: RateLimiter::new(
+                vec![rule.clone()],
+                Arc::new(PostgresStore::new(pool.clone())),
+            ),
+            db_pool: pool,
+            metrics: Metrics::default(),
+            shutdown: Default::default(),
+            trusted_proxies: Default::default(),
+        })
+    };
+    let other_pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&std::env::var("DATABASE_URL").unwrap())
+        .await
+        .unwrap();
+    let replicas = [
+        serve(build_router(replica(state.db_pool.clone()))).await,
+        serve(build_router(replica(other_pool))).await,
+    ];
+
+    let http = reqwest::Client::new();
+    let mut statuses = Vec::new();
+    for attempt in 0..4 {
+        let response = http
+            .post(format!("{}/api/v1/validate-token", replicas[attempt % 2]))
+            .json(&serde_json::json!({ "token": "not-a-token" }))
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status());
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            assert!(response.headers().contains_key("retry-after"));
+            let problem: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(problem["code"], "rate_limited");
+        }
+    }
+
+    assert_eq!(
+        statuses,
+        [
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_bulk_replace_then_upsert_updates_one_row() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let pool = state.db_pool.clone();
+    let client = client(&serve(build_router(state)).await);
+    client.onboard().await.unwrap();
+
+    let organization_id: Uuid =
+        sqlx::query_scalar("INSERT INTO organizations (name) VALUES ($1) RETURNING id")
+            .bind(format!("Permissions {}", Uuid::new_v4()))
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let role_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO roles (name, organization_id) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(format!("Tenant role {}", Uuid::new_v4()))
+    .bind(organization_id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    client
+        .replace_role_permissions(
+            role_id,
+            &ReplacePermissionsRequest {
+                version: None,
+                permissions: vec![flags().for_page("dashboard".to_string())],
+            },
+        )
+        .await
+        .unwrap();
+    let editable = PermissionFlags {
+        can_edit: true,
+        ..flags()
+    };
+    let permission = client
+        .set_role_permission(role_id, "dashboard", &editable)
+        .await
+        .unwrap();
+
+    assert_eq!(permission.organization_id, Some(organization_id));
+    let matrix = client.get_role_permissions(role_id).await.unwrap();
+    assert_eq!(matrix.permissions.len(), 1);
+    assert!(matrix.permissions[0].can_edit);
+}
+
+/// Onboard `sub` through the real router and return the new user's ID
+async fn onboard(base_url: &str, sub: &str, organization: Option<&str>) -> Uuid {
+    let response: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/v1/system/onboarding", base_url))
+        .bearer_auth(token_for(sub, organization, false))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    response["user_id"].as_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_deleted_users_tokens_are_rejected() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let base_url = serve(build_router(state)).await;
+    let admin = client(&base_url);
+    admin.onboard().await.unwrap();
+
+    let sub = format!("deleted-{}", Uuid::new_v4());
+    let user_id = onboard(&base_url, &sub, None).await;
+    admin.delete_user(user_id).await.unwrap();
+
+    let http = reqwest::Client::new();
+    for (method, path, token) in [
+        (
+            reqwest::Method::GET,
+            "/api/v1/profile",
+            token_for(&sub, None, false),
+        ),
+        (
+            reqwest::Method::POST,
+            "/api/v1/system/onboarding",
+            token_for(&sub, None, false),
+        ),
+        (
+            reqwest::Method::GET,
+            "/api/v1/admin/users",
+            token_for(&sub, None, true),
+        ),
+    ] {
+        let response = http
+            .request(method, format!("{}{}", base_url, path))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", path);
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["code"], "account_deleted", "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_organization_admins_only_manage_their_members() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let pool = state.db_pool.clone();
+    let base_url = serve(build_router(state)).await;
+
+    let mut organizations = Vec::new();
+    for _ in 0..2 {
+        let name = format!("Tenant {}", Uuid::new_v4());
+        sqlx::query("INSERT INTO organizations (name) VALUES ($1)")
+            .bind(&name)
+            .execute(&pool)
+            .await
+            .unwrap();
+        organizations.push(name);
+    }
+    let admin_sub = format!("tenant-admin-{}", Uuid::new_v4());
+    onboard(&base_url, &admin_sub, Some(&organizations[0])).await;
+    let member = onboard(
+        &base_url,
+        &format!("member-{}", Uuid::new_v4()),
+        Some(&organizations[0]),
+    )
+    .await;
+    let outsider = onboard(
+        &base_url,
+        &format!("outsider-{}", Uuid::new_v4()),
+        Some(&organizations[1]),
+    )
+    .await;
+
+    let tenant_admin = ApiClient::builder(&base_url)
+        .token(token_for(&admin_sub, Some(&organizations[0]), true))
+        .build()
+        .unwrap();
+    let error = tenant_admin.delete_user(outsider).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(error.code(), Some("user_not_found"));
+    assert!(tenant_admin.deactivate_user(outsider).await.is_err());
+    assert!(tenant_admin.anonymize_user(outsider).await.is_err());
+    let error = tenant_admin
+        .get_user_roles(outsider, &PaginationQuery::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert!(tenant_admin
+        .get_user_roles(member, &PaginationQuery::default())
+        .await
+        .is_ok());
+
+    let untouched: bool = sqlx::query_scalar(
+        "SELECT deactivated_at IS NULL AND anonymized_at IS NULL FROM users WHERE id = $1",
+    )
+    .bind(outsider)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(untouched);
+
+    let deactivated = tenant_admin.deactivate_user(member).await.unwrap();
+    assert!(deactivated.deactivated_at.is_some());
+}
+
+#[tokio::test]
+async fn test_audit_events_join_the_target_organization() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let pool = state.db_pool.clone();
+    let base_url = serve(build_router(state)).await;
+    let admin = client(&base_url);
+    admin.onboard().await.unwrap();
+
+    let organization = format!("Tenant {}", Uuid::new_v4());
+    sqlx::query("INSERT INTO organizations (name) VALUES ($1)")
+        .bind(&organization)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let admin_sub = format!("tenant-admin-{}", Uuid::new_v4());
+    onboard(&base_url, &admin_sub, Some(&organization)).await;
+    let member = onboard(
+        &base_url,
+        &format!("member-{}", Uuid::new_v4()),
+        Some(&organization),
+    )
+    .await;
+
+    // A global administrator changes the member; the organization's admin
+    // must still find the event in their own chain
+    admin.deactivate_user(member).await.unwrap();
+
+    let tenant_admin = ApiClient::builder(&base_url)
+        .token(token_for(&admin_sub, Some(&organization), true))
+        .build()
+        .unwrap();
+    let filter = AuditEventQuery {
+        actor_id: None,
+        actor_sub: None,
+        action: None,
+        target_type: None,
+        target_id: Some(member),
+        from: None,
+        to: None,
+    };
+    let events = tenant_admin
+        .list_audit_events(&filter, &PaginationQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(events.items.len(), 1);
+    assert_eq!(events.items[0].actor_sub, "client-test");
+}
+
+#[tokio::test]
+async fn test_unknown_organization_claims_are_not_system_admins() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let base_url = serve(build_router(state)).await;
+    let admin_sub = format!("stray-admin-{}", Uuid::new_v4());
+    onboard(&base_url, &admin_sub, None).await;
+
+    let stray_admin = ApiClient::builder(&base_url)
+        .token(token_for(
+            &admin_sub,
+            Some(&format!("Missing {}", Uuid::new_v4())),
+            true,
+        ))
+        .build()
+        .unwrap();
+    let filter = AuditEventQuery {
+        actor_id: None,
+        actor_sub: None,
+        action: None,
+        target_type: None,
+        target_id: None,
+        from: None,
+        to: None,
+    };
+    let error = stray_admin
+        .list_audit_events(&filter, &PaginationQuery::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(error.code(), Some("organization_not_found"));
+
+    let error = stray_admin
+        .verify_audit_chain(&AuditChainQuery {
+            organization_id: None,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn test_user_roles_are_paginated_and_legacy_lists_stay_arrays() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let base_url = serve(build_router(state)).await;
+    let admin = client(&base_url);
+    admin.onboard().await.unwrap();
+
+    let user_id = onboard(&base_url, &format!("paged-{}", Uuid::new_v4()), None).await;
+    for _ in 0..2 {
+        let role = admin
+            .create_role(&CreateRoleRequest {
+                name: format!("Paged {}", Uuid::new_v4()),
+                description: None,
+                is_admin: false,
+            })
+            .await
+            .unwrap();
+        admin.assign_user_role(user_id, role.id).await.unwrap();
+    }
+
+    let mut page = PaginationQuery {
+        cursor: None,
+        limit: Some(1),
+        include_total: Some(true),
+    };
+    let first = admin.get_user_roles(user_id, &page).await.unwrap();
+    assert_eq!(first.items.len(), 1);
+    assert_eq!(first.total, Some(2));
+    page.cursor = first.next_cursor;
+    let second = admin.get_user_roles(user_id, &page).await.unwrap();
+    assert_eq!(second.items.len(), 1);
+    assert!(second.items[0].name > first.items[0].name);
+    assert_eq!(second.next_cursor, None);
+
+    let http = reqwest::Client::new();
+    for (path, expected_len) in [
+        ("/admin/roles".to_string(), None),
+        ("/admin/users?page=1&per_page=1".to_string(), Some(1)),
+        (format!("/admin/users/{}/roles", user_id), Some(2)),
+        ("/admin/audit-events?per_page=2".to_string(), Some(2)),
+    ] {
+        let response = http
+            .get(format!("{}{}", base_url, path))
+            .bearer_auth(token(true))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert!(response.headers().contains_key("Deprecation"), "{}", path);
+        let body: serde_json::Value = response.json().await.unwrap();
+        let items = body
+            .as_array()
+            .unwrap_or_else(|| panic!("{}: {}", path, body));
+        if let Some(expected_len) = expected_len {
+            assert_eq!(items.len(), expected_len, "{}", path);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_organization_admins_manage_no_users() {
+    let Some(state) = database_state().await else {
+        return;
+    };
+    let pool = state.db_pool.clone();
+    let base_url = serve(build_router(state)).await;
+
+    let organization = format!("Tenant {}", Uuid::new_v4());
+    sqlx::query("INSERT INTO organizations (name) VALUES ($1)")
+        .bind(&organization)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let member = onboard(
+        &base_url,
+        &format!("member-{}", Uuid::new_v4()),
+        Some(&organization),
+    )
+    .await;
+    let admin_sub = format!("stray-admin-{}", Uuid::new_v4());
+    onboard(&base_url, &admin_sub, None).await;
+
+    let stray_admin = ApiClient::builder(&base_url)
+        .token(token_for(
+            &admin_sub,
+            Some(&format!("Missing {}", Uuid::new_v4())),
+            true,
+        ))
+        .build()
+        .unwrap();
+    let error = stray_admin.delete_user(member).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(error.code(), Some("user_not_found"));
+    let error = stray_admin
+        .get_user_roles(member, &PaginationQuery::default())
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
+        .bind(member)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(exists);
+}